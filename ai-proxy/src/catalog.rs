@@ -0,0 +1,191 @@
+use ai::{
+    AiClient, ConfigManager, ModelMapper,
+    models::custom::{apply_custom, models_for_provider},
+    types::ModelDef,
+};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// How long a provider's model listing may take before the static catalog is used.
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Resolve model definitions for a list of `<provider>/<model>` ids.
+///
+/// Static catalog entries, with the config's custom providers and models applied,
+/// are used when available. Models missing from it are looked up through the
+/// provider's live model listing, once per provider. Fails when the config cannot
+/// be read, e.g. while its credentials are locked.
+pub async fn resolve_models(
+    client: &AiClient,
+    config: &ConfigManager,
+    full_ids: &[String],
+) -> anyhow::Result<Vec<(String, ModelDef)>> {
+    let cfg = config.load()?;
+    let all_static = apply_custom(
+        ai::models::static_models::all_static_models(),
+        &cfg.custom_providers,
+        &cfg.custom_models,
+    );
+    let mapper = ModelMapper::new();
+    let mut discovered: HashMap<String, Vec<ModelDef>> = HashMap::new();
+    let mut resolved = Vec::new();
+
+    for full_id in full_ids {
+        let (provider, model_id) = match mapper.split_id(full_id) {
+            Some(p) => p,
+            None => continue,
+        };
+
+        if let Some(def) = all_static
+            .iter()
+            .find(|m| m.provider == provider && m.id == model_id)
+        {
+            resolved.push((full_id.clone(), def.clone()));
+            continue;
+        }
+
+        if !discovered.contains_key(provider) {
+            let models = discover_models(client, config, provider).await?;
+            discovered.insert(provider.to_string(), models);
+        }
+
+        match discovered[provider].iter().find(|m| m.id == model_id) {
+            Some(def) => resolved.push((full_id.clone(), def.clone())),
+            None => tracing::warn!("Model {} is not served by provider {}", model_id, provider),
+        }
+    }
+
+    Ok(resolved)
+}
+
+/// Query a provider's live model list, with the config's model overrides applied.
+/// Falls back to the provider's static catalog when the listing fails or takes
+/// longer than [`DISCOVERY_TIMEOUT`].
+pub async fn discover_models(
+    client: &AiClient,
+    config: &ConfigManager,
+    provider: &str,
+) -> anyhow::Result<Vec<ModelDef>> {
+    let cfg = config.load()?;
+    let params = config.get_provider_params(provider)?;
+    let api_key = config
+        .resolve_api_key(provider)
+        .await
+        .ok()
+        .flatten()
+        .unwrap_or_default();
+
+    let models = match tokio::time::timeout(DISCOVERY_TIMEOUT, client.list_models(provider, &api_key, &params)).await {
+        Ok(Ok(models)) => apply_custom(models, &cfg.custom_providers, &cfg.custom_models)
+            .into_iter()
+            .filter(|m| m.provider == provider)
+            .collect(),
+        Ok(Err(e)) => {
+            tracing::warn!("Model discovery failed for {}: {}", provider, e);
+            models_for_provider(provider, &cfg.custom_providers, &cfg.custom_models)
+        }
+        Err(_) => {
+            tracing::warn!("Model discovery for {} timed out", provider);
+            models_for_provider(provider, &cfg.custom_providers, &cfg.custom_models)
+        }
+    };
+    Ok(models)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ai::models::custom::{CustomModel, CustomProvider};
+    use ai::types::Api;
+    use axum::{Json, Router, http::StatusCode, response::IntoResponse, routing::get};
+    use serde_json::json;
+
+    /// Serve `app` on a local port; returns its base URL.
+    async fn serve(app: Router) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        url
+    }
+
+    /// A config pointing OpenAI at `base_url`, with a tweak to one of its models.
+    fn config_for(base_url: &str) -> (ConfigManager, AiClient) {
+        let path = std::env::temp_dir().join(format!("ai-proxy-test-{}", uuid::Uuid::new_v4().simple()));
+        let config = ConfigManager::new(path.join("config.json"));
+        config
+            .update(|c| {
+                c.custom_providers.push(CustomProvider {
+                    id: "openai".into(),
+                    name: None,
+                    api: Api::OpenaiCompletions,
+                    base_url: base_url.into(),
+                    headers: None,
+                    auth_header: None,
+                });
+                c.custom_models.push(CustomModel {
+                    provider: "openai".into(),
+                    id: "gpt-4o".into(),
+                    name: Some("Team GPT-4o".into()),
+                    api: None,
+                    base_url: None,
+                    reasoning: None,
+                    input: None,
+                    cost: None,
+                    context_window: None,
+                    max_tokens: None,
+                    headers: None,
+                    compat: None,
+                });
+            })
+            .unwrap();
+        let client = AiClient::builder().with_config(&config.load().unwrap()).build();
+        (config, client)
+    }
+
+    fn ids(models: &[ModelDef]) -> Vec<&str> {
+        models.iter().map(|m| m.id.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn discovery_lists_the_providers_models_with_overrides() {
+        let app = Router::new().route(
+            "/models",
+            get(|| async { Json(json!({"data": [{"id": "gpt-4o"}, {"id": "gpt-4.1", "context_window": 1047576}]})) }),
+        );
+        let url = serve(app).await;
+        let (config, client) = config_for(&url);
+
+        let models = discover_models(&client, &config, "openai").await.unwrap();
+        assert_eq!(ids(&models), ["gpt-4o", "gpt-4.1"]);
+        assert_eq!(models[0].name, "Team GPT-4o");
+        assert_eq!(models[1].context_window, 1_047_576);
+        assert!(models.iter().all(|m| m.base_url == url));
+    }
+
+    #[tokio::test]
+    async fn failed_discovery_falls_back_to_the_catalog() {
+        let app = Router::new().route("/models", get(|| async { StatusCode::INTERNAL_SERVER_ERROR.into_response() }));
+        let url = serve(app).await;
+        let (config, client) = config_for(&url);
+
+        let models = discover_models(&client, &config, "openai").await.unwrap();
+        let catalog = ai::static_models::static_models_for_provider("openai");
+        assert_eq!(ids(&models), ids(&catalog));
+        assert_eq!(models[0].name, "Team GPT-4o");
+        assert!(models.iter().all(|m| m.base_url == url));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn slow_discovery_falls_back_to_the_catalog() {
+        // Never answers; the paused clock runs ahead to the timeout
+        let app = Router::new().route("/models", get(std::future::pending::<String>));
+        let url = serve(app).await;
+        let (config, client) = config_for(&url);
+
+        let started = tokio::time::Instant::now();
+        let models = discover_models(&client, &config, "openai").await.unwrap();
+        assert!(started.elapsed() >= DISCOVERY_TIMEOUT);
+        let catalog = ai::static_models::static_models_for_provider("openai");
+        assert_eq!(ids(&models), ids(&catalog));
+    }
+}
@@ -1,10 +1,12 @@
 use ai::{
-    ConfigManager,
+    AiClient, ConfigManager,
     auth::{
         self, AuthMethod, Credential, ApiKeyCredential, SetupTokenCredential,
+        config::AppConfig,
         ProviderAuthInfo,
     },
-    models::static_models::static_models_for_provider,
+    models::custom::{models_for_provider, AuthHeader},
+    providers::template_params,
     oauth::{
         anthropic::AnthropicOAuthProvider,
         google_antigravity::AntigravityOAuthProvider,
@@ -56,6 +58,8 @@ struct AuthInputState {
     hint: String,
     is_oauth: bool,
     oauth_url: Option<String>,
+    /// Endpoint parameter being entered instead of a credential.
+    param: Option<String>,
 }
 
 struct ModelSelectState {
@@ -116,7 +120,8 @@ pub async fn run_config_tui() -> anyhow::Result<()> {
     let mut terminal = Terminal::new(backend)?;
 
     let config = ConfigManager::default_path();
-    let groups = auth::provider_groups();
+    let mut groups = auth::provider_groups();
+    groups.extend(custom_provider_group(&config));
 
     let mut screen = Screen::ProviderGroups;
     let mut group_state = ListState::default();
@@ -231,6 +236,10 @@ async fn run_tui_loop(
                                         *res = Some(state.input.trim().to_string());
                                         state.input.clear();
                                         state.hint = "Exchanging code for token...".into();
+                                    } else if let Some(param) = state.param.clone() {
+                                        let provider_id = state.provider_id.clone();
+                                        config.set_provider_param(&provider_id, &param, state.input.trim())?;
+                                        enter_model_selection(&config, &provider_id, screen).await?;
                                     } else {
                                         let provider_id = state.provider_id.clone();
                                         let input = state.input.trim().to_string();
@@ -324,7 +333,8 @@ async fn handle_provider_select(
 ) -> anyhow::Result<()> {
     let provider_id = prov.provider_id.clone();
 
-    if config.has_credential(&provider_id).unwrap_or(false) {
+    // Providers without auth methods need no credential
+    if prov.auth_methods.is_empty() || config.has_credential(&provider_id).unwrap_or(false) {
         return enter_model_selection(&config, &provider_id, screen).await;
     }
 
@@ -353,6 +363,7 @@ async fn handle_provider_select(
                 hint: hint.unwrap_or_default(),
                 is_oauth: false,
                 oauth_url: None,
+                param: None,
             });
         }
         AuthMethod::SetupToken { hint } => {
@@ -363,6 +374,7 @@ async fn handle_provider_select(
                 hint: hint.unwrap_or_else(|| "Run `claude setup-token` to generate".into()),
                 is_oauth: false,
                 oauth_url: None,
+                param: None,
             });
         }
         AuthMethod::OAuth { hint } => {
@@ -394,14 +406,85 @@ async fn handle_provider_select(
                 hint: hint.unwrap_or_else(|| "Connecting to Google...".into()),
                 is_oauth: true,
                 oauth_url: None,
+                param: None,
             });
         }
     }
     Ok(())
 }
 
+/// Config TUI entries for user-defined providers that are not built-ins.
+fn custom_provider_group(config: &ConfigManager) -> Option<(String, Vec<ProviderAuthInfo>)> {
+    let builtin = auth::all_provider_auth_info();
+    let providers: Vec<ProviderAuthInfo> = config
+        .get_custom_providers()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|p| !builtin.iter().any(|b| b.provider_id == p.id))
+        .map(|p| ProviderAuthInfo {
+            label: p.name.clone().unwrap_or_else(|| p.id.clone()),
+            group: "Custom".into(),
+            hint: p.base_url.clone(),
+            auth_methods: if p.auth_header == Some(AuthHeader::None) {
+                Vec::new()
+            } else {
+                vec![AuthMethod::ApiKey { env_var: None, hint: None }]
+            },
+            provider_id: p.id,
+        })
+        .collect();
+
+    if providers.is_empty() {
+        None
+    } else {
+        Some(("Custom".into(), providers))
+    }
+}
+
+/// First required endpoint parameter of a provider that is not configured yet.
+fn missing_endpoint_param(cfg: &AppConfig, client: &AiClient, provider_id: &str) -> Option<String> {
+    let api_key = cfg.credentials.get(provider_id).and_then(|c| c.api_key()).unwrap_or_default();
+    let mut params = client.credential_endpoint_params(provider_id, &api_key);
+    for (name, value) in cfg.provider_params.get(provider_id).into_iter().flatten() {
+        if !value.is_empty() {
+            params.insert(name.clone(), value.clone());
+        }
+    }
+    models_for_provider(provider_id, &cfg.custom_providers, &cfg.custom_models)
+        .iter()
+        .flat_map(|m| template_params(&m.base_url))
+        .find(|(name, default)| default.is_none() && !params.contains_key(name))
+        .map(|(name, _)| name)
+}
+
 async fn enter_model_selection(config: &ConfigManager, provider_id: &str, screen: &mut Screen) -> anyhow::Result<()> {
-    let models = static_models_for_provider(provider_id).into_iter().map(|m| m.id).collect::<Vec<_>>();
+    let cfg = config.load()?;
+    let client = AiClient::builder()
+        .with_config(&cfg)
+        .build();
+
+    // Templated endpoints need their parameters before models can be listed
+    if let Some(param) = missing_endpoint_param(&cfg, &client, provider_id) {
+        *screen = Screen::AuthInput(AuthInputState {
+            provider_id: provider_id.to_string(),
+            label: format!("Enter {} for {}", param, provider_id),
+            input: String::new(),
+            hint: "Part of the provider's endpoint URL".into(),
+            is_oauth: false,
+            oauth_url: None,
+            param: Some(param),
+        });
+        return Ok(());
+    }
+
+    // Prefer the models the provider really serves; fall back to the static catalog
+    let models = crate::catalog::discover_models(&client, config, provider_id).await?;
+    let models = if models.is_empty() {
+        models_for_provider(provider_id, &cfg.custom_providers, &cfg.custom_models)
+    } else {
+        models
+    };
+    let models = models.into_iter().map(|m| m.id).collect::<Vec<_>>();
     let enabled = config.get_enabled_models().unwrap_or_default();
     let model_items: Vec<(String, bool)> = models
         .into_iter()
@@ -0,0 +1,127 @@
+use ai::{
+    ConfigManager,
+    auth::vault::{KEY_FILE_ENV, KeySource, PASSPHRASE_ENV, Secret},
+};
+use crossterm::{
+    event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    terminal::{disable_raw_mode, enable_raw_mode},
+};
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::Path;
+
+/// Unlock encrypted credentials for this process, from the environment or else by
+/// asking for the passphrase. Does nothing when they are stored in plaintext.
+pub fn unlock(config: &ConfigManager) -> anyhow::Result<()> {
+    let source = match config.credentials_key_source()? {
+        Some(s) => s,
+        None => return Ok(()),
+    };
+    if let Some(secret) = Secret::from_env(source)? {
+        return config.unlock(secret);
+    }
+    match source {
+        KeySource::KeyFile => anyhow::bail!(
+            "Credentials are encrypted with a key file; set {} to its path",
+            KEY_FILE_ENV
+        ),
+        KeySource::Passphrase => {
+            let passphrase = read_passphrase("Passphrase for the stored credentials: ")?;
+            config.unlock(Secret::Passphrase(passphrase))
+        }
+    }
+}
+
+/// Encrypt the stored credentials with a new passphrase or key file.
+pub fn encrypt(key_file: Option<&Path>) -> anyhow::Result<()> {
+    let config = ConfigManager::default_path();
+    let secret = new_secret(key_file)?;
+    config.encrypt_credentials(secret)?;
+    println!("Credentials in {} are now encrypted.", config.path().display());
+    print_unlock_hint(key_file);
+    Ok(())
+}
+
+/// Store the credentials in plaintext again.
+pub fn decrypt() -> anyhow::Result<()> {
+    let config = ConfigManager::default_path();
+    config.decrypt_credentials()?;
+    println!("Credentials in {} are now stored in plaintext.", config.path().display());
+    Ok(())
+}
+
+/// Re-encrypt the credentials with a new passphrase or key file.
+pub fn rotate_key(key_file: Option<&Path>) -> anyhow::Result<()> {
+    let config = ConfigManager::default_path();
+    let secret = new_secret(key_file)?;
+    config.rotate_credentials_key(secret)?;
+    println!("Credentials in {} are now encrypted with the new key.", config.path().display());
+    print_unlock_hint(key_file);
+    Ok(())
+}
+
+/// Read the key file, creating one of random bytes if it does not exist, or ask
+/// for a new passphrase.
+fn new_secret(key_file: Option<&Path>) -> anyhow::Result<Secret> {
+    match key_file {
+        Some(path) if path.exists() => Secret::from_key_file(path),
+        Some(path) => {
+            let secret = Secret::create_key_file(path)?;
+            println!("Created key file {}", path.display());
+            Ok(secret)
+        }
+        None => {
+            let passphrase = read_passphrase("New passphrase: ")?;
+            if passphrase.is_empty() {
+                anyhow::bail!("The passphrase is empty");
+            }
+            if read_passphrase("Repeat passphrase: ")? != passphrase {
+                anyhow::bail!("The passphrases do not match");
+            }
+            Ok(Secret::Passphrase(passphrase))
+        }
+    }
+}
+
+fn print_unlock_hint(key_file: Option<&Path>) {
+    match key_file {
+        Some(path) => println!("Set {}={} to unlock them.", KEY_FILE_ENV, path.display()),
+        None => println!("Enter the passphrase at startup, or set {} to unlock them.", PASSPHRASE_ENV),
+    }
+}
+
+/// Read a passphrase without echoing it, or a line of stdin when it is not a terminal.
+fn read_passphrase(prompt: &str) -> anyhow::Result<String> {
+    if !io::stdin().is_terminal() {
+        let mut line = String::new();
+        io::stdin().lock().read_line(&mut line)?;
+        return Ok(line.trim_end_matches(['\r', '\n']).to_string());
+    }
+
+    eprint!("{}", prompt);
+    io::stderr().flush()?;
+    enable_raw_mode()?;
+    let input = (|| -> anyhow::Result<String> {
+        let mut input = String::new();
+        loop {
+            let key = match event::read()? {
+                Event::Key(key) if key.kind == KeyEventKind::Press => key,
+                _ => continue,
+            };
+            match key.code {
+                KeyCode::Enter => return Ok(input),
+                KeyCode::Backspace => {
+                    input.pop();
+                }
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    anyhow::bail!("Cancelled")
+                }
+                KeyCode::Esc => anyhow::bail!("Cancelled"),
+                KeyCode::Char(c) => input.push(c),
+                _ => {}
+            }
+        }
+    })();
+    disable_raw_mode()?;
+    eprintln!();
+    input
+}
@@ -1,5 +1,6 @@
 use ai::{
-    AiClient, ConfigManager, ModelMapper, StreamEvent, StreamOptions,
+    AiClient, ConfigManager, Credential, ModelMapper, StreamEvent, StreamOptions,
+    providers::google_gemini_cli::TIER_FIELD,
     types::{
         ChatContext, ContentBlock, Message, ModelDef, TextContent, ToolDef, ToolResultMessage,
         UserMessage,
@@ -12,7 +13,10 @@ use serde_json::json;
 /// Run the doctor check.
 pub async fn run_doctor(model_filter: Option<&str>) -> anyhow::Result<()> {
     let config = ConfigManager::default_path();
-    let client = AiClient::builder().build();
+    let client = AiClient::builder()
+        .with_config(&config.load()?)
+        .with_config_manager(config.clone())
+        .build();
     let enabled_models = config.get_enabled_models()?;
 
     if enabled_models.is_empty() {
@@ -20,24 +24,15 @@ pub async fn run_doctor(model_filter: Option<&str>) -> anyhow::Result<()> {
         return Ok(());
     }
 
-    let all_static = ai::models::static_models::all_static_models();
-
     // Determine which models to check
     let models_to_check: Vec<(String, ModelDef)> = if let Some(filter) = model_filter {
         // Check specific model
-        match all_static
-            .iter()
-            .find(|m| {
-                let full_id = format!("{}/{}", m.provider, m.id);
-                full_id == filter
-            })
-        {
-            Some(def) => vec![(filter.to_string(), def.clone())],
-            None => {
-                println!("Model not found: {}", filter);
-                return Ok(());
-            }
+        let found = crate::catalog::resolve_models(&client, &config, &[filter.to_string()]).await?;
+        if found.is_empty() {
+            println!("Model not found: {}", filter);
+            return Ok(());
         }
+        found
     } else {
         // One random model per provider from the enabled list
         let mut provider_models: std::collections::HashMap<String, Vec<String>> = std::collections::HashMap::new();
@@ -47,22 +42,15 @@ pub async fn run_doctor(model_filter: Option<&str>) -> anyhow::Result<()> {
             }
         }
 
-        let mut rng = rand::rng();
-        let mut selected: Vec<(String, ModelDef)> = Vec::new();
+        let picked: Vec<String> = {
+            let mut rng = rand::rng();
+            provider_models
+                .values()
+                .filter_map(|models| models.choose(&mut rng).cloned())
+                .collect()
+        };
 
-        for (provider, models) in provider_models {
-            if let Some(full_id) = models.choose(&mut rng) {
-                let (_, model_id) = ModelMapper::default().split_id(full_id).unwrap();
-                if let Some(def) = all_static
-                    .iter()
-                    .find(|m| m.provider == provider && m.id == model_id)
-                {
-                    selected.push((full_id.to_string(), def.clone()));
-                }
-            }
-        }
-
-        selected
+        crate::catalog::resolve_models(&client, &config, &picked).await?
     };
 
     if models_to_check.is_empty() {
@@ -83,12 +71,13 @@ pub async fn run_doctor(model_filter: Option<&str>) -> anyhow::Result<()> {
 
     for (full_id, model_def) in &models_to_check {
         let (provider, _) = ModelMapper::default().split_id(full_id).unwrap();
-        let api_key = config.resolve_api_key(provider).await?;
+        let credential = config.resolve_credential(provider).await?;
 
-        if api_key.is_none() {
+        if credential.is_none() && client.requires_api_key(provider) {
             println!("  {} - No credentials", full_id);
             continue;
         }
+        let endpoint_params = config.get_provider_params(provider)?;
 
         println!("\n📋 Checking {}...", full_id);
 
@@ -97,7 +86,8 @@ pub async fn run_doctor(model_filter: Option<&str>) -> anyhow::Result<()> {
             &client,
             full_id,
             model_def,
-            api_key.as_deref().unwrap(),
+            credential.as_ref(),
+            endpoint_params,
             &tool,
         )
         .await;
@@ -105,6 +95,14 @@ pub async fn run_doctor(model_filter: Option<&str>) -> anyhow::Result<()> {
         match stream_result {
             Ok(report) => {
                 println!("  Stream:     ✅ {} tokens, stop={:?}", report.total_tokens, report.stop_reason);
+                // Cloud Code Assist providers record the account tier on first use
+                let tier = match config.get_credential(provider)? {
+                    Some(Credential::OAuth(oauth)) => oauth.extra.get(TIER_FIELD).cloned(),
+                    _ => None,
+                };
+                if let Some(tier) = tier.as_ref().and_then(|t| t.as_str()) {
+                    println!("  Tier:       {}", tier);
+                }
                 if report.tool_call_received {
                     println!("  Tool call:  ✅ Received");
                     if report.tool_result_ok {
@@ -141,7 +139,8 @@ async fn check_model(
     client: &AiClient,
     full_id: &str,
     model_def: &ModelDef,
-    api_key: &str,
+    credential: Option<&Credential>,
+    endpoint_params: std::collections::HashMap<String, String>,
     tool: &ToolDef,
 ) -> anyhow::Result<CheckReport> {
     let context = ChatContext {
@@ -149,17 +148,23 @@ async fn check_model(
         messages: vec![Message::User(UserMessage {
             content: vec![ContentBlock::Text(TextContent {
                 text: "What time is it right now? Please use the tool to check.".into(),
+                thought_signature: None,
             })],
         })],
         tools: vec![tool.clone()],
+        hosted_tools: Vec::new(),
     };
 
     let options = StreamOptions {
         temperature: Some(0.0),
         max_tokens: Some(1024),
         reasoning: None,
-        api_key: Some(api_key.to_string()),
+        api_key: credential.and_then(|c| c.api_key()),
+        credential_kind: credential.map(|c| c.kind()),
         extra_headers: None,
+        endpoint_params: Some(endpoint_params),
+        safety_settings: None,
+        conversation_id: None,
     };
 
     let mut stream = client.stream(full_id, model_def, &context, &options)?;
@@ -237,11 +242,13 @@ async fn check_model(
                             tool_name: tc.name,
                             content: vec![ContentBlock::Text(TextContent {
                                 text: chrono::Utc::now().to_rfc3339(),
+                                thought_signature: None,
                             })],
                             is_error: false,
                         }),
                     ],
                     tools: vec![tool.clone()],
+                    hosted_tools: Vec::new(),
                 };
 
                 match client.stream(full_id, model_def, &follow_up, &options) {
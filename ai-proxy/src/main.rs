@@ -1,8 +1,11 @@
+mod catalog;
 mod config_tui;
+mod credentials;
 mod doctor;
 mod server;
 
 use clap::{Parser, Subcommand};
+use std::path::PathBuf;
 
 #[derive(Parser)]
 #[command(name = "ai-proxy", version, about = "AI model proxy server")]
@@ -33,6 +36,32 @@ enum Commands {
         #[arg(short, long)]
         model: Option<String>,
     },
+
+    /// Manage encryption of the stored credentials
+    Credentials {
+        #[command(subcommand)]
+        action: CredentialsAction,
+    },
+}
+
+#[derive(Subcommand)]
+enum CredentialsAction {
+    /// Encrypt the stored credentials with a passphrase or key file
+    Encrypt {
+        /// Key file to use instead of a passphrase, created if missing
+        #[arg(long)]
+        key_file: Option<PathBuf>,
+    },
+
+    /// Store the credentials in plaintext again
+    Decrypt,
+
+    /// Re-encrypt the credentials with a new passphrase or key file
+    RotateKey {
+        /// Key file to use instead of a passphrase, created if missing
+        #[arg(long)]
+        key_file: Option<PathBuf>,
+    },
 }
 
 #[tokio::main]
@@ -46,6 +75,11 @@ async fn main() -> anyhow::Result<()> {
 
     let cli = Cli::parse();
 
+    // Encrypted credentials are unlocked once, before anything reads them
+    if !matches!(cli.command, Commands::Credentials { action: CredentialsAction::Encrypt { .. } }) {
+        credentials::unlock(&ai::ConfigManager::default_path())?;
+    }
+
     match cli.command {
         Commands::Serve { port, host } => {
             server::run_server(&host, port).await?;
@@ -56,6 +90,11 @@ async fn main() -> anyhow::Result<()> {
         Commands::Doctor { model } => {
             doctor::run_doctor(model.as_deref()).await?;
         }
+        Commands::Credentials { action } => match action {
+            CredentialsAction::Encrypt { key_file } => credentials::encrypt(key_file.as_deref())?,
+            CredentialsAction::Decrypt => credentials::decrypt()?,
+            CredentialsAction::RotateKey { key_file } => credentials::rotate_key(key_file.as_deref())?,
+        },
     }
 
     Ok(())
@@ -1,23 +1,31 @@
 use ai::{
-    AiClient, ConfigManager, ModelMapper, StreamEvent, StreamOptions,
+    AiClient, ConfigManager, Credential, ModelMapper, ProviderError, StreamEvent, StreamOptions,
+    auth::pool::{self, CredentialSelector, DEFAULT_LABEL},
     types::{
-        AssistantMessage, ChatContext, ContentBlock, Message, ModelDef, StopReason, TextContent,
-        ThinkingContent, ToolCall, ToolDef, ToolResultMessage, UserMessage,
+        AssistantMessage, ChatContext, ContentBlock, HostedTool, Message, ModelDef, StopReason,
+        TextContent, ThinkingContent, ThinkingLevel, ToolCall, ToolDef, ToolResultMessage,
+        UserMessage,
     },
 };
 use axum::{
     Json, Router,
     extract::State,
-    http::StatusCode,
+    http::{HeaderMap, HeaderValue, StatusCode},
     response::{IntoResponse, Response, Sse, sse::Event},
     routing::{get, post},
 };
 use futures::StreamExt;
+use futures::stream::BoxStream;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
+/// Response header naming the credential that served the request.
+const CREDENTIAL_HEADER: &str = "x-ai-credential";
+/// Request header identifying a conversation, for sticky credential pools.
+const CONVERSATION_HEADER: &str = "x-conversation-id";
+
 // ---------------------------------------------------------------------------
 // App state
 // ---------------------------------------------------------------------------
@@ -27,46 +35,61 @@ pub struct AppState {
     pub config: ConfigManager,
     /// Cache of model definitions keyed by `<provider>/<model>`.
     pub models_cache: RwLock<Vec<(String, ModelDef)>>,
+    /// Rotation and cooldowns of the credential pools.
+    pub selector: CredentialSelector,
+}
+
+type EventStream = BoxStream<'static, Result<StreamEvent, ProviderError>>;
+
+/// Why a stream could not be opened.
+pub enum OpenStreamError {
+    /// The provider needs a credential and has none.
+    NoCredentials,
+    Provider(ProviderError),
+}
+
+impl OpenStreamError {
+    fn status(&self) -> StatusCode {
+        match self {
+            OpenStreamError::NoCredentials => StatusCode::UNAUTHORIZED,
+            OpenStreamError::Provider(ProviderError::Http { status, .. }) => {
+                StatusCode::from_u16(*status).unwrap_or(StatusCode::BAD_GATEWAY)
+            }
+            OpenStreamError::Provider(ProviderError::RateLimited { .. }) => StatusCode::TOO_MANY_REQUESTS,
+            OpenStreamError::Provider(ProviderError::AuthRequired(_)) => StatusCode::UNAUTHORIZED,
+            OpenStreamError::Provider(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
 }
 
 impl AppState {
     pub async fn new() -> anyhow::Result<Self> {
         let config = ConfigManager::default_path();
-        let client = AiClient::builder().build();
+        let client = AiClient::builder()
+            .with_config(&config.load()?)
+            .with_config_manager(config.clone())
+            .build();
 
         let state = Self {
             client,
             config,
             models_cache: RwLock::new(Vec::new()),
+            selector: CredentialSelector::new(),
         };
 
-        state.refresh_models_cache().await;
+        state.refresh_models_cache().await?;
 
         Ok(state)
     }
 
     /// Rebuild the models cache from enabled models in config.
-    pub async fn refresh_models_cache(&self) {
-        let enabled = self.config.get_enabled_models().unwrap_or_default();
-
-        let mut cache = Vec::new();
-
-        // Build model defs from static lists
-        let all_static = ai::models::static_models::all_static_models();
-
-        for full_id in &enabled {
-            if let Some((provider, model_id)) = ModelMapper::default().split_id(full_id) {
-                // Look up in static models
-                if let Some(def) = all_static
-                    .iter()
-                    .find(|m| m.provider == provider && m.id == model_id)
-                {
-                    cache.push((full_id.clone(), def.clone()));
-                }
-            }
-        }
+    /// Models missing from the static catalog are discovered from their provider.
+    pub async fn refresh_models_cache(&self) -> anyhow::Result<()> {
+        let enabled = self.config.get_enabled_models()?;
+        let cache = crate::catalog::resolve_models(&self.client, &self.config, &enabled).await?;
 
         *self.models_cache.write().await = cache;
+        Ok(())
     }
 
     /// Find a model definition by full ID.
@@ -78,10 +101,89 @@ impl AppState {
             .map(|(_, def)| def.clone())
     }
 
-    /// Resolve API key for a provider.
-    pub async fn resolve_api_key(&self, provider: &str) -> Option<String> {
-        self.config.resolve_api_key(provider).await.ok().flatten()
+    /// Resolve a credential of a provider's pool.
+    pub async fn resolve_credential(&self, provider: &str, label: &str) -> Option<Credential> {
+        self.config.resolve_pooled_credential(provider, label).await.ok().flatten()
+    }
+
+    /// Open a stream on one of the provider's credentials. They are tried in the
+    /// selector's order, moving on while one is rate-limited or out of quota, which
+    /// rests it for a while. Returns the label of the credential serving the stream.
+    pub async fn open_stream(
+        &self,
+        full_id: &str,
+        provider: &str,
+        model_def: &ModelDef,
+        context: &ChatContext,
+        options: &StreamOptions,
+    ) -> Result<(Option<String>, EventStream), OpenStreamError> {
+        let (labels, strategy) = self
+            .config
+            .credential_pool(provider)
+            .unwrap_or_else(|_| (vec![DEFAULT_LABEL.to_string()], Default::default()));
+        let conversation = options.conversation_id.as_deref();
+
+        let mut last_error = None;
+        for label in self.selector.order(provider, &labels, strategy, conversation) {
+            let credential = match self.resolve_credential(provider, &label).await {
+                Some(c) => c,
+                None => continue,
+            };
+            let mut options = options.clone();
+            options.api_key = credential.api_key();
+            options.credential_kind = Some(credential.kind());
+
+            let mut stream = self
+                .client
+                .stream(full_id, model_def, context, &options)
+                .map_err(OpenStreamError::Provider)?;
+
+            // Providers report a rejected request as the first event
+            let first = stream.next().await;
+            match &first {
+                Some(Err(e)) => {
+                    if let Some(cooldown) = pool::rate_limit_cooldown(e) {
+                        self.selector.cool_down(provider, &label, cooldown);
+                        last_error = first.and_then(Result::err);
+                        continue;
+                    }
+                }
+                _ => self.selector.record_success(provider, &label, conversation),
+            }
+
+            tracing::info!("{} served by credential {}/{}", full_id, provider, label);
+            return Ok((Some(label), futures::stream::iter(first).chain(stream).boxed()));
+        }
+
+        if let Some(e) = last_error {
+            return Err(OpenStreamError::Provider(e));
+        }
+        // Local providers such as Ollama run without credentials
+        if self.client.requires_api_key(provider) {
+            return Err(OpenStreamError::NoCredentials);
+        }
+        let stream = self
+            .client
+            .stream(full_id, model_def, context, options)
+            .map_err(OpenStreamError::Provider)?;
+        Ok((None, stream))
+    }
+}
+
+/// Conversation id of a request, from [`CONVERSATION_HEADER`].
+fn conversation_id(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(CONVERSATION_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+}
+
+/// Report the credential that served the request in [`CREDENTIAL_HEADER`].
+fn with_credential_header(mut response: Response, label: Option<&str>) -> Response {
+    if let Some(value) = label.and_then(|l| HeaderValue::from_str(l).ok()) {
+        response.headers_mut().insert(CREDENTIAL_HEADER, value);
     }
+    response
 }
 
 // ---------------------------------------------------------------------------
@@ -162,6 +264,11 @@ struct ChatCompletionRequest {
     max_tokens: Option<u64>,
     #[serde(default)]
     tools: Option<Vec<OpenAITool>>,
+    #[serde(default)]
+    reasoning_effort: Option<String>,
+    /// Present (even as `{}`) to enable the provider's hosted web search.
+    #[serde(default)]
+    web_search_options: Option<serde_json::Value>,
 }
 
 #[derive(Deserialize)]
@@ -220,7 +327,7 @@ fn convert_openai_messages(msgs: &[OpenAIMessage]) -> (Option<String>, Vec<Messa
                     .unwrap_or("")
                     .to_string();
                 messages.push(Message::User(UserMessage {
-                    content: vec![ContentBlock::Text(TextContent { text })],
+                    content: vec![ContentBlock::Text(TextContent { text, thought_signature: None })],
                 }));
             }
             "assistant" => {
@@ -230,6 +337,7 @@ fn convert_openai_messages(msgs: &[OpenAIMessage]) -> (Option<String>, Vec<Messa
                         if !text.is_empty() {
                             content.push(ContentBlock::Text(TextContent {
                                 text: text.to_string(),
+                                thought_signature: None,
                             }));
                         }
                     }
@@ -242,6 +350,7 @@ fn convert_openai_messages(msgs: &[OpenAIMessage]) -> (Option<String>, Vec<Messa
                             id: tc.id.clone(),
                             name: tc.function.name.clone(),
                             arguments: args,
+                            thought_signature: None,
                         }));
                     }
                 }
@@ -251,6 +360,7 @@ fn convert_openai_messages(msgs: &[OpenAIMessage]) -> (Option<String>, Vec<Messa
                     provider: String::new(),
                     usage: None,
                     stop_reason: StopReason::Stop,
+                    error_message: None,
                 }));
             }
             "tool" => {
@@ -263,7 +373,7 @@ fn convert_openai_messages(msgs: &[OpenAIMessage]) -> (Option<String>, Vec<Messa
                 messages.push(Message::ToolResult(ToolResultMessage {
                     tool_call_id: msg.tool_call_id.clone().unwrap_or_default(),
                     tool_name: msg.name.clone().unwrap_or_default(),
-                    content: vec![ContentBlock::Text(TextContent { text })],
+                    content: vec![ContentBlock::Text(TextContent { text, thought_signature: None })],
                     is_error: false,
                 }));
             }
@@ -274,6 +384,16 @@ fn convert_openai_messages(msgs: &[OpenAIMessage]) -> (Option<String>, Vec<Messa
     (system, messages)
 }
 
+fn parse_reasoning_effort(effort: &str) -> Option<ThinkingLevel> {
+    match effort {
+        "minimal" => Some(ThinkingLevel::Minimal),
+        "low" => Some(ThinkingLevel::Low),
+        "medium" => Some(ThinkingLevel::Medium),
+        "high" => Some(ThinkingLevel::High),
+        _ => None,
+    }
+}
+
 fn convert_openai_tools(tools: &[OpenAITool]) -> Vec<ToolDef> {
     tools
         .iter()
@@ -287,6 +407,7 @@ fn convert_openai_tools(tools: &[OpenAITool]) -> Vec<ToolDef> {
 
 async fn chat_completions(
     State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
     Json(req): Json<ChatCompletionRequest>,
 ) -> Response {
     let model_def = match state.find_model(&req.model).await {
@@ -311,48 +432,49 @@ async fn chat_completions(
         }
     };
 
-    let api_key = match state.resolve_api_key(provider_name).await {
-        Some(k) => k,
-        None => {
-            return (
-                StatusCode::UNAUTHORIZED,
-                Json(json!({"error": {"message": format!("No credentials for provider: {}", provider_name)}})),
-            )
-                .into_response();
-        }
-    };
-
     let (system_prompt, messages) = convert_openai_messages(&req.messages);
     let tools = req.tools.as_ref().map(|t| convert_openai_tools(t)).unwrap_or_default();
 
+    let hosted_tools = match req.web_search_options {
+        Some(_) => vec![HostedTool::WebSearch { max_uses: None }],
+        None => Vec::new(),
+    };
+
     let context = ChatContext {
         system_prompt,
         messages,
         tools,
+        hosted_tools,
     };
 
     let options = StreamOptions {
         temperature: req.temperature,
         max_tokens: req.max_tokens,
-        reasoning: None,
-        api_key: Some(api_key),
+        reasoning: req.reasoning_effort.as_deref().and_then(parse_reasoning_effort),
+        api_key: None,
+        credential_kind: None,
         extra_headers: None,
+        endpoint_params: state.config.get_provider_params(provider_name).ok(),
+        safety_settings: None,
+        conversation_id: conversation_id(&headers),
     };
 
-    let is_stream = req.stream.unwrap_or(false);
-
-    if is_stream {
-        let event_stream = match state.client.stream(&req.model, &model_def, &context, &options) {
+    let (credential, event_stream) =
+        match state.open_stream(&req.model, provider_name, &model_def, &context, &options).await {
             Ok(s) => s,
             Err(e) => {
-                return (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(json!({"error": {"message": e.to_string()}})),
-                )
-                    .into_response();
+                let message = match &e {
+                    OpenStreamError::NoCredentials => format!("No credentials for provider: {}", provider_name),
+                    OpenStreamError::Provider(e) => e.to_string(),
+                };
+                return (e.status(), Json(json!({"error": {"message": message}}))).into_response();
             }
         };
 
+    let is_stream = req.stream.unwrap_or(false);
+
+    if is_stream {
+
         let model_name = req.model.clone();
         let sse = event_stream.filter_map(move |event| {
             let model_name = model_name.clone();
@@ -374,6 +496,20 @@ async fn chat_completions(
                             Event::default().data(chunk.to_string()),
                         ))
                     }
+                    Ok(StreamEvent::ThinkingDelta(delta)) => {
+                        let chunk = json!({
+                            "id": format!("chatcmpl-{}", uuid::Uuid::new_v4()),
+                            "object": "chat.completion.chunk",
+                            "created": chrono::Utc::now().timestamp(),
+                            "model": model_name,
+                            "choices": [{
+                                "index": 0,
+                                "delta": {"reasoning_content": delta},
+                                "finish_reason": null
+                            }]
+                        });
+                        Some(Ok(Event::default().data(chunk.to_string())))
+                    }
                     Ok(StreamEvent::ToolCallStart { index, id, name }) => {
                         let chunk = json!({
                             "id": format!("chatcmpl-{}", uuid::Uuid::new_v4()),
@@ -419,6 +555,7 @@ async fn chat_completions(
                             StopReason::Stop => "stop",
                             StopReason::Length => "length",
                             StopReason::ToolUse => "tool_calls",
+                            StopReason::ContentFilter => "content_filter",
                             _ => "stop",
                         };
                         let chunk = json!({
@@ -435,6 +572,7 @@ async fn chat_completions(
                                 "prompt_tokens": u.input_tokens,
                                 "completion_tokens": u.output_tokens,
                                 "total_tokens": u.total_tokens,
+                                "completion_tokens_details": {"reasoning_tokens": u.reasoning_tokens},
                             }))
                         });
                         Some(Ok(Event::default().data(chunk.to_string())))
@@ -452,20 +590,9 @@ async fn chat_completions(
             }
         });
 
-        Sse::new(sse).into_response()
+        with_credential_header(Sse::new(sse).into_response(), credential.as_deref())
     } else {
         // Non-streaming: collect the full response
-        let event_stream = match state.client.stream(&req.model, &model_def, &context, &options) {
-            Ok(s) => s,
-            Err(e) => {
-                return (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(json!({"error": {"message": e.to_string()}})),
-                )
-                    .into_response();
-            }
-        };
-
         let mut final_message: Option<AssistantMessage> = None;
         let mut stream = event_stream;
 
@@ -495,11 +622,14 @@ async fn chat_completions(
         };
 
         let mut content_text = String::new();
+        let mut reasoning_text = String::new();
         let mut tool_calls_json = Vec::new();
+        let mut annotations = Vec::new();
 
         for block in &msg.content {
             match block {
                 ContentBlock::Text(t) => content_text.push_str(&t.text),
+                ContentBlock::Thinking(th) => reasoning_text.push_str(&th.thinking),
                 ContentBlock::ToolCall(tc) => {
                     tool_calls_json.push(json!({
                         "id": tc.id,
@@ -510,6 +640,19 @@ async fn chat_completions(
                         }
                     }));
                 }
+                ContentBlock::Citations(c) => {
+                    annotations.extend(c.sources.iter().filter(|s| s.uri.is_some()).map(|s| {
+                        json!({
+                            "type": "url_citation",
+                            "url_citation": {
+                                "url": s.uri,
+                                "title": s.title,
+                                "start_index": s.start_index,
+                                "end_index": s.end_index,
+                            }
+                        })
+                    }));
+                }
                 _ => {}
             }
         }
@@ -518,6 +661,7 @@ async fn chat_completions(
             StopReason::Stop => "stop",
             StopReason::Length => "length",
             StopReason::ToolUse => "tool_calls",
+            StopReason::ContentFilter => "content_filter",
             _ => "stop",
         };
 
@@ -531,7 +675,10 @@ async fn chat_completions(
                 "message": {
                     "role": "assistant",
                     "content": if content_text.is_empty() { serde_json::Value::Null } else { json!(content_text) },
-                    "tool_calls": if tool_calls_json.is_empty() { serde_json::Value::Null } else { json!(tool_calls_json) }
+                    "reasoning_content": if reasoning_text.is_empty() { serde_json::Value::Null } else { json!(reasoning_text) },
+                    "tool_calls": if tool_calls_json.is_empty() { serde_json::Value::Null } else { json!(tool_calls_json) },
+                    "annotations": if annotations.is_empty() { serde_json::Value::Null } else { json!(annotations) },
+                    "refusal": msg.error_message
                 },
                 "finish_reason": finish_reason
             }],
@@ -539,10 +686,11 @@ async fn chat_completions(
                 "prompt_tokens": u.input_tokens,
                 "completion_tokens": u.output_tokens,
                 "total_tokens": u.total_tokens,
+                "completion_tokens_details": {"reasoning_tokens": u.reasoning_tokens},
             }))
         });
 
-        Json(response).into_response()
+        with_credential_header(Json(response).into_response(), credential.as_deref())
     }
 }
 
@@ -574,9 +722,30 @@ struct AnthropicMessage {
 
 #[derive(Deserialize)]
 struct AnthropicToolReq {
+    /// Versioned type of a server tool, e.g. `web_search_20250305`; absent for client tools.
+    #[serde(rename = "type", default)]
+    tool_type: Option<String>,
     name: String,
     description: Option<String>,
     input_schema: Option<serde_json::Value>,
+    #[serde(default)]
+    max_uses: Option<u32>,
+}
+
+/// Map an Anthropic server tool declaration to a hosted tool.
+fn anthropic_hosted_tool(tool: &AnthropicToolReq) -> Option<HostedTool> {
+    let tool_type = tool.tool_type.as_deref()?;
+    if tool_type.starts_with("web_search_") {
+        Some(HostedTool::WebSearch {
+            max_uses: tool.max_uses,
+        })
+    } else if tool_type.starts_with("code_execution_") {
+        Some(HostedTool::CodeExecution)
+    } else if tool_type.starts_with("web_fetch_") {
+        Some(HostedTool::UrlContext)
+    } else {
+        None
+    }
 }
 
 fn convert_anthropic_messages(
@@ -589,7 +758,7 @@ fn convert_anthropic_messages(
             "user" => {
                 let text = msg.content.as_str().unwrap_or("").to_string();
                 messages.push(Message::User(UserMessage {
-                    content: vec![ContentBlock::Text(TextContent { text })],
+                    content: vec![ContentBlock::Text(TextContent { text, thought_signature: None })],
                 }));
             }
             "assistant" => {
@@ -597,6 +766,7 @@ fn convert_anthropic_messages(
                 if let Some(text) = msg.content.as_str() {
                     content.push(ContentBlock::Text(TextContent {
                         text: text.to_string(),
+                        thought_signature: None,
                     }));
                 } else if let Some(blocks) = msg.content.as_array() {
                     for block in blocks {
@@ -606,6 +776,7 @@ fn convert_anthropic_messages(
                                     if let Some(text) = block.get("text").and_then(|v| v.as_str()) {
                                         content.push(ContentBlock::Text(TextContent {
                                             text: text.to_string(),
+                                            thought_signature: None,
                                         }));
                                     }
                                 }
@@ -625,6 +796,7 @@ fn convert_anthropic_messages(
                                         id,
                                         name,
                                         arguments: input,
+                                        thought_signature: None,
                                     }));
                                 }
                                 _ => {}
@@ -638,6 +810,7 @@ fn convert_anthropic_messages(
                     provider: String::new(),
                     usage: None,
                     stop_reason: StopReason::Stop,
+                    error_message: None,
                 }));
             }
             _ => {}
@@ -649,6 +822,7 @@ fn convert_anthropic_messages(
 
 async fn anthropic_messages(
     State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
     Json(req): Json<AnthropicRequest>,
 ) -> Response {
     let model_def = match state.find_model(&req.model).await {
@@ -673,57 +847,60 @@ async fn anthropic_messages(
         }
     };
 
-    let api_key = match state.resolve_api_key(provider_name).await {
-        Some(k) => k,
-        None => {
-            return (
-                StatusCode::UNAUTHORIZED,
-                Json(json!({"type": "error", "error": {"type": "authentication_error", "message": format!("No credentials for: {}", provider_name)}})),
-            )
-                .into_response();
-        }
-    };
-
     let messages = convert_anthropic_messages(&req.messages);
-    let tools = req
-        .tools
-        .as_ref()
-        .map(|t| {
-            t.iter()
-                .map(|tool| ToolDef {
-                    name: tool.name.clone(),
-                    description: tool.description.clone().unwrap_or_default(),
-                    parameters: tool.input_schema.clone().unwrap_or(json!({})),
-                })
-                .collect::<Vec<_>>()
-        })
-        .unwrap_or_default();
+    let mut tools = Vec::new();
+    let mut hosted_tools = Vec::new();
+    for tool in req.tools.iter().flatten() {
+        match anthropic_hosted_tool(tool) {
+            Some(hosted) => hosted_tools.push(hosted),
+            None => tools.push(ToolDef {
+                name: tool.name.clone(),
+                description: tool.description.clone().unwrap_or_default(),
+                parameters: tool.input_schema.clone().unwrap_or(json!({})),
+            }),
+        }
+    }
 
     let context = ChatContext {
         system_prompt: req.system.clone(),
         messages,
         tools,
+        hosted_tools,
     };
 
     let options = StreamOptions {
         temperature: req.temperature,
         max_tokens: Some(req.max_tokens),
         reasoning: None,
-        api_key: Some(api_key),
+        api_key: None,
+        credential_kind: None,
         extra_headers: None,
+        endpoint_params: state.config.get_provider_params(provider_name).ok(),
+        safety_settings: None,
+        conversation_id: conversation_id(&headers),
     };
 
     // Non-streaming Anthropic response
-    let event_stream = match state.client.stream(&req.model, &model_def, &context, &options) {
-        Ok(s) => s,
-        Err(e) => {
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({"type": "error", "error": {"type": "api_error", "message": e.to_string()}})),
-            )
-                .into_response();
-        }
-    };
+    let (credential, event_stream) =
+        match state.open_stream(&req.model, provider_name, &model_def, &context, &options).await {
+            Ok(s) => s,
+            Err(e) => {
+                let (error_type, message) = match &e {
+                    OpenStreamError::NoCredentials => {
+                        ("authentication_error", format!("No credentials for: {}", provider_name))
+                    }
+                    OpenStreamError::Provider(p) if e.status() == StatusCode::TOO_MANY_REQUESTS => {
+                        ("rate_limit_error", p.to_string())
+                    }
+                    OpenStreamError::Provider(p) => ("api_error", p.to_string()),
+                };
+                return (
+                    e.status(),
+                    Json(json!({"type": "error", "error": {"type": error_type, "message": message}})),
+                )
+                    .into_response();
+            }
+        };
 
     let mut final_message: Option<AssistantMessage> = None;
     let mut stream = event_stream;
@@ -778,6 +955,7 @@ async fn anthropic_messages(
         StopReason::Stop => "end_turn",
         StopReason::Length => "max_tokens",
         StopReason::ToolUse => "tool_use",
+        StopReason::ContentFilter => "refusal",
         _ => "end_turn",
     };
 
@@ -796,5 +974,98 @@ async fn anthropic_messages(
         }))
     });
 
-    Json(response).into_response()
+    with_credential_header(Json(response).into_response(), credential.as_deref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ai::auth::ApiKeyCredential;
+    use ai::types::{Api, InputModality, ModelCost};
+    use std::sync::Mutex;
+
+    /// An OpenAI-compatible upstream that rate-limits `key-a` and answers any other key.
+    async fn upstream(seen: Arc<Mutex<Vec<String>>>) -> String {
+        let app = Router::new().route(
+            "/chat/completions",
+            post(move |headers: HeaderMap| async move {
+                let auth = headers["authorization"].to_str().unwrap().to_string();
+                seen.lock().unwrap().push(auth.clone());
+                if auth == "Bearer key-a" {
+                    let body = json!({"error": {"status": "RESOURCE_EXHAUSTED",
+                        "details": [{"violations": [{"quotaId": "GenerateRequestsPerMinutePerProject"}]}]}});
+                    return (StatusCode::TOO_MANY_REQUESTS, body.to_string()).into_response();
+                }
+                let chunks = [
+                    json!({"choices": [{"index": 0, "delta": {"content": "hi"}}]}),
+                    json!({"choices": [{"index": 0, "delta": {}, "finish_reason": "stop"}]}),
+                ];
+                let body: String = chunks.iter().map(|c| format!("data: {}\n\n", c)).collect();
+                ([("content-type", "text/event-stream")], body + "data: [DONE]\n\n").into_response()
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        url
+    }
+
+    fn model(base_url: &str) -> ModelDef {
+        ModelDef {
+            id: "gpt-test".into(),
+            name: "gpt-test".into(),
+            api: Api::OpenaiCompletions,
+            provider: "openai".into(),
+            base_url: base_url.into(),
+            reasoning: false,
+            input: vec![InputModality::Text],
+            cost: ModelCost::default(),
+            context_window: 128_000,
+            max_tokens: 4096,
+            headers: None,
+            compat: None,
+        }
+    }
+
+    fn key(key: &str) -> Credential {
+        Credential::ApiKey(ApiKeyCredential { key: key.into() })
+    }
+
+    #[tokio::test]
+    async fn rate_limited_credentials_fail_over_and_rest() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let model = model(&upstream(seen.clone()).await);
+        let path = std::env::temp_dir().join(format!("ai-proxy-test-{}", uuid::Uuid::new_v4().simple()));
+        let config = ConfigManager::new(path.join("config.json"));
+        config.set_pooled_credential("openai", DEFAULT_LABEL, key("key-a")).unwrap();
+        config.set_pooled_credential("openai", "b", key("key-b")).unwrap();
+        let state = AppState {
+            client: AiClient::builder().with_config_manager(config.clone()).build(),
+            config,
+            models_cache: RwLock::new(Vec::new()),
+            selector: CredentialSelector::new(),
+        };
+        let context = ChatContext {
+            system_prompt: None,
+            messages: vec![Message::User(UserMessage {
+                content: vec![ContentBlock::Text(TextContent { text: "hello".into(), thought_signature: None })],
+            })],
+            tools: vec![],
+            hosted_tools: vec![],
+        };
+
+        for _ in 0..3 {
+            let (label, stream) = state
+                .open_stream("openai/gpt-test", "openai", &model, &context, &StreamOptions::default())
+                .await
+                .ok()
+                .unwrap();
+            assert_eq!(label.as_deref(), Some("b"));
+            let events: Vec<_> = stream.collect().await;
+            assert!(events.iter().all(Result::is_ok));
+        }
+        // The per-minute limit rested `default`, so later requests went straight to `b`
+        assert_eq!(*seen.lock().unwrap(), ["Bearer key-a", "Bearer key-b", "Bearer key-b", "Bearer key-b"]);
+        let _ = std::fs::remove_dir_all(path);
+    }
 }
@@ -1,9 +1,16 @@
-use super::Credential;
+use super::pool::{CredentialPool, DEFAULT_LABEL, LabeledCredential, PoolStrategy};
+use super::vault::{self, EncryptedCredentials, KeySource, Secret};
+use super::{Credential, OAuthCredential};
+use crate::models::custom::{CustomModel, CustomProvider};
+use crate::providers::anthropic::AnthropicConfig;
+use crate::providers::ollama::OllamaConfig;
+use crate::providers::openai::AzureConfig;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
 use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, Mutex};
 
 /// The main configuration file structure.
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -12,21 +19,88 @@ pub struct AppConfig {
     #[serde(default)]
     pub credentials: HashMap<String, Credential>,
 
+    /// Further labelled credentials per provider, used after the one in `credentials`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub credential_pools: HashMap<String, CredentialPool>,
+
     /// Enabled models: list of `<provider>/<model>` strings
     #[serde(default)]
     pub enabled_models: Vec<String>,
+
+    /// Endpoint parameters: provider_id -> name -> value (account id, region, ...)
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub provider_params: HashMap<String, HashMap<String, String>>,
+
+    /// User-defined providers, or endpoint overrides of built-in ones
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub custom_providers: Vec<CustomProvider>,
+
+    /// User-defined models, or overrides of built-in ones
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub custom_models: Vec<CustomModel>,
+
+    /// Azure OpenAI deployment mapping and api-version
+    #[serde(default)]
+    pub azure: AzureConfig,
+
+    /// Anthropic settings, such as how tool names are sent with subscription tokens
+    #[serde(default)]
+    pub anthropic: AnthropicConfig,
+
+    /// Ollama settings, such as how long models stay loaded
+    #[serde(default)]
+    pub ollama: OllamaConfig,
+
+    /// Set when the credentials are encrypted at rest. Loading decrypts them into
+    /// `credentials` and `credential_pools`; writing encrypts them again.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encrypted_credentials: Option<EncryptedCredentials>,
+}
+
+/// The part of the config that is encrypted at rest.
+#[derive(Default, Serialize, Deserialize)]
+struct CredentialSection {
+    #[serde(default)]
+    credentials: HashMap<String, Credential>,
+    #[serde(default)]
+    credential_pools: HashMap<String, CredentialPool>,
+}
+
+/// Refresh lock for one credential in one config file.
+type RefreshLock = Arc<tokio::sync::Mutex<()>>;
+/// Config file, provider and credential label.
+type RefreshKey = (PathBuf, String, String);
+
+/// In-process OAuth refresh locks.
+static REFRESH_LOCKS: LazyLock<Mutex<HashMap<RefreshKey, RefreshLock>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Held while refreshing a provider's OAuth token; the file lock is released when
+/// the file is closed.
+struct RefreshGuard {
+    _task: tokio::sync::OwnedMutexGuard<()>,
+    _file: fs::File,
 }
 
 /// Manages reading/writing the config file with safe atomic writes.
 #[derive(Clone)]
 pub struct ConfigManager {
     path: PathBuf,
+    /// OAuth flows refreshing tokens in place of the built-in ones, by provider id.
+    oauth_providers: HashMap<String, Arc<dyn crate::oauth::OAuthProvider>>,
 }
 
 impl ConfigManager {
     /// Create a config manager with a custom path.
     pub fn new(path: impl Into<PathBuf>) -> Self {
-        Self { path: path.into() }
+        Self { path: path.into(), oauth_providers: HashMap::new() }
+    }
+
+    /// Refresh a provider's OAuth tokens through `provider` rather than the
+    /// built-in flow, e.g. for a custom provider signing in with OAuth.
+    pub fn with_oauth_provider(mut self, provider_id: &str, provider: impl crate::oauth::OAuthProvider + 'static) -> Self {
+        self.oauth_providers.insert(provider_id.to_string(), Arc::new(provider));
+        self
     }
 
     /// Create a config manager with the default path (~/.ai-rs/config.json).
@@ -47,13 +121,104 @@ impl ConfigManager {
         }
 
         let content = fs::read_to_string(&self.path)?;
-        let config: AppConfig = serde_json::from_str(&content)?;
+        let mut config: AppConfig = serde_json::from_str(&content)?;
+
+        if let Some(encrypted) = &config.encrypted_credentials {
+            let secret = vault::secret_for(encrypted.key_source)?;
+            let mut section: CredentialSection = serde_json::from_slice(&encrypted.open(&secret)?)?;
+            // Plaintext entries added by hand win, and get encrypted on the next write
+            section.credentials.extend(std::mem::take(&mut config.credentials));
+            section.credential_pools.extend(std::mem::take(&mut config.credential_pools));
+            config.credentials = section.credentials;
+            config.credential_pools = section.credential_pools;
+        }
         Ok(config)
     }
 
+    /// What the credentials in the config file are encrypted with, or `None` when
+    /// they are stored in plaintext.
+    pub fn credentials_key_source(&self) -> anyhow::Result<Option<KeySource>> {
+        Ok(self.encrypted_credentials()?.map(|e| e.key_source))
+    }
+
+    /// Check that the credentials decrypt with `secret`, and use it for the rest
+    /// of the process.
+    pub fn unlock(&self, secret: Secret) -> anyhow::Result<()> {
+        if let Some(encrypted) = self.encrypted_credentials()? {
+            encrypted.open(&secret)?;
+        }
+        vault::unlock(secret);
+        Ok(())
+    }
+
+    /// The encrypted credentials envelope, which reads without the key.
+    fn encrypted_credentials(&self) -> anyhow::Result<Option<EncryptedCredentials>> {
+        if !self.path.exists() {
+            return Ok(None);
+        }
+        let mut value: serde_json::Value = serde_json::from_str(&fs::read_to_string(&self.path)?)?;
+        match value.get_mut("encrypted_credentials").map(serde_json::Value::take) {
+            Some(v) if !v.is_null() => Ok(Some(serde_json::from_value(v)?)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Encrypt the stored credentials with a key derived from `secret`.
+    pub fn encrypt_credentials(&self, secret: Secret) -> anyhow::Result<()> {
+        let _lock = self.lock()?;
+        if self.encrypted_credentials()?.is_some() {
+            anyhow::bail!("Credentials are already encrypted; rotate the key instead");
+        }
+        let mut config = self.load()?;
+        config.encrypted_credentials = Some(EncryptedCredentials::new(&secret)?);
+        self.write_with(&config, Some(&secret))?;
+        vault::unlock(secret);
+        Ok(())
+    }
+
+    /// Re-encrypt the stored credentials with a key derived from a new `secret`.
+    pub fn rotate_credentials_key(&self, secret: Secret) -> anyhow::Result<()> {
+        let _lock = self.lock()?;
+        let mut config = self.load()?;
+        if config.encrypted_credentials.is_none() {
+            anyhow::bail!("Credentials are not encrypted");
+        }
+        config.encrypted_credentials = Some(EncryptedCredentials::new(&secret)?);
+        self.write_with(&config, Some(&secret))?;
+        vault::unlock(secret);
+        Ok(())
+    }
+
+    /// Store the credentials in plaintext again.
+    pub fn decrypt_credentials(&self) -> anyhow::Result<()> {
+        self.update(|config| config.encrypted_credentials = None)
+    }
+
     /// Save the config to disk atomically (write to temp file, then rename).
-    /// This prevents corruption from concurrent writes or crashes.
+    /// This prevents corruption from concurrent writes or crashes. Prefer
+    /// [`update`](Self::update), which cannot lose changes made since `load()`.
     pub fn save(&self, config: &AppConfig) -> anyhow::Result<()> {
+        let _lock = self.lock()?;
+        self.write(config)
+    }
+
+    /// Read-modify-write the config under an exclusive lock: re-read the file,
+    /// apply `f`, and write the result atomically. Other processes and tasks
+    /// updating the same file wait for the lock, so no change is lost.
+    ///
+    /// Waiting blocks the thread; async code calls this through
+    /// [`tokio::task::spawn_blocking`] so it does not stall a runtime worker.
+    pub fn update<T>(&self, f: impl FnOnce(&mut AppConfig) -> T) -> anyhow::Result<T> {
+        let _lock = self.lock()?;
+        let mut config = self.load()?;
+        let result = f(&mut config);
+        self.write(&config)?;
+        Ok(result)
+    }
+
+    /// Take the exclusive advisory lock on a file beside the config. Released when
+    /// the returned file is closed.
+    fn lock(&self) -> anyhow::Result<fs::File> {
         // Ensure parent directory exists
         if let Some(parent) = self.path.parent() {
             fs::create_dir_all(parent)?;
@@ -65,44 +230,93 @@ impl ConfigManager {
             }
         }
 
-        let json = serde_json::to_string_pretty(config)?;
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(self.path.with_extension("lock"))?;
+        file.lock()?;
+        Ok(file)
+    }
 
-        // Write to a temp file in the same directory, then rename for atomicity
-        let tmp_path = self.path.with_extension("json.tmp");
-        {
-            let mut file = fs::File::create(&tmp_path)?;
-            file.write_all(json.as_bytes())?;
-            file.sync_all()?;
-        }
+    /// Write the config through a temp file and rename. The caller holds the lock.
+    fn write(&self, config: &AppConfig) -> anyhow::Result<()> {
+        let secret = match &config.encrypted_credentials {
+            Some(encrypted) => Some(vault::secret_for(encrypted.key_source)?),
+            None => None,
+        };
+        self.write_with(config, secret.as_ref())
+    }
 
-        // Set file permissions to 600 on Unix (before rename)
-        #[cfg(unix)]
-        {
-            use std::os::unix::fs::PermissionsExt;
-            let _ = fs::set_permissions(&tmp_path, fs::Permissions::from_mode(0o600));
-        }
+    /// Write the config, encrypting the credentials with `secret` if they are
+    /// encrypted at rest.
+    fn write_with(&self, config: &AppConfig, secret: Option<&Secret>) -> anyhow::Result<()> {
+        let json = match (&config.encrypted_credentials, secret) {
+            (Some(encrypted), Some(secret)) => {
+                let section = CredentialSection {
+                    credentials: config.credentials.clone(),
+                    credential_pools: config.credential_pools.clone(),
+                };
+                let sealed = encrypted.seal(secret, &serde_json::to_vec(&section)?)?;
+                serde_json::to_string_pretty(&AppConfig {
+                    credentials: HashMap::new(),
+                    credential_pools: HashMap::new(),
+                    encrypted_credentials: Some(sealed),
+                    ..config.clone()
+                })?
+            }
+            (Some(_), None) => anyhow::bail!("No key to encrypt the credentials with"),
+            (None, _) => serde_json::to_string_pretty(config)?,
+        };
+
+        // A temp name of our own, so an interrupted writer's leftover is never reused
+        let file_name = self.path.file_name().and_then(|n| n.to_str()).unwrap_or("config.json");
+        let tmp_path = self.path.with_file_name(format!(
+            ".{}.{}.{}.tmp",
+            file_name,
+            std::process::id(),
+            uuid::Uuid::new_v4().simple()
+        ));
+
+        let written = (|| -> anyhow::Result<()> {
+            {
+                let mut file = fs::File::create(&tmp_path)?;
+                file.write_all(json.as_bytes())?;
+                file.sync_all()?;
+            }
 
-        fs::rename(&tmp_path, &self.path)?;
+            // Set file permissions to 600 on Unix (before rename)
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                let _ = fs::set_permissions(&tmp_path, fs::Permissions::from_mode(0o600));
+            }
 
-        Ok(())
+            fs::rename(&tmp_path, &self.path)?;
+            Ok(())
+        })();
+        if written.is_err() {
+            let _ = fs::remove_file(&tmp_path);
+        }
+        written
     }
 
-    /// Set a credential for a provider (read-modify-write, atomic).
+    /// Set a credential for a provider.
     pub fn set_credential(
         &self,
         provider_id: &str,
         credential: Credential,
     ) -> anyhow::Result<()> {
-        let mut config = self.load()?;
-        config.credentials.insert(provider_id.to_string(), credential);
-        self.save(&config)
+        self.update(|config| {
+            config.credentials.insert(provider_id.to_string(), credential);
+        })
     }
 
     /// Remove a credential for a provider.
     pub fn remove_credential(&self, provider_id: &str) -> anyhow::Result<()> {
-        let mut config = self.load()?;
-        config.credentials.remove(provider_id);
-        self.save(&config)
+        self.update(|config| {
+            config.credentials.remove(provider_id);
+        })
     }
 
     /// Get a credential for a provider.
@@ -123,11 +337,89 @@ impl ConfigManager {
         Ok(config.credentials.keys().cloned().collect())
     }
 
+    /// Labels of a provider's credentials in the order to try them, starting with
+    /// [`DEFAULT_LABEL`], and the pool's strategy.
+    pub fn credential_pool(&self, provider_id: &str) -> anyhow::Result<(Vec<String>, PoolStrategy)> {
+        let config = self.load()?;
+        let mut labels = vec![DEFAULT_LABEL.to_string()];
+        let pool = config.credential_pools.get(provider_id);
+        labels.extend(pool.iter().flat_map(|p| p.entries.iter().map(|e| e.label.clone())));
+        Ok((labels, pool.map(|p| p.strategy).unwrap_or_default()))
+    }
+
+    /// Get a credential of a provider's pool by label.
+    pub fn get_pooled_credential(&self, provider_id: &str, label: &str) -> anyhow::Result<Option<Credential>> {
+        if label == DEFAULT_LABEL {
+            return self.get_credential(provider_id);
+        }
+        let config = self.load()?;
+        Ok(config
+            .credential_pools
+            .get(provider_id)
+            .and_then(|p| p.entries.iter().find(|e| e.label == label))
+            .map(|e| e.credential.clone()))
+    }
+
+    /// Add a credential to a provider's pool, or replace the one with this label.
+    pub fn set_pooled_credential(&self, provider_id: &str, label: &str, credential: Credential) -> anyhow::Result<()> {
+        if label == DEFAULT_LABEL {
+            return self.set_credential(provider_id, credential);
+        }
+        self.update(|config| {
+            let entries = &mut config.credential_pools.entry(provider_id.to_string()).or_default().entries;
+            match entries.iter_mut().find(|e| e.label == label) {
+                Some(entry) => entry.credential = credential,
+                None => entries.push(LabeledCredential { label: label.to_string(), credential }),
+            }
+        })
+    }
+
+    /// Remove a credential from a provider's pool.
+    pub fn remove_pooled_credential(&self, provider_id: &str, label: &str) -> anyhow::Result<()> {
+        if label == DEFAULT_LABEL {
+            return self.remove_credential(provider_id);
+        }
+        self.update(|config| {
+            if let Some(pool) = config.credential_pools.get_mut(provider_id) {
+                pool.entries.retain(|e| e.label != label);
+            }
+        })
+    }
+
+    /// Store extra fields, such as a discovered project, on the OAuth credential
+    /// whose API key is `api_key`, in whichever slot of the provider's pool holds
+    /// it. Returns false when no stored credential has that key.
+    pub fn set_oauth_extra(
+        &self,
+        provider_id: &str,
+        api_key: &str,
+        fields: &[(&str, serde_json::Value)],
+    ) -> anyhow::Result<bool> {
+        self.update(|config| {
+            let pooled = config
+                .credential_pools
+                .get_mut(provider_id)
+                .into_iter()
+                .flat_map(|p| p.entries.iter_mut().map(|e| &mut e.credential));
+            let slots = config.credentials.get_mut(provider_id).into_iter().chain(pooled);
+            for cred in slots {
+                if cred.api_key().as_deref() != Some(api_key) {
+                    continue;
+                }
+                if let Credential::OAuth(oauth) = cred {
+                    for (name, value) in fields {
+                        oauth.extra.insert(name.to_string(), value.clone());
+                    }
+                    return true;
+                }
+            }
+            false
+        })
+    }
+
     /// Set enabled models list.
     pub fn set_enabled_models(&self, models: Vec<String>) -> anyhow::Result<()> {
-        let mut config = self.load()?;
-        config.enabled_models = models;
-        self.save(&config)
+        self.update(|config| config.enabled_models = models)
     }
 
     /// Get enabled models list.
@@ -138,91 +430,227 @@ impl ConfigManager {
 
     /// Add models to the enabled list (dedup).
     pub fn add_enabled_models(&self, models: &[String]) -> anyhow::Result<()> {
-        let mut config = self.load()?;
-        for m in models {
-            if !config.enabled_models.contains(m) {
-                config.enabled_models.push(m.clone());
+        self.update(|config| {
+            for m in models {
+                if !config.enabled_models.contains(m) {
+                    config.enabled_models.push(m.clone());
+                }
             }
-        }
-        self.save(&config)
+        })
     }
 
     /// Remove models from the enabled list.
     pub fn remove_enabled_models(&self, models: &[String]) -> anyhow::Result<()> {
-        let mut config = self.load()?;
-        config.enabled_models.retain(|m| !models.contains(m));
-        self.save(&config)
+        self.update(|config| config.enabled_models.retain(|m| !models.contains(m)))
     }
 
-    /// Get the API key for a provider. Checks config, then env vars, then sniffed files.
+    /// Get the endpoint parameters for a provider.
+    pub fn get_provider_params(&self, provider_id: &str) -> anyhow::Result<HashMap<String, String>> {
+        let config = self.load()?;
+        Ok(config.provider_params.get(provider_id).cloned().unwrap_or_default())
+    }
+
+    /// Set one endpoint parameter for a provider.
+    pub fn set_provider_param(&self, provider_id: &str, name: &str, value: &str) -> anyhow::Result<()> {
+        self.update(|config| {
+            config
+                .provider_params
+                .entry(provider_id.to_string())
+                .or_default()
+                .insert(name.to_string(), value.to_string());
+        })
+    }
+
+    /// Get user-defined providers.
+    pub fn get_custom_providers(&self) -> anyhow::Result<Vec<CustomProvider>> {
+        let config = self.load()?;
+        Ok(config.custom_providers)
+    }
+
+    /// Get user-defined models and model overrides.
+    pub fn get_custom_models(&self) -> anyhow::Result<Vec<CustomModel>> {
+        let config = self.load()?;
+        Ok(config.custom_models)
+    }
+
+    /// Get the credential for a provider. Checks config, then env vars, then sniffed files.
     /// Automatically refreshes OAuth tokens if expired or near expiry.
-    pub async fn resolve_api_key_with_buffer(&self, provider_id: &str, buffer_secs: u64) -> anyhow::Result<Option<String>> {
+    pub async fn resolve_credential_with_buffer(&self, provider_id: &str, buffer_secs: u64) -> anyhow::Result<Option<Credential>> {
         // 1. Check config
-        if let Some(mut cred) = self.get_credential(provider_id)? {
-            // Handle OAuth refresh if needed
-            if let super::Credential::OAuth(ref mut oauth) = cred {
-                let now = chrono::Utc::now().timestamp_millis();
-                // If expired or expiring within buffer
-                if now + (buffer_secs as i64 * 1000) >= oauth.expires {
-                    let oauth_provider: Box<dyn crate::oauth::OAuthProvider> = match provider_id {
-                        "anthropic" => Box::new(crate::oauth::anthropic::AnthropicOAuthProvider),
-                        "gemini-cli" => Box::new(crate::oauth::google_gemini_cli::GeminiCliOAuthProvider),
-                        "antigravity" => Box::new(crate::oauth::google_antigravity::AntigravityOAuthProvider),
-                        "openai-codex" => Box::new(crate::oauth::openai_codex::OpenAiCodexOAuthProvider),
-                        "github-copilot" => Box::new(crate::oauth::github_copilot::GitHubCopilotOAuthProvider),
-                        "qwen" => Box::new(crate::oauth::qwen_portal::QwenPortalOAuthProvider),
-                        _ => return Ok(cred.api_key()), // Unknown provider, can't refresh
-                    };
-
-                    let old_creds = crate::oauth::OAuthCredentials {
-                        refresh: oauth.refresh.clone(),
-                        access: oauth.access.clone(),
-                        expires: oauth.expires,
-                        extra: oauth.extra.clone(),
-                    };
-
-                    match oauth_provider.refresh_token(&old_creds).await {
-                        Ok(new_creds) => {
-                            oauth.access = new_creds.access;
-                            oauth.refresh = new_creds.refresh;
-                            oauth.expires = new_creds.expires;
-                            oauth.extra = new_creds.extra;
-                            // Save refreshed token back to config
-                            self.set_credential(provider_id, cred.clone())?;
-                        }
-                        Err(e) => {
-                            tracing::warn!("Failed to refresh OAuth token for {}: {}", provider_id, e);
-                            // Continue with old token, might fail with 401
-                        }
-                    }
-                }
-            }
-
-            if let Some(key) = cred.api_key() {
-                return Ok(Some(key));
-            }
+        if let Some(cred) = self.resolve_stored_credential(provider_id, DEFAULT_LABEL, buffer_secs).await? {
+            return Ok(Some(cred));
         }
 
         // 2. Check environment variables
-        if let Some(key) = super::sniff::env_api_key(provider_id) {
-            return Ok(Some(key));
+        if let Some(cred) = super::sniff::env_credential(provider_id) {
+            return Ok(Some(cred));
         }
 
         // 3. Check external credential files
         if let Some(cred) = super::sniff::sniff_external_credential(provider_id) {
             // Persist the sniffed credential
-            self.set_credential(provider_id, cred.clone())?;
-            return Ok(cred.api_key());
+            self.store_credential(provider_id, DEFAULT_LABEL, cred.clone()).await?;
+            return Ok(Some(cred));
         }
 
         Ok(None)
     }
 
+    /// Get a credential of a provider's pool by label, refreshing OAuth tokens if
+    /// expired or near expiry. Only [`DEFAULT_LABEL`] falls back to env vars and
+    /// sniffed files, as in `resolve_credential`.
+    pub async fn resolve_pooled_credential_with_buffer(&self, provider_id: &str, label: &str, buffer_secs: u64) -> anyhow::Result<Option<Credential>> {
+        if label == DEFAULT_LABEL {
+            return self.resolve_credential_with_buffer(provider_id, buffer_secs).await;
+        }
+        self.resolve_stored_credential(provider_id, label, buffer_secs).await
+    }
+
+    /// Resolve a credential of a provider's pool with a default buffer of 5 minutes.
+    pub async fn resolve_pooled_credential(&self, provider_id: &str, label: &str) -> anyhow::Result<Option<Credential>> {
+        self.resolve_pooled_credential_with_buffer(provider_id, label, 5 * 60).await
+    }
+
+    /// The credential stored under `label`, refreshed if it expires within the buffer.
+    async fn resolve_stored_credential(&self, provider_id: &str, label: &str, buffer_secs: u64) -> anyhow::Result<Option<Credential>> {
+        let mut cred = match self.get_pooled_credential(provider_id, label)? {
+            Some(c) => c,
+            None => return Ok(None),
+        };
+
+        // Handle OAuth refresh if expired or expiring within buffer
+        let expiring = |oauth: &OAuthCredential| {
+            chrono::Utc::now().timestamp_millis() + (buffer_secs as i64 * 1000) >= oauth.expires
+        };
+        if matches!(&cred, Credential::OAuth(oauth) if expiring(oauth)) {
+            match self.refresh_locked(provider_id, label, expiring).await {
+                Ok(Some(fresh)) => cred = fresh,
+                Ok(None) => {}
+                Err(e) => {
+                    tracing::warn!("Failed to refresh OAuth token for {}/{}: {}", provider_id, label, e);
+                    // Continue with old token, might fail with 401
+                }
+            }
+        }
+
+        Ok(Some(cred).filter(|c| c.api_key().is_some()))
+    }
+
+    /// Resolve the credential with a default buffer of 5 minutes.
+    pub async fn resolve_credential(&self, provider_id: &str) -> anyhow::Result<Option<Credential>> {
+        self.resolve_credential_with_buffer(provider_id, 5 * 60).await
+    }
+
+    /// Get the API key for a provider. Checks config, then env vars, then sniffed files.
+    /// Automatically refreshes OAuth tokens if expired or near expiry.
+    pub async fn resolve_api_key_with_buffer(&self, provider_id: &str, buffer_secs: u64) -> anyhow::Result<Option<String>> {
+        let cred = self.resolve_credential_with_buffer(provider_id, buffer_secs).await?;
+        Ok(cred.and_then(|c| c.api_key()))
+    }
+
     /// Resolve API key with a default buffer of 5 minutes.
     pub async fn resolve_api_key(&self, provider_id: &str) -> anyhow::Result<Option<String>> {
         self.resolve_api_key_with_buffer(provider_id, 5 * 60).await
     }
 
+    /// Refresh the stored OAuth credential after the server rejected its API key
+    /// `rejected`. Returns the new API key, or `None` when the provider has no
+    /// refreshable credential with that key.
+    pub async fn refresh_api_key(&self, provider_id: &str, rejected: &str) -> anyhow::Result<Option<String>> {
+        let is_rejected = |oauth: &OAuthCredential| {
+            Credential::OAuth(oauth.clone()).api_key().as_deref() == Some(rejected)
+        };
+
+        // The rejected key may belong to any credential of the pool
+        let (labels, _) = self.credential_pool(provider_id)?;
+        for label in labels {
+            let cred = self.get_pooled_credential(provider_id, &label)?;
+            if !matches!(&cred, Some(Credential::OAuth(oauth)) if is_rejected(oauth)) {
+                continue;
+            }
+            let cred = self.refresh_locked(provider_id, &label, is_rejected).await?;
+            return Ok(cred.and_then(|c| c.api_key()).filter(|key| key != rejected));
+        }
+        Ok(None)
+    }
+
+    /// Refresh the stored OAuth credential if it is still `stale` once this caller
+    /// holds the refresh lock, and return the credential as stored afterwards.
+    ///
+    /// Refresh tokens may rotate, so of two concurrent refreshes the second would
+    /// fail and could invalidate the login. The lock lets one refresh run per
+    /// credential, across tasks and processes sharing the config file; whoever
+    /// waited re-reads the credential and finds it already fresh.
+    async fn refresh_locked(
+        &self,
+        provider_id: &str,
+        label: &str,
+        stale: impl Fn(&OAuthCredential) -> bool,
+    ) -> anyhow::Result<Option<Credential>> {
+        let oauth_provider = match self.oauth_providers.get(provider_id) {
+            Some(p) => Some(p.clone()),
+            None => oauth_provider(provider_id),
+        };
+        let _guard = self.lock_refresh(provider_id, label).await?;
+        let mut cred = match self.get_pooled_credential(provider_id, label)? {
+            Some(c) => c,
+            None => return Ok(None),
+        };
+        let refreshed = match (&mut cred, oauth_provider) {
+            (Credential::OAuth(oauth), Some(oauth_provider)) if stale(oauth) => {
+                refresh_oauth(oauth_provider.as_ref(), oauth).await?;
+                true
+            }
+            _ => false,
+        };
+        if refreshed {
+            // Save refreshed token back to config
+            self.store_credential(provider_id, label, cred.clone()).await?;
+        }
+        Ok(Some(cred))
+    }
+
+    /// [`Self::set_pooled_credential`] on a blocking thread, as the config lock may
+    /// be held by another writer.
+    async fn store_credential(&self, provider_id: &str, label: &str, credential: Credential) -> anyhow::Result<()> {
+        let (config, provider_id, label) = (self.clone(), provider_id.to_string(), label.to_string());
+        tokio::task::spawn_blocking(move || config.set_pooled_credential(&provider_id, &label, credential)).await?
+    }
+
+    /// Take the refresh lock for a credential: a mutex shared by every manager of
+    /// this config file in the process, then an advisory lock on a file beside it.
+    async fn lock_refresh(&self, provider_id: &str, label: &str) -> anyhow::Result<RefreshGuard> {
+        let lock = REFRESH_LOCKS
+            .lock()
+            .unwrap()
+            .entry((self.path.clone(), provider_id.to_string(), label.to_string()))
+            .or_default()
+            .clone();
+        let task = lock.lock_owned().await;
+
+        let name = if label == DEFAULT_LABEL {
+            provider_id.to_string()
+        } else {
+            format!("{}.{}", provider_id, label)
+        };
+        let name: String = name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '.' { c } else { '_' })
+            .collect();
+        let path = self.path.with_extension(format!("refresh-{}.lock", name));
+        let file = tokio::task::spawn_blocking(move || -> std::io::Result<fs::File> {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let file = fs::OpenOptions::new().create(true).truncate(false).write(true).open(&path)?;
+            file.lock()?;
+            Ok(file)
+        })
+        .await??;
+
+        Ok(RefreshGuard { _task: task, _file: file })
+    }
+
     /// Refresh all OAuth credentials in the config if they are near expiry.
     pub async fn refresh_all_credentials(&self, buffer_secs: u64) -> anyhow::Result<()> {
         let providers = self.list_providers_with_credentials()?;
@@ -230,6 +658,12 @@ impl ConfigManager {
             // resolve_api_key handles the logic of checking expiry and refreshing
             let _ = self.resolve_api_key_with_buffer(&pid, buffer_secs).await?;
         }
+        let pools = self.load()?.credential_pools;
+        for (pid, pool) in pools {
+            for entry in pool.entries {
+                let _ = self.resolve_pooled_credential_with_buffer(&pid, &entry.label, buffer_secs).await?;
+            }
+        }
         Ok(())
     }
 
@@ -248,3 +682,109 @@ impl ConfigManager {
         })
     }
 }
+
+/// The built-in OAuth flow refreshing a provider's tokens, if it has one.
+fn oauth_provider(provider_id: &str) -> Option<Arc<dyn crate::oauth::OAuthProvider>> {
+    let oauth_provider: Arc<dyn crate::oauth::OAuthProvider> = match provider_id {
+        "anthropic" => Arc::new(crate::oauth::anthropic::AnthropicOAuthProvider),
+        "gemini-cli" => Arc::new(crate::oauth::google_gemini_cli::GeminiCliOAuthProvider),
+        "antigravity" => Arc::new(crate::oauth::google_antigravity::AntigravityOAuthProvider),
+        "openai-codex" => Arc::new(crate::oauth::openai_codex::OpenAiCodexOAuthProvider),
+        "github-copilot" => Arc::new(crate::oauth::github_copilot::GitHubCopilotOAuthProvider),
+        "qwen" => Arc::new(crate::oauth::qwen_portal::QwenPortalOAuthProvider),
+        _ => return None, // Unknown provider, can't refresh
+    };
+    Some(oauth_provider)
+}
+
+/// Refresh an OAuth credential in place.
+async fn refresh_oauth(oauth_provider: &dyn crate::oauth::OAuthProvider, oauth: &mut OAuthCredential) -> anyhow::Result<()> {
+    let old_creds = crate::oauth::OAuthCredentials {
+        refresh: oauth.refresh.clone(),
+        access: oauth.access.clone(),
+        expires: oauth.expires,
+        extra: oauth.extra.clone(),
+    };
+
+    let new_creds = oauth_provider.refresh_token(&old_creds).await?;
+    oauth.access = new_creds.access;
+    oauth.refresh = new_creds.refresh;
+    oauth.expires = new_creds.expires;
+    oauth.extra = new_creds.extra;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::test_server::{FakeOAuth, FakeServer, Response};
+
+    fn temp_config() -> ConfigManager {
+        let dir = std::env::temp_dir().join(format!("ai-rs-test-{}", uuid::Uuid::new_v4().simple()));
+        ConfigManager::new(dir.join("config.json"))
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_refreshes_of_a_credential_make_one_token_request() {
+        // Refresh tokens rotate: a second request with the old one would be refused
+        let server = FakeServer::start(|req| match req.body.as_str() {
+            "refresh_token=old-refresh" => Response::json(200, serde_json::json!({
+                "access_token": "fresh-access", "refresh_token": "new-refresh"})),
+            _ => Response::json(400, serde_json::json!({"error": "invalid_grant"})),
+        })
+        .await;
+        let config = temp_config().with_oauth_provider("test", FakeOAuth::new(&server.url));
+        config
+            .set_credential("test", Credential::OAuth(OAuthCredential {
+                refresh: "old-refresh".into(),
+                access: "stale-access".into(),
+                expires: 0,
+                extra: HashMap::new(),
+            }))
+            .unwrap();
+
+        let tasks: Vec<_> = (0..8)
+            .map(|_| {
+                let config = config.clone();
+                tokio::spawn(async move { config.resolve_credential("test").await })
+            })
+            .collect();
+        for task in tasks {
+            let cred = task.await.unwrap().unwrap().unwrap();
+            assert_eq!(cred.api_key().as_deref(), Some("fresh-access"));
+        }
+
+        assert_eq!(server.requests().len(), 1);
+        match config.get_credential("test").unwrap() {
+            Some(Credential::OAuth(oauth)) => assert_eq!(oauth.refresh, "new-refresh"),
+            other => panic!("unexpected credential {:?}", other),
+        }
+        let _ = fs::remove_dir_all(config.path().parent().unwrap());
+    }
+
+    #[test]
+    fn concurrent_updates_keep_every_change() {
+        let config = temp_config();
+        config.set_enabled_models(vec!["openai/gpt-4o".into()]).unwrap();
+
+        // Each writer has its own manager, as separate processes would
+        let writers: Vec<_> = (0..16)
+            .map(|i| {
+                let config = ConfigManager::new(config.path());
+                std::thread::spawn(move || {
+                    config.add_enabled_models(&[format!("test/model-{}", i)]).unwrap();
+                    config.set_provider_param("test", &format!("param-{}", i), "x").unwrap();
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        let loaded = config.load().unwrap();
+        assert_eq!(loaded.enabled_models.len(), 17);
+        assert!((0..16).all(|i| loaded.enabled_models.contains(&format!("test/model-{}", i))));
+        assert_eq!(loaded.provider_params["test"].len(), 16);
+        let _ = fs::remove_dir_all(config.path().parent().unwrap());
+    }
+}
@@ -1,5 +1,7 @@
 pub mod config;
+pub mod pool;
 pub mod sniff;
+pub mod vault;
 
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -35,28 +37,48 @@ pub enum Credential {
     SetupToken(SetupTokenCredential),
 }
 
+/// OAuth `extra` fields a provider needs besides the token, and their names in
+/// the JSON key `{"token", ...}` it is then given.
+const OAUTH_KEY_FIELDS: &[(&str, &str)] = &[
+    // Cloud Code Assist project
+    ("projectId", "projectId"),
+    // GitHub Copilot API endpoint
+    ("apiEndpoint", "endpoint"),
+    // ChatGPT account for OpenAI Codex
+    ("accountId", "accountId"),
+    // Qwen API host
+    ("resourceUrl", "resourceUrl"),
+];
+
 impl Credential {
     pub fn api_key(&self) -> Option<String> {
         match self {
             Credential::ApiKey(c) => Some(c.key.clone()),
             Credential::OAuth(c) => {
-                if let Some(project_id) = c.extra.get("projectId") {
-                    if let Some(pid) = project_id.as_str() {
-                        return Some(
-                            serde_json::json!({
-                                "token": c.access,
-                                "projectId": pid
-                            })
-                            .to_string(),
-                        );
+                let mut key = serde_json::Map::new();
+                for (field, name) in OAUTH_KEY_FIELDS {
+                    if let Some(value) = c.extra.get(*field).filter(|v| v.is_string()) {
+                        key.insert(name.to_string(), value.clone());
                     }
                 }
-                Some(c.access.clone())
+                if key.is_empty() {
+                    return Some(c.access.clone());
+                }
+                key.insert("token".into(), serde_json::Value::String(c.access.clone()));
+                Some(serde_json::Value::Object(key).to_string())
             }
             Credential::SetupToken(c) => Some(c.token.clone()),
         }
     }
 
+    pub fn kind(&self) -> crate::types::CredentialKind {
+        match self {
+            Credential::ApiKey(_) => crate::types::CredentialKind::ApiKey,
+            Credential::OAuth(_) => crate::types::CredentialKind::OAuth,
+            Credential::SetupToken(_) => crate::types::CredentialKind::SetupToken,
+        }
+    }
+
     pub fn is_expired(&self) -> bool {
         match self {
             Credential::OAuth(c) => chrono::Utc::now().timestamp_millis() >= c.expires,
@@ -115,6 +137,16 @@ pub fn all_provider_auth_info() -> Vec<ProviderAuthInfo> {
                 hint: Some("OAuth flow for ChatGPT session".into()),
             }],
         },
+        ProviderAuthInfo {
+            provider_id: "azure-openai".into(),
+            label: "Azure OpenAI".into(),
+            group: "OpenAI".into(),
+            hint: "API key or Entra ID token + resource name".into(),
+            auth_methods: vec![AuthMethod::ApiKey {
+                env_var: Some("AZURE_OPENAI_API_KEY".into()),
+                hint: Some("Deployments and api-version are set under \"azure\" in the config file".into()),
+            }],
+        },
         // Anthropic Group
         ProviderAuthInfo {
             provider_id: "anthropic".into(),
@@ -197,6 +229,16 @@ pub fn all_provider_auth_info() -> Vec<ProviderAuthInfo> {
                 hint: Some("Uses the bundled Gemini CLI auth plugin".into()),
             }],
         },
+        ProviderAuthInfo {
+            provider_id: "vertex".into(),
+            label: "Google Vertex AI".into(),
+            group: "Google".into(),
+            hint: "Service account or gcloud ADC + project".into(),
+            auth_methods: vec![AuthMethod::ApiKey {
+                env_var: Some("GOOGLE_APPLICATION_CREDENTIALS".into()),
+                hint: Some("Path to a service-account JSON key file, or an access token".into()),
+            }],
+        },
         // xAI Group
         ProviderAuthInfo {
             provider_id: "xai".into(),
@@ -323,6 +365,17 @@ pub fn all_provider_auth_info() -> Vec<ProviderAuthInfo> {
             hint: "Account ID + Gateway ID + API key".into(),
             auth_methods: vec![AuthMethod::ApiKey {
                 env_var: None,
+                hint: Some("Account ID and Gateway ID are asked for next".into()),
+            }],
+        },
+        // Mistral Group
+        ProviderAuthInfo {
+            provider_id: "mistral".into(),
+            label: "Mistral API key".into(),
+            group: "Mistral".into(),
+            hint: "Mistral Large, Codestral".into(),
+            auth_methods: vec![AuthMethod::ApiKey {
+                env_var: Some("MISTRAL_API_KEY".into()),
                 hint: None,
             }],
         },
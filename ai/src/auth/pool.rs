@@ -0,0 +1,257 @@
+//! Several credentials per provider, e.g. a team's API keys or accounts.
+//!
+//! A provider's pool is its main credential (labelled [`DEFAULT_LABEL`]) followed
+//! by the entries of its [`CredentialPool`] in the config. Which one serves a
+//! request is decided by a [`CredentialSelector`], which also rests credentials
+//! that hit a rate limit or quota.
+
+use super::Credential;
+use crate::providers::{ProviderError, is_quota_exhausted};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Label of the provider's main credential, the one in `AppConfig.credentials`.
+pub const DEFAULT_LABEL: &str = "default";
+
+/// How long a credential rests after a rate limit that names no retry time.
+pub const RATE_LIMIT_COOLDOWN: Duration = Duration::from_secs(60);
+/// How long a credential rests after running out of quota.
+pub const QUOTA_COOLDOWN: Duration = Duration::from_secs(15 * 60);
+
+/// Sticky conversations remembered per provider before starting over.
+const MAX_STICKY_CONVERSATIONS: usize = 10_000;
+
+/// Which credential of a pool serves a request.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PoolStrategy {
+    /// Each request starts at the next credential.
+    #[default]
+    RoundRobin,
+    /// The credential rate-limited longest ago (or never) first, to spread out limits.
+    LeastRecentlyLimited,
+    /// A conversation keeps the credential it started with, so server-side prompt
+    /// caches keep hitting; new conversations are spread round-robin.
+    Sticky,
+}
+
+/// Additional credentials for one provider.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CredentialPool {
+    #[serde(default)]
+    pub strategy: PoolStrategy,
+    /// Credentials after the provider's main one, in order.
+    #[serde(default)]
+    pub entries: Vec<LabeledCredential>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LabeledCredential {
+    pub label: String,
+    pub credential: Credential,
+}
+
+/// How long to rest a credential after this error, if it means the credential hit
+/// a rate limit or ran out of quota.
+pub fn rate_limit_cooldown(err: &ProviderError) -> Option<Duration> {
+    match err {
+        ProviderError::RateLimited { retry_after_ms } => Some(
+            retry_after_ms
+                .map(Duration::from_millis)
+                .unwrap_or(RATE_LIMIT_COOLDOWN),
+        ),
+        ProviderError::Http { status, body } => match status {
+            429 if is_quota_exhausted(body) => Some(QUOTA_COOLDOWN),
+            429 => Some(RATE_LIMIT_COOLDOWN),
+            // Some APIs report an exhausted quota as forbidden
+            403 if is_quota_exhausted(body) => Some(QUOTA_COOLDOWN),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[derive(Default)]
+struct ProviderState {
+    /// Round-robin position.
+    next: usize,
+    /// Label -> when it may serve again.
+    cooldowns: HashMap<String, Instant>,
+    /// Label -> when it was last rate-limited.
+    last_limited: HashMap<String, Instant>,
+    /// Conversation id -> label serving it.
+    sticky: HashMap<String, String>,
+}
+
+/// In-memory rotation and rate-limit state of the credential pools, shared by all
+/// requests of a process.
+#[derive(Default)]
+pub struct CredentialSelector {
+    providers: Mutex<HashMap<String, ProviderState>>,
+}
+
+impl CredentialSelector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Labels in the order to try them for one request: the chosen credential
+    /// first, the others after it for failover. Credentials that are cooling down
+    /// come last, soonest available first.
+    pub fn order(
+        &self,
+        provider_id: &str,
+        labels: &[String],
+        strategy: PoolStrategy,
+        conversation_id: Option<&str>,
+    ) -> Vec<String> {
+        let mut providers = self.providers.lock().unwrap();
+        let state = providers.entry(provider_id.to_string()).or_default();
+        let now = Instant::now();
+        state.cooldowns.retain(|_, until| *until > now);
+
+        let mut order: Vec<String> = labels.to_vec();
+        let len = order.len();
+        if len > 0 {
+            match strategy {
+                PoolStrategy::RoundRobin => {
+                    order.rotate_left(state.next % len);
+                    state.next = state.next.wrapping_add(1);
+                }
+                PoolStrategy::LeastRecentlyLimited => {
+                    // Stable sort: never-limited credentials keep their config order
+                    order.sort_by_key(|l| state.last_limited.get(l).copied());
+                }
+                PoolStrategy::Sticky => {
+                    let bound = conversation_id
+                        .and_then(|c| state.sticky.get(c))
+                        .and_then(|l| order.iter().position(|o| o == l));
+                    match bound {
+                        Some(pos) => order.rotate_left(pos),
+                        None => {
+                            order.rotate_left(state.next % len);
+                            state.next = state.next.wrapping_add(1);
+                        }
+                    }
+                }
+            }
+        }
+
+        // Stable sort: available credentials keep the strategy's order
+        order.sort_by_key(|l| state.cooldowns.get(l).copied());
+        order
+    }
+
+    /// Record that `label` served a request, binding the conversation to it.
+    pub fn record_success(&self, provider_id: &str, label: &str, conversation_id: Option<&str>) {
+        let conversation_id = match conversation_id {
+            Some(c) => c,
+            None => return,
+        };
+        let mut providers = self.providers.lock().unwrap();
+        let state = providers.entry(provider_id.to_string()).or_default();
+        if state.sticky.len() >= MAX_STICKY_CONVERSATIONS && !state.sticky.contains_key(conversation_id) {
+            state.sticky.clear();
+        }
+        state.sticky.insert(conversation_id.to_string(), label.to_string());
+    }
+
+    /// Rest `label` for `cooldown` after a rate limit or quota error.
+    pub fn cool_down(&self, provider_id: &str, label: &str, cooldown: Duration) {
+        tracing::info!("Credential {}/{} rate limited, resting it for {}s", provider_id, label, cooldown.as_secs());
+        let mut providers = self.providers.lock().unwrap();
+        let state = providers.entry(provider_id.to_string()).or_default();
+        let now = Instant::now();
+        state.cooldowns.insert(label.to_string(), now + cooldown);
+        state.last_limited.insert(label.to_string(), now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    fn http(status: u16, body: &str) -> ProviderError {
+        ProviderError::Http { status, body: body.into() }
+    }
+
+    #[test]
+    fn round_robin_starts_each_request_at_the_next_credential() {
+        let selector = CredentialSelector::new();
+        let pool = labels(&["default", "b", "c"]);
+        let first: Vec<_> = (0..4)
+            .map(|_| selector.order("openai", &pool, PoolStrategy::RoundRobin, None)[0].clone())
+            .collect();
+        assert_eq!(first, ["default", "b", "c", "default"]);
+        // The rest follow for failover
+        assert_eq!(selector.order("openai", &pool, PoolStrategy::RoundRobin, None), ["b", "c", "default"]);
+        // Providers rotate independently
+        assert_eq!(selector.order("groq", &pool, PoolStrategy::RoundRobin, None)[0], "default");
+    }
+
+    #[test]
+    fn cooling_credentials_go_last_until_they_recover() {
+        let selector = CredentialSelector::new();
+        let pool = labels(&["default", "b", "c"]);
+        selector.cool_down("openai", "default", Duration::from_secs(60));
+        selector.cool_down("openai", "b", Duration::from_secs(30));
+        assert_eq!(selector.order("openai", &pool, PoolStrategy::LeastRecentlyLimited, None), ["c", "b", "default"]);
+
+        // An expired cooldown no longer holds it back
+        selector.cool_down("openai", "b", Duration::ZERO);
+        assert_eq!(selector.order("openai", &pool, PoolStrategy::LeastRecentlyLimited, None), ["c", "b", "default"]);
+        assert_eq!(selector.order("openai", &pool, PoolStrategy::RoundRobin, None), ["b", "c", "default"]);
+    }
+
+    #[test]
+    fn least_recently_limited_prefers_never_limited_credentials() {
+        let selector = CredentialSelector::new();
+        let pool = labels(&["default", "b", "c"]);
+        selector.cool_down("openai", "default", Duration::ZERO);
+        std::thread::sleep(Duration::from_millis(2));
+        selector.cool_down("openai", "c", Duration::ZERO);
+        assert_eq!(selector.order("openai", &pool, PoolStrategy::LeastRecentlyLimited, None), ["b", "default", "c"]);
+    }
+
+    #[test]
+    fn sticky_conversations_keep_their_credential() {
+        let selector = CredentialSelector::new();
+        let pool = labels(&["default", "b"]);
+        let first = selector.order("anthropic", &pool, PoolStrategy::Sticky, Some("conv-1"));
+        selector.record_success("anthropic", &first[1], Some("conv-1"));
+        for _ in 0..3 {
+            assert_eq!(selector.order("anthropic", &pool, PoolStrategy::Sticky, Some("conv-1"))[0], first[1]);
+        }
+
+        // Unless that credential is resting
+        selector.cool_down("anthropic", &first[1], Duration::from_secs(60));
+        assert_eq!(selector.order("anthropic", &pool, PoolStrategy::Sticky, Some("conv-1"))[0], first[0]);
+    }
+
+    #[test]
+    fn rate_limits_rest_briefly_and_exhausted_quotas_long() {
+        let retry = ProviderError::RateLimited { retry_after_ms: Some(2500) };
+        assert_eq!(rate_limit_cooldown(&retry), Some(Duration::from_millis(2500)));
+        let limited = ProviderError::RateLimited { retry_after_ms: None };
+        assert_eq!(rate_limit_cooldown(&limited), Some(RATE_LIMIT_COOLDOWN));
+
+        let per_minute = r#"{"error": {"status": "RESOURCE_EXHAUSTED", "message": "Quota exceeded for metric ... per minute",
+            "details": [{"violations": [{"quotaId": "GenerateRequestsPerMinutePerProjectPerModel-FreeTier"}]}]}}"#;
+        assert_eq!(rate_limit_cooldown(&http(429, per_minute)), Some(RATE_LIMIT_COOLDOWN));
+        let per_day = per_minute.replace("PerMinute", "PerDay");
+        assert_eq!(rate_limit_cooldown(&http(429, &per_day)), Some(QUOTA_COOLDOWN));
+        let no_credit = r#"{"error": {"code": "insufficient_quota"}}"#;
+        assert_eq!(rate_limit_cooldown(&http(429, no_credit)), Some(QUOTA_COOLDOWN));
+        assert_eq!(rate_limit_cooldown(&http(403, no_credit)), Some(QUOTA_COOLDOWN));
+
+        assert_eq!(rate_limit_cooldown(&http(403, "forbidden")), None);
+        assert_eq!(rate_limit_cooldown(&http(500, "insufficient_quota")), None);
+        assert_eq!(rate_limit_cooldown(&ProviderError::Other("quota".into())), None);
+    }
+}
@@ -1,4 +1,4 @@
-use super::{ApiKeyCredential, Credential, OAuthCredential};
+use super::{ApiKeyCredential, Credential, OAuthCredential, SetupTokenCredential};
 use std::collections::HashMap;
 use std::path::PathBuf;
 
@@ -9,6 +9,7 @@ use std::path::PathBuf;
 /// Known environment variables per provider.
 const ENV_VAR_MAP: &[(&str, &str)] = &[
     ("openai", "OPENAI_API_KEY"),
+    ("azure-openai", "AZURE_OPENAI_API_KEY"),
     ("anthropic", "ANTHROPIC_API_KEY"),
     ("google", "GEMINI_API_KEY"),
     ("deepseek", "DEEPSEEK_API_KEY"),
@@ -22,20 +23,27 @@ const ENV_VAR_MAP: &[(&str, &str)] = &[
     ("openrouter", "OPENROUTER_API_KEY"),
     ("mistral", "MISTRAL_API_KEY"),
     ("huggingface", "HF_TOKEN"),
+    ("qwen", "QWEN_API_KEY"),
+    ("qwen", "DASHSCOPE_API_KEY"),
 ];
 
 /// Try to get an API key from environment variables for the given provider.
 pub fn env_api_key(provider_id: &str) -> Option<String> {
-    // Special case: Anthropic has multiple env vars
+    env_credential(provider_id).and_then(|c| c.api_key())
+}
+
+/// Credential from environment variables for the given provider.
+pub fn env_credential(provider_id: &str) -> Option<Credential> {
+    // Special case: Anthropic has multiple env vars, and the OAuth token is not an API key
     if provider_id == "anthropic" {
         if let Ok(val) = std::env::var("ANTHROPIC_OAUTH_TOKEN") {
             if !val.is_empty() {
-                return Some(val);
+                return Some(Credential::SetupToken(SetupTokenCredential { token: val }));
             }
         }
         if let Ok(val) = std::env::var("ANTHROPIC_API_KEY") {
             if !val.is_empty() {
-                return Some(val);
+                return Some(Credential::ApiKey(ApiKeyCredential { key: val }));
             }
         }
         return None;
@@ -45,7 +53,7 @@ pub fn env_api_key(provider_id: &str) -> Option<String> {
         if *prov == provider_id {
             if let Ok(val) = std::env::var(env_var) {
                 if !val.is_empty() {
-                    return Some(val);
+                    return Some(Credential::ApiKey(ApiKeyCredential { key: val }));
                 }
             }
         }
@@ -104,6 +112,22 @@ fn external_credential_paths() -> Vec<ExternalCredFile> {
                 .join("application_default_credentials.json"),
             kind: CredFileKind::GCloudADC,
         },
+        ExternalCredFile {
+            provider: "vertex".into(),
+            path: std::env::var("GOOGLE_APPLICATION_CREDENTIALS")
+                .map(PathBuf::from)
+                .unwrap_or_else(|_| {
+                    home.join(".config")
+                        .join("gcloud")
+                        .join("application_default_credentials.json")
+                }),
+            kind: CredFileKind::GoogleCredentialsFile,
+        },
+        ExternalCredFile {
+            provider: "qwen".into(),
+            path: home.join(".qwen").join("oauth_creds.json"),
+            kind: CredFileKind::QwenOAuth,
+        },
         ExternalCredFile {
             provider: "anthropic".into(),
             path: home.join(".anthropic").join("config.json"),
@@ -126,6 +150,8 @@ struct ExternalCredFile {
 enum CredFileKind {
     GeminiOAuth,
     GCloudADC,
+    GoogleCredentialsFile,
+    QwenOAuth,
     AnthropicConfig,
     OpenAiAuth,
 }
@@ -157,6 +183,12 @@ pub fn sniff_external_credential(provider_id: &str) -> Option<Credential> {
             CredFileKind::GCloudADC => {
                 return parse_gcloud_adc(&content);
             }
+            CredFileKind::GoogleCredentialsFile => {
+                return parse_google_credentials(&content);
+            }
+            CredFileKind::QwenOAuth => {
+                return parse_qwen_oauth_creds(&content);
+            }
             CredFileKind::AnthropicConfig => {
                 return parse_anthropic_config(&content);
             }
@@ -183,6 +215,8 @@ pub fn sniff_all_external_credentials() -> HashMap<String, Credential> {
         let cred = match file.kind {
             CredFileKind::GeminiOAuth => parse_gemini_oauth_creds(&content),
             CredFileKind::GCloudADC => parse_gcloud_adc(&content),
+            CredFileKind::GoogleCredentialsFile => parse_google_credentials(&content),
+            CredFileKind::QwenOAuth => parse_qwen_oauth_creds(&content),
             CredFileKind::AnthropicConfig => parse_anthropic_config(&content),
             CredFileKind::OpenAiAuth => parse_openai_auth(&content),
         };
@@ -256,6 +290,45 @@ fn parse_gcloud_adc(content: &str) -> Option<Credential> {
     }))
 }
 
+/// Parse a service-account or ADC file. The JSON itself is the key, so the
+/// credential keeps working if the file moves.
+fn parse_google_credentials(content: &str) -> Option<Credential> {
+    let creds: serde_json::Value = serde_json::from_str(content).ok()?;
+    match creds.get("type")?.as_str()? {
+        "service_account" | "authorized_user" => Some(Credential::ApiKey(ApiKeyCredential {
+            key: creds.to_string(),
+        })),
+        _ => None,
+    }
+}
+
+/// Parse ~/.qwen/oauth_creds.json (written by Qwen Code)
+fn parse_qwen_oauth_creds(content: &str) -> Option<Credential> {
+    #[derive(serde::Deserialize)]
+    struct QwenOAuth {
+        access_token: Option<String>,
+        refresh_token: Option<String>,
+        resource_url: Option<String>,
+        /// Epoch millis
+        expiry_date: Option<i64>,
+    }
+
+    let creds: QwenOAuth = serde_json::from_str(content).ok()?;
+    let refresh = creds.refresh_token?;
+
+    let mut extra = HashMap::new();
+    if let Some(resource_url) = creds.resource_url {
+        extra.insert("resourceUrl".to_string(), serde_json::Value::String(resource_url));
+    }
+
+    Some(Credential::OAuth(OAuthCredential {
+        refresh,
+        access: creds.access_token.unwrap_or_default(),
+        expires: creds.expiry_date.unwrap_or(0),
+        extra,
+    }))
+}
+
 /// Parse ~/.anthropic/config.json
 fn parse_anthropic_config(content: &str) -> Option<Credential> {
     #[derive(serde::Deserialize)]
@@ -293,3 +366,26 @@ fn parse_openai_auth(content: &str) -> Option<Credential> {
     }
     Some(Credential::ApiKey(ApiKeyCredential { key }))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn google_credential_files_are_stored_as_their_json() {
+        let content = r#"{
+            "type": "service_account",
+            "project_id": "my-project",
+            "client_email": "bot@my-project.iam.gserviceaccount.com"
+        }"#;
+        let key = parse_google_credentials(content).and_then(|c| c.api_key()).unwrap();
+        assert!(key.starts_with('{'), "{}", key);
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&key).unwrap(),
+            serde_json::from_str::<serde_json::Value>(content).unwrap()
+        );
+
+        assert!(parse_google_credentials(r#"{"type": "external_account"}"#).is_none());
+        assert!(parse_google_credentials("not json").is_none());
+    }
+}
@@ -0,0 +1,348 @@
+//! Encryption at rest for the credentials section of the config.
+//!
+//! When enabled, `credentials` and `credential_pools` are stored as one
+//! AES-256-GCM sealed blob, an [`EncryptedCredentials`], instead of plaintext.
+//! The key is derived from a passphrase with PBKDF2, or from a key file.
+//! [`ConfigManager`](super::config::ConfigManager) opens the blob on load and seals
+//! it again on every write, so callers keep seeing plaintext credentials.
+//!
+//! The secret comes from [`unlock`], or else from [`PASSPHRASE_ENV`] or
+//! [`KEY_FILE_ENV`].
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use ring::aead::{AES_256_GCM, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey};
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::{digest, pbkdf2};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::num::NonZeroU32;
+use std::path::Path;
+use std::sync::{LazyLock, Mutex};
+
+/// Env var holding the passphrase.
+pub const PASSPHRASE_ENV: &str = "AI_RS_PASSPHRASE";
+/// Env var naming the key file.
+pub const KEY_FILE_ENV: &str = "AI_RS_KEY_FILE";
+
+/// PBKDF2-HMAC-SHA256 rounds for passphrases (OWASP 2023 recommendation).
+const PASSPHRASE_ITERATIONS: u32 = 600_000;
+/// Key files are random already and need no stretching.
+const KEY_FILE_ITERATIONS: u32 = 1;
+/// Shortest key file accepted.
+const MIN_KEY_FILE_LEN: usize = 16;
+const SALT_LEN: usize = 16;
+const KEY_LEN: usize = 32;
+const FORMAT_VERSION: u32 = 1;
+/// Binds the ciphertext to its use.
+const AAD: &[u8] = b"ai-rs credentials v1";
+
+/// What the encryption key is derived from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeySource {
+    Passphrase,
+    KeyFile,
+}
+
+/// A secret to derive the encryption key from.
+#[derive(Clone)]
+pub enum Secret {
+    Passphrase(String),
+    /// Contents of a key file.
+    KeyFile(Vec<u8>),
+}
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Secret::{:?}(..)", self.source())
+    }
+}
+
+impl Secret {
+    pub fn source(&self) -> KeySource {
+        match self {
+            Secret::Passphrase(_) => KeySource::Passphrase,
+            Secret::KeyFile(_) => KeySource::KeyFile,
+        }
+    }
+
+    fn bytes(&self) -> &[u8] {
+        match self {
+            Secret::Passphrase(p) => p.as_bytes(),
+            Secret::KeyFile(k) => k,
+        }
+    }
+
+    /// Read a key file.
+    pub fn from_key_file(path: &Path) -> anyhow::Result<Self> {
+        let key = std::fs::read(path)
+            .map_err(|e| anyhow::anyhow!("Cannot read key file {}: {}", path.display(), e))?;
+        if key.len() < MIN_KEY_FILE_LEN {
+            anyhow::bail!(
+                "Key file {} is too short; use at least {} random bytes",
+                path.display(),
+                MIN_KEY_FILE_LEN
+            );
+        }
+        Ok(Secret::KeyFile(key))
+    }
+
+    /// Write a new key file of random bytes, readable by the owner only.
+    pub fn create_key_file(path: &Path) -> anyhow::Result<Self> {
+        let mut key = vec![0u8; KEY_LEN];
+        SystemRandom::new()
+            .fill(&mut key)
+            .map_err(|_| anyhow::anyhow!("No system randomness"))?;
+
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(path)?;
+        std::io::Write::write_all(&mut file, &key)?;
+        file.sync_all()?;
+        Ok(Secret::KeyFile(key))
+    }
+
+    /// The secret named by [`KEY_FILE_ENV`] or [`PASSPHRASE_ENV`], if one is set.
+    pub fn from_env(source: KeySource) -> anyhow::Result<Option<Self>> {
+        match source {
+            KeySource::KeyFile => match std::env::var_os(KEY_FILE_ENV) {
+                Some(path) => Self::from_key_file(Path::new(&path)).map(Some),
+                None => Ok(None),
+            },
+            KeySource::Passphrase => Ok(std::env::var(PASSPHRASE_ENV).ok().map(Secret::Passphrase)),
+        }
+    }
+}
+
+/// Secret given to [`unlock`], used before the environment.
+static UNLOCKED: Mutex<Option<Secret>> = Mutex::new(None);
+
+/// Derived keys, by digest of source, secret, salt and rounds. PBKDF2 is slow on
+/// purpose and the config is read often.
+static KEY_CACHE: LazyLock<Mutex<HashMap<Vec<u8>, [u8; KEY_LEN]>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Use `secret` for encrypted credentials in this process.
+pub fn unlock(secret: Secret) {
+    *UNLOCKED.lock().unwrap() = Some(secret);
+}
+
+/// The secret to use for credentials encrypted with a key from `source`.
+pub fn secret_for(source: KeySource) -> anyhow::Result<Secret> {
+    if let Some(secret) = UNLOCKED.lock().unwrap().as_ref().filter(|s| s.source() == source) {
+        return Ok(secret.clone());
+    }
+    match Secret::from_env(source)? {
+        Some(secret) => Ok(secret),
+        None => match source {
+            KeySource::Passphrase => anyhow::bail!(
+                "Credentials are encrypted with a passphrase; set {} or unlock them",
+                PASSPHRASE_ENV
+            ),
+            KeySource::KeyFile => anyhow::bail!(
+                "Credentials are encrypted with a key file; set {} to its path",
+                KEY_FILE_ENV
+            ),
+        },
+    }
+}
+
+/// The sealed credentials section, as stored in the config file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncryptedCredentials {
+    pub version: u32,
+    pub key_source: KeySource,
+    /// PBKDF2-HMAC-SHA256 rounds.
+    pub iterations: u32,
+    /// Base64 PBKDF2 salt.
+    pub salt: String,
+    /// Base64 AES-256-GCM nonce.
+    pub nonce: String,
+    /// Base64 ciphertext and tag.
+    pub ciphertext: String,
+}
+
+impl EncryptedCredentials {
+    /// An empty envelope for a key from `secret`, with a fresh salt.
+    pub fn new(secret: &Secret) -> anyhow::Result<Self> {
+        let mut salt = [0u8; SALT_LEN];
+        SystemRandom::new()
+            .fill(&mut salt)
+            .map_err(|_| anyhow::anyhow!("No system randomness"))?;
+        Ok(Self {
+            version: FORMAT_VERSION,
+            key_source: secret.source(),
+            iterations: match secret.source() {
+                KeySource::Passphrase => PASSPHRASE_ITERATIONS,
+                KeySource::KeyFile => KEY_FILE_ITERATIONS,
+            },
+            salt: STANDARD.encode(salt),
+            nonce: String::new(),
+            ciphertext: String::new(),
+        })
+    }
+
+    /// Encrypt `plaintext` with this envelope's key parameters and a fresh nonce.
+    pub fn seal(&self, secret: &Secret, plaintext: &[u8]) -> anyhow::Result<Self> {
+        let key = self.key(secret)?;
+        let mut nonce = [0u8; NONCE_LEN];
+        SystemRandom::new()
+            .fill(&mut nonce)
+            .map_err(|_| anyhow::anyhow!("No system randomness"))?;
+
+        let mut data = plaintext.to_vec();
+        key.seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(AAD), &mut data)
+            .map_err(|_| anyhow::anyhow!("Failed to encrypt credentials"))?;
+
+        Ok(Self {
+            nonce: STANDARD.encode(nonce),
+            ciphertext: STANDARD.encode(data),
+            ..self.clone()
+        })
+    }
+
+    /// Decrypt the credentials section.
+    pub fn open(&self, secret: &Secret) -> anyhow::Result<Vec<u8>> {
+        if self.version != FORMAT_VERSION {
+            anyhow::bail!("Unsupported encrypted credentials version {}", self.version);
+        }
+        let key = self.key(secret)?;
+        let nonce: [u8; NONCE_LEN] = STANDARD
+            .decode(&self.nonce)?
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Invalid nonce in encrypted credentials"))?;
+
+        let mut data = STANDARD.decode(&self.ciphertext)?;
+        let plaintext = key
+            .open_in_place(Nonce::assume_unique_for_key(nonce), Aad::from(AAD), &mut data)
+            .map_err(|_| anyhow::anyhow!("Cannot decrypt credentials: wrong passphrase or key file"))?;
+        Ok(plaintext.to_vec())
+    }
+
+    fn key(&self, secret: &Secret) -> anyhow::Result<LessSafeKey> {
+        if secret.source() != self.key_source {
+            anyhow::bail!("Credentials are encrypted with a {:?} key, not a {:?}", self.key_source, secret.source());
+        }
+        let salt = STANDARD.decode(&self.salt)?;
+        let iterations = NonZeroU32::new(self.iterations)
+            .ok_or_else(|| anyhow::anyhow!("Invalid iterations in encrypted credentials"))?;
+
+        let mut ctx = digest::Context::new(&digest::SHA256);
+        ctx.update(&[secret.source() as u8]);
+        ctx.update(&self.iterations.to_le_bytes());
+        ctx.update(&(salt.len() as u32).to_le_bytes());
+        ctx.update(&salt);
+        ctx.update(secret.bytes());
+        let cache_key = ctx.finish().as_ref().to_vec();
+
+        let cached = KEY_CACHE.lock().unwrap().get(&cache_key).copied();
+        let key = match cached {
+            Some(k) => k,
+            None => {
+                let mut k = [0u8; KEY_LEN];
+                pbkdf2::derive(pbkdf2::PBKDF2_HMAC_SHA256, iterations, &salt, secret.bytes(), &mut k);
+                KEY_CACHE.lock().unwrap().insert(cache_key, k);
+                k
+            }
+        };
+
+        let key = UnboundKey::new(&AES_256_GCM, &key).map_err(|_| anyhow::anyhow!("Invalid key"))?;
+        Ok(LessSafeKey::new(key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::config::ConfigManager;
+    use crate::auth::{ApiKeyCredential, Credential};
+    use std::path::PathBuf;
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ai-rs-test-{}", uuid::Uuid::new_v4().simple()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// A passphrase envelope with few rounds, so tests stay fast.
+    fn quick_envelope(secret: &Secret) -> EncryptedCredentials {
+        EncryptedCredentials { iterations: 1000, ..EncryptedCredentials::new(secret).unwrap() }
+    }
+
+    #[test]
+    fn sealed_credentials_open_with_the_same_secret() {
+        for secret in [Secret::Passphrase("correct horse".into()), Secret::KeyFile(vec![7; KEY_LEN])] {
+            let sealed = quick_envelope(&secret).seal(&secret, b"{\"credentials\":{}}").unwrap();
+            assert!(!STANDARD.decode(&sealed.ciphertext).unwrap().windows(11).any(|w| w == b"credentials"));
+            assert_eq!(sealed.open(&secret).unwrap(), b"{\"credentials\":{}}");
+
+            // A fresh nonce every time
+            let again = sealed.seal(&secret, b"{\"credentials\":{}}").unwrap();
+            assert_ne!(again.nonce, sealed.nonce);
+        }
+    }
+
+    #[test]
+    fn the_wrong_secret_does_not_open_them() {
+        let secret = Secret::Passphrase("correct horse".into());
+        let sealed = quick_envelope(&secret).seal(&secret, b"secret").unwrap();
+
+        let err = sealed.open(&Secret::Passphrase("battery staple".into())).unwrap_err();
+        assert!(err.to_string().contains("wrong passphrase or key file"), "{}", err);
+        let err = sealed.open(&Secret::KeyFile(vec![7; KEY_LEN])).unwrap_err();
+        assert!(err.to_string().contains("encrypted with a Passphrase key"), "{}", err);
+
+        let mut tampered = sealed.clone();
+        let mut data = STANDARD.decode(&tampered.ciphertext).unwrap();
+        data[0] ^= 1;
+        tampered.ciphertext = STANDARD.encode(data);
+        assert!(tampered.open(&secret).is_err());
+    }
+
+    #[test]
+    fn key_files_must_be_long_enough() {
+        let dir = temp_dir();
+        let short = dir.join("short.key");
+        std::fs::write(&short, [1u8; MIN_KEY_FILE_LEN - 1]).unwrap();
+        let err = Secret::from_key_file(&short).unwrap_err();
+        assert!(err.to_string().contains(&format!("at least {} random bytes", MIN_KEY_FILE_LEN)), "{}", err);
+
+        let created = dir.join("new.key");
+        let secret = Secret::create_key_file(&created).unwrap();
+        assert_eq!(Secret::from_key_file(&created).unwrap().bytes(), secret.bytes());
+        assert!(Secret::create_key_file(&created).is_err(), "an existing key file is never overwritten");
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn rotating_the_key_re_encrypts_the_credentials() {
+        let dir = temp_dir();
+        let config = ConfigManager::new(dir.join("config.json"));
+        let credential = Credential::ApiKey(ApiKeyCredential { key: "sk-test-rotate-0123456789".into() });
+        config.set_credential("openai", credential).unwrap();
+
+        let old_key = Secret::create_key_file(&dir.join("old.key")).unwrap();
+        config.encrypt_credentials(old_key.clone()).unwrap();
+        let sealed = std::fs::read_to_string(config.path()).unwrap();
+        assert!(!sealed.contains("sk-test-rotate"));
+
+        let new_key = Secret::create_key_file(&dir.join("new.key")).unwrap();
+        config.rotate_credentials_key(new_key.clone()).unwrap();
+        let rotated = std::fs::read_to_string(config.path()).unwrap();
+        assert!(!rotated.contains("sk-test-rotate"));
+        assert_ne!(rotated, sealed);
+
+        assert!(config.unlock(old_key).is_err());
+        config.unlock(new_key).unwrap();
+        assert_eq!(config.get_credential("openai").unwrap().unwrap().api_key().as_deref(), Some("sk-test-rotate-0123456789"));
+
+        config.decrypt_credentials().unwrap();
+        assert!(std::fs::read_to_string(config.path()).unwrap().contains("sk-test-rotate"));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -1,9 +1,17 @@
 use crate::mapper::ModelMapper;
-use crate::providers::{Provider, ProviderError};
+use crate::models::catalog::default_base_url;
+use crate::models::custom::{AuthHeader, CustomProvider};
+use crate::providers::{Provider, ProviderError, redact_secrets, resolve_base_url};
+use crate::providers::github_copilot::GitHubCopilotProvider;
 use crate::providers::google_gemini_cli::GoogleGeminiCliProvider;
-use crate::providers::anthropic::AnthropicProvider;
-use crate::providers::openai::OpenAiProvider;
+use crate::providers::anthropic::{AnthropicConfig, AnthropicProvider};
+use crate::providers::ollama::{OllamaConfig, OllamaProvider};
+use crate::auth::config::{AppConfig, ConfigManager};
+use crate::providers::openai::{AzureConfig, OpenAiProvider};
+use crate::providers::openai_codex::OpenAiCodexProvider;
+use crate::providers::qwen::QwenProvider;
 use crate::providers::google::GoogleProvider;
+use crate::providers::google_vertex::GoogleVertexProvider;
 use crate::types::*;
 use futures::stream::{BoxStream, StreamExt};
 use std::sync::Arc;
@@ -14,6 +22,7 @@ use std::collections::HashMap;
 pub struct AiClient {
     mapper: ModelMapper,
     providers: HashMap<String, Arc<dyn Provider>>,
+    custom: HashMap<String, CustomProvider>,
 }
 
 impl AiClient {
@@ -37,8 +46,27 @@ impl AiClient {
             ProviderError::Other(format!("Unknown provider: {}", provider_name))
         })?;
 
+        if options.api_key.is_none() && self.requires_api_key(provider_name) {
+            return Err(ProviderError::AuthRequired(format!(
+                "API key required for {}",
+                provider_name
+            )));
+        }
+        // Collected before the key moves into a custom header of any name
+        let secrets = credential_values(options);
+        let options = self.apply_auth_header(provider_name, options);
+
+        // Fill in `{placeholder}`s such as account or region in the endpoint
+        let params = endpoint_params(
+            provider.as_ref(),
+            options.api_key.as_deref().unwrap_or_default(),
+            options.endpoint_params.clone().unwrap_or_default(),
+        );
+        let mut model_def = model_def.clone();
+        model_def.base_url = resolve_base_url(&model_def.base_url, &params)?;
+
         // Call the provider
-        let stream = provider.stream(model_def, context, options);
+        let stream = provider.stream(&model_def, context, &options);
         
         // Hook the response to add provider prefix back to the model ID
         let p_name = provider_name.to_string();
@@ -55,22 +83,147 @@ impl AiClient {
                 let short_id = message.model.clone();
                 message.model = mapper.join_id(&p_name, &short_id);
                 message.provider = p_name.clone();
+                message.error_message = message.error_message.map(|m| redact_secrets(m, &secrets));
+                for block in &mut message.content {
+                    if let ContentBlock::Text(t) = block {
+                        t.text = redact_secrets(std::mem::take(&mut t.text), &secrets);
+                    }
+                }
                 Ok(StreamEvent::Error { message })
             }
+            Err(e) => Err(e.redact(&secrets)),
             other => other,
         });
         
         Ok(Box::pin(mapped))
     }
+
+    /// List the models a provider actually serves, queried from its default endpoint.
+    /// `params` fills in placeholders in templated endpoints.
+    pub async fn list_models(
+        &self,
+        provider_id: &str,
+        api_key: &str,
+        params: &HashMap<String, String>,
+    ) -> Result<Vec<ModelDef>, ProviderError> {
+        let provider = self.providers.get(provider_id).ok_or_else(|| {
+            ProviderError::Other(format!("Unknown provider: {}", provider_id))
+        })?;
+        let base_url = self
+            .custom
+            .get(provider_id)
+            .map(|p| p.base_url.clone())
+            .or_else(|| default_base_url(provider_id))
+            .ok_or_else(|| {
+            ProviderError::Other(format!("No known endpoint for provider: {}", provider_id))
+        })?;
+
+        let params = endpoint_params(provider.as_ref(), api_key, params.clone());
+        let base_url = resolve_base_url(&base_url, &params)?;
+
+        provider
+            .list_models(provider_id, &base_url, api_key)
+            .await
+            .map_err(|e| e.redact(&[api_key.to_string()]))
+    }
+
+    /// Endpoint parameters a provider derives from the credential, such as a
+    /// service account's project.
+    pub fn credential_endpoint_params(&self, provider_id: &str, api_key: &str) -> HashMap<String, String> {
+        self.providers
+            .get(provider_id)
+            .map(|p| p.credential_endpoint_params(api_key))
+            .unwrap_or_default()
+    }
+
+    /// Whether the provider needs a credential to serve requests.
+    pub fn requires_api_key(&self, provider_id: &str) -> bool {
+        let custom_auth = self.custom.get(provider_id).and_then(|p| p.auth_header.as_ref());
+        if custom_auth == Some(&AuthHeader::None) {
+            return false;
+        }
+        self.providers
+            .get(provider_id)
+            .map(|p| p.requires_api_key())
+            .unwrap_or(true)
+    }
+
+    /// Move the credential into the header a custom provider expects.
+    fn apply_auth_header(&self, provider_id: &str, options: &StreamOptions) -> StreamOptions {
+        let mut options = options.clone();
+        let auth = match self.custom.get(provider_id).and_then(|p| p.auth_header.as_ref()) {
+            Some(a) => a,
+            None => return options,
+        };
+
+        let key = options.api_key.take();
+        let header = match auth {
+            AuthHeader::Bearer => key.map(|k| ("Authorization".to_string(), format!("Bearer {}", k))),
+            AuthHeader::Header(name) => key.map(|k| (name.clone(), k)),
+            AuthHeader::None => None,
+        };
+        if let Some((name, value)) = header {
+            options
+                .extra_headers
+                .get_or_insert_with(HashMap::new)
+                .insert(name, value);
+        }
+        options
+    }
+}
+
+/// Configured endpoint parameters over the ones the credential implies.
+fn endpoint_params(
+    provider: &dyn Provider,
+    api_key: &str,
+    configured: HashMap<String, String>,
+) -> HashMap<String, String> {
+    let mut params = provider.credential_endpoint_params(api_key);
+    params.extend(configured.into_iter().filter(|(_, v)| !v.is_empty()));
+    params
+}
+
+/// Credentials in the request options, for scrubbing them out of errors.
+fn credential_values(options: &StreamOptions) -> Vec<String> {
+    let mut secrets: Vec<String> = options.api_key.iter().cloned().collect();
+    // OAuth keys may be a JSON object carrying the access token
+    let token = options
+        .api_key
+        .as_deref()
+        .and_then(|k| serde_json::from_str::<serde_json::Value>(k).ok())
+        .and_then(|v| v.get("token").and_then(|t| t.as_str()).map(str::to_string));
+    secrets.extend(token);
+    for (name, value) in options.extra_headers.iter().flatten() {
+        let name = name.to_ascii_lowercase();
+        if name == "authorization" || name.contains("api-key") || name.contains("token") {
+            secrets.push(value.clone());
+            if let Some(token) = value.strip_prefix("Bearer ") {
+                secrets.push(token.to_string());
+            }
+        }
+    }
+    secrets
 }
 
 pub struct AiClientBuilder {
     mapper: Option<ModelMapper>,
+    custom_providers: Vec<CustomProvider>,
+    azure: AzureConfig,
+    anthropic: AnthropicConfig,
+    ollama: OllamaConfig,
+    config_manager: Option<ConfigManager>,
 }
 
 impl AiClientBuilder {
     pub fn new() -> Self {
-        Self { mapper: None }
+        Self {
+            mapper: None,
+            custom_providers: Vec::new(),
+            azure: AzureConfig::default(),
+            anthropic: AnthropicConfig::default(),
+            ollama: OllamaConfig::default(),
+            config_manager: None,
+        }
     }
 
     pub fn with_mapper(mut self, mapper: ModelMapper) -> Self {
@@ -78,6 +231,45 @@ impl AiClientBuilder {
         self
     }
 
+    /// Register user-defined providers. An entry with a built-in id replaces it.
+    pub fn with_custom_providers(mut self, providers: Vec<CustomProvider>) -> Self {
+        self.custom_providers = providers;
+        self
+    }
+
+    /// Deployment mapping and api-version for Azure OpenAI.
+    pub fn with_azure(mut self, azure: AzureConfig) -> Self {
+        self.azure = azure;
+        self
+    }
+
+    /// Anthropic settings, such as how tool names are sent with subscription tokens.
+    pub fn with_anthropic(mut self, anthropic: AnthropicConfig) -> Self {
+        self.anthropic = anthropic;
+        self
+    }
+
+    /// Ollama settings, such as keep-alive and context size.
+    pub fn with_ollama(mut self, ollama: OllamaConfig) -> Self {
+        self.ollama = ollama;
+        self
+    }
+
+    /// Let providers persist what they discover (e.g. Cloud Code Assist projects)
+    /// and refresh OAuth tokens the server rejects.
+    pub fn with_config_manager(mut self, config: ConfigManager) -> Self {
+        self.config_manager = Some(config);
+        self
+    }
+
+    /// Apply everything provider-related from the config file.
+    pub fn with_config(self, config: &AppConfig) -> Self {
+        self.with_custom_providers(config.custom_providers.clone())
+            .with_azure(config.azure.clone())
+            .with_anthropic(config.anthropic.clone())
+            .with_ollama(config.ollama.clone())
+    }
+
     pub fn build(self) -> AiClient {
         let mapper = self.mapper.unwrap_or_default();
         let mut providers = HashMap::new();
@@ -88,6 +280,7 @@ impl AiClientBuilder {
         providers.insert("deepseek".into(), openai.clone() as Arc<dyn Provider>);
         providers.insert("xai".into(), openai.clone() as Arc<dyn Provider>);
         providers.insert("groq".into(), openai.clone() as Arc<dyn Provider>);
+        providers.insert("mistral".into(), openai.clone() as Arc<dyn Provider>);
         providers.insert("together".into(), openai.clone() as Arc<dyn Provider>);
         providers.insert("siliconflow".into(), openai.clone() as Arc<dyn Provider>);
         providers.insert("zhipuai".into(), openai.clone() as Arc<dyn Provider>);
@@ -97,26 +290,166 @@ impl AiClientBuilder {
         providers.insert("minimax".into(), openai.clone() as Arc<dyn Provider>);
         providers.insert("moonshot".into(), openai.clone() as Arc<dyn Provider>);
         providers.insert("qianfan".into(), openai.clone() as Arc<dyn Provider>);
-        providers.insert("ollama".into(), openai.clone() as Arc<dyn Provider>);
         providers.insert("vllm".into(), openai.clone() as Arc<dyn Provider>);
         providers.insert("huggingface".into(), openai.clone() as Arc<dyn Provider>);
-        providers.insert("github-copilot".into(), openai.clone() as Arc<dyn Provider>);
         providers.insert("amazon-bedrock".into(), openai.clone() as Arc<dyn Provider>);
-        providers.insert("openai-codex".into(), openai.clone() as Arc<dyn Provider>);
 
-        let anthropic = Arc::new(AnthropicProvider::new());
+        providers.insert("azure-openai".into(), Arc::new(OpenAiProvider::azure(self.azure)) as Arc<dyn Provider>);
+        providers.insert("github-copilot".into(), Arc::new(GitHubCopilotProvider::new()) as Arc<dyn Provider>);
+        providers.insert("openai-codex".into(), Arc::new(OpenAiCodexProvider::new()) as Arc<dyn Provider>);
+        providers.insert("qwen".into(), Arc::new(QwenProvider::new()) as Arc<dyn Provider>);
+
+        let mut anthropic = AnthropicProvider::new().with_settings(self.anthropic);
+        if let Some(config) = &self.config_manager {
+            anthropic = anthropic.with_config(config.clone());
+        }
+        let anthropic = Arc::new(anthropic);
         providers.insert("anthropic".into(), anthropic.clone() as Arc<dyn Provider>);
         providers.insert("xiaomi".into(), anthropic.clone() as Arc<dyn Provider>);
         providers.insert("synthetic".into(), anthropic.clone() as Arc<dyn Provider>);
         providers.insert("cloudflare-ai-gateway".into(), anthropic.clone() as Arc<dyn Provider>);
 
-        providers.insert("google".into(), Arc::new(GoogleProvider::new()) as Arc<dyn Provider>);
-        providers.insert("gemini-cli".into(), Arc::new(GoogleGeminiCliProvider::new_gemini_cli()) as Arc<dyn Provider>);
-        providers.insert("antigravity".into(), Arc::new(GoogleGeminiCliProvider::new_antigravity()) as Arc<dyn Provider>);
+        let ollama = Arc::new(OllamaProvider::new().with_settings(self.ollama));
+        providers.insert("ollama".into(), ollama.clone() as Arc<dyn Provider>);
+
+        let google = Arc::new(GoogleProvider::new());
+        providers.insert("google".into(), google.clone() as Arc<dyn Provider>);
+        let vertex = Arc::new(GoogleVertexProvider::new());
+        providers.insert("vertex".into(), vertex.clone() as Arc<dyn Provider>);
+        let mut gemini_cli = GoogleGeminiCliProvider::new_gemini_cli();
+        let mut antigravity = GoogleGeminiCliProvider::new_antigravity();
+        if let Some(config) = &self.config_manager {
+            gemini_cli = gemini_cli.with_config(config.clone());
+            antigravity = antigravity.with_config(config.clone());
+        }
+        let gemini_cli = Arc::new(gemini_cli);
+        let antigravity = Arc::new(antigravity);
+        providers.insert("gemini-cli".into(), gemini_cli.clone() as Arc<dyn Provider>);
+        providers.insert("antigravity".into(), antigravity.clone() as Arc<dyn Provider>);
+
+        // User-defined providers are served by the implementation of their wire API.
+        // Overrides of built-in ones only repoint their models: the dedicated
+        // implementation (Copilot tokens, Codex accounts, ...) keeps serving them.
+        let mut custom = HashMap::new();
+        for cp in self.custom_providers {
+            if !providers.contains_key(&cp.id) {
+                let provider: Arc<dyn Provider> = match cp.api {
+                    Api::OpenaiCompletions => openai.clone(),
+                    Api::AnthropicMessages => anthropic.clone(),
+                    Api::GoogleGenerativeAi => google.clone(),
+                    Api::GoogleVertex => vertex.clone(),
+                    Api::OllamaChat => ollama.clone(),
+                    Api::GoogleGeminiCli => gemini_cli.clone(),
+                    ref other => {
+                        tracing::warn!("Custom provider {} uses unsupported API {:?}", cp.id, other);
+                        continue;
+                    }
+                };
+                providers.insert(cp.id.clone(), provider);
+            }
+            custom.insert(cp.id.clone(), cp);
+        }
 
         AiClient {
             mapper,
             providers,
+            custom,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::test_server::{FakeServer, Response};
+    use serde_json::json;
+
+    #[test]
+    fn credential_values_include_oauth_token() {
+        let options = StreamOptions {
+            api_key: Some(r#"{"projectId":"p1","token":"ya29.access-token"}"#.into()),
+            ..Default::default()
+        };
+        let secrets = credential_values(&options);
+        assert!(secrets.contains(&"ya29.access-token".to_string()));
+    }
+
+    fn model(provider: &str, base_url: &str) -> ModelDef {
+        ModelDef {
+            id: "m1".into(),
+            name: "m1".into(),
+            api: Api::OpenaiCompletions,
+            provider: provider.into(),
+            base_url: base_url.into(),
+            reasoning: false,
+            input: vec![InputModality::Text],
+            cost: ModelCost::default(),
+            context_window: 8192,
+            max_tokens: 1024,
+            headers: None,
+            compat: None,
+        }
+    }
+
+    fn empty_context() -> ChatContext {
+        ChatContext {
+            system_prompt: None,
+            messages: Vec::new(),
+            tools: Vec::new(),
+            hosted_tools: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn custom_header_key_is_redacted() {
+        // Answers with a 400 that echoes the key
+        let server = FakeServer::start(|req| {
+            let secret = req.headers.get("x-secret").cloned().unwrap_or_default();
+            Response::json(400, json!({"error": format!("bad key {}", secret)}))
+        })
+        .await;
+        let base_url = server.url.clone();
+        let client = AiClient::builder()
+            .with_custom_providers(vec![CustomProvider {
+                id: "px".into(),
+                name: None,
+                api: Api::OpenaiCompletions,
+                base_url: base_url.clone(),
+                headers: None,
+                auth_header: Some(AuthHeader::Header("x-secret".into())),
+            }])
+            .build();
+        let model = model("px", &base_url);
+        let context = empty_context();
+        let options = StreamOptions { api_key: Some("sk-custom-secret".into()), ..Default::default() };
+
+        let events: Vec<_> = client.stream("px/m1", &model, &context, &options).unwrap().collect().await;
+        let errors: Vec<String> = events.iter().filter_map(|e| e.as_ref().err()).map(|e| e.to_string()).collect();
+        assert_eq!(errors.len(), 1, "{:?}", events);
+        assert!(errors[0].contains("bad key [REDACTED]"), "{}", errors[0]);
+    }
+
+    #[tokio::test]
+    async fn overriding_a_built_in_provider_keeps_its_implementation() {
+        let server = FakeServer::start(|_| Response::json(400, json!({"error": "stop here"}))).await;
+        let client = AiClient::builder()
+            .with_custom_providers(vec![CustomProvider {
+                id: "github-copilot".into(),
+                name: None,
+                api: Api::OpenaiCompletions,
+                base_url: server.url.clone(),
+                headers: None,
+                auth_header: None,
+            }])
+            .build();
+        let options = StreamOptions { api_key: Some("tid=1;exp=2".into()), ..Default::default() };
+
+        let model = model("github-copilot", &server.url);
+        let _: Vec<_> = client.stream("github-copilot/m1", &model, &empty_context(), &options).unwrap().collect().await;
+        // Copilot's own headers show the request went through its provider
+        let request = &server.requests()[0];
+        assert_eq!(request.headers["x-initiator"], "user");
+        assert!(request.headers.contains_key("editor-version"));
+        assert_eq!(request.headers["authorization"], "Bearer tid=1;exp=2");
+    }
+}
@@ -0,0 +1,135 @@
+use super::static_models::static_models_for_provider;
+use crate::types::*;
+
+/// Metadata reported by a provider's model listing endpoint.
+///
+/// Values the endpoint does not report are left as `None` so that the
+/// static catalog (or a sensible default) can fill them in.
+#[derive(Debug, Clone, Default)]
+pub struct DiscoveredModel {
+    pub id: String,
+    pub name: Option<String>,
+    pub reasoning: Option<bool>,
+    pub input: Option<Vec<InputModality>>,
+    pub cost: Option<ModelCost>,
+    pub context_window: Option<u64>,
+    pub max_tokens: Option<u64>,
+}
+
+/// Default base URL for a provider, taken from its static catalog entries.
+pub fn default_base_url(provider: &str) -> Option<String> {
+    static_models_for_provider(provider)
+        .into_iter()
+        .next()
+        .map(|m| m.base_url)
+}
+
+/// Turn discovered models into full definitions, merging with the static catalog.
+///
+/// Curated static entries keep their capabilities and compat settings; anything
+/// the endpoint reports explicitly (context length, pricing, ...) takes precedence.
+pub fn merge_discovered(
+    provider: &str,
+    base_url: &str,
+    api: Api,
+    discovered: Vec<DiscoveredModel>,
+) -> Vec<ModelDef> {
+    let known = static_models_for_provider(provider);
+
+    discovered
+        .into_iter()
+        .map(|d| {
+            let mut def = known
+                .iter()
+                .find(|m| m.id == d.id)
+                .cloned()
+                .unwrap_or_else(|| ModelDef {
+                    id: d.id.clone(),
+                    name: d.id.clone(),
+                    api: api.clone(),
+                    provider: provider.into(),
+                    base_url: base_url.into(),
+                    reasoning: false,
+                    input: vec![InputModality::Text],
+                    cost: ModelCost::default(),
+                    context_window: 128000,
+                    max_tokens: 16384,
+                    headers: None,
+                    compat: None,
+                });
+
+            def.base_url = base_url.into();
+            if let Some(name) = d.name {
+                def.name = name;
+            }
+            if let Some(reasoning) = d.reasoning {
+                def.reasoning = def.reasoning || reasoning;
+            }
+            if let Some(input) = d.input {
+                def.input = input;
+            }
+            if let Some(cost) = d.cost {
+                def.cost = cost;
+            }
+            if let Some(ctx) = d.context_window {
+                def.context_window = ctx;
+            }
+            if let Some(max) = d.max_tokens {
+                def.max_tokens = max;
+            }
+            def.max_tokens = def.max_tokens.min(def.context_window);
+            def
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn discovered_models_merge_over_the_catalog() {
+        let discovered = vec![
+            DiscoveredModel {
+                id: "o1".into(),
+                reasoning: Some(false),
+                context_window: Some(100_000),
+                ..Default::default()
+            },
+            DiscoveredModel {
+                id: "gpt-4o".into(),
+                name: Some("GPT-4o (2024-11-20)".into()),
+                input: Some(vec![InputModality::Text, InputModality::Image]),
+                cost: Some(ModelCost { input: 2.5, output: 10.0, cache_read: 1.25, cache_write: 0.0 }),
+                ..Default::default()
+            },
+            DiscoveredModel { id: "ft:gpt-4o:team".into(), max_tokens: Some(4096), ..Default::default() },
+        ];
+        let models = merge_discovered("openai", "http://proxy.local/v1", Api::OpenaiCompletions, discovered);
+        assert!(models.iter().all(|m| m.base_url == "http://proxy.local/v1"));
+
+        // Catalog entries keep what the endpoint leaves out; a reported limit wins
+        let o1 = &models[0];
+        assert!(o1.reasoning, "reasoning from the catalog is kept");
+        assert_eq!(o1.context_window, 100_000);
+        assert_eq!(o1.max_tokens, 100_000, "clamped to the context window");
+
+        let gpt = &models[1];
+        assert_eq!(gpt.name, "GPT-4o (2024-11-20)");
+        assert_eq!(gpt.cost.input, 2.5);
+        assert_eq!(gpt.input.len(), 2);
+        assert_eq!(gpt.context_window, 128_000);
+
+        // Models the catalog does not know get generic defaults
+        let tuned = &models[2];
+        assert_eq!((tuned.name.as_str(), tuned.api.clone()), ("ft:gpt-4o:team", Api::OpenaiCompletions));
+        assert_eq!((tuned.context_window, tuned.max_tokens), (128_000, 4096));
+        assert_eq!(tuned.input, vec![InputModality::Text]);
+    }
+
+    #[test]
+    fn default_base_urls_come_from_the_catalog() {
+        assert_eq!(default_base_url("openai").as_deref(), Some("https://api.openai.com/v1"));
+        assert_eq!(default_base_url("nowhere"), None);
+    }
+}
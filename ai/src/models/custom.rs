@@ -0,0 +1,313 @@
+use super::catalog::default_base_url;
+use super::static_models::static_models_for_provider;
+use crate::types::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// ---------------------------------------------------------------------------
+// Config entries
+// ---------------------------------------------------------------------------
+
+/// How the credential is attached to requests for a custom provider.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthHeader {
+    /// `Authorization: Bearer <key>`
+    Bearer,
+    /// `<name>: <key>`, e.g. `{"header": "api-key"}`
+    Header(String),
+    /// No credential is sent (local servers).
+    None,
+}
+
+/// A user-defined provider, or an override of a built-in one with the same id.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CustomProvider {
+    /// Provider id used as the `<provider>/` prefix of model ids.
+    pub id: String,
+    /// Display name in the config TUI. Defaults to the id.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Wire format the endpoint speaks.
+    pub api: Api,
+    /// Base URL for API requests.
+    pub base_url: String,
+    /// Additional headers to send with every request.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub headers: Option<HashMap<String, String>>,
+    /// How to send the credential. Defaults to the wire API's own scheme.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_header: Option<AuthHeader>,
+}
+
+/// A user-defined model, or an override of a built-in one.
+///
+/// Unset fields keep the built-in value when overriding, and fall back to the
+/// provider's endpoint and generic defaults otherwise.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CustomModel {
+    pub provider: String,
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api: Option<Api>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input: Option<Vec<InputModality>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost: Option<ModelCost>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context_window: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub headers: Option<HashMap<String, String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compat: Option<OpenAiCompat>,
+}
+
+// ---------------------------------------------------------------------------
+// Merging
+// ---------------------------------------------------------------------------
+
+/// Point a model at its custom provider's endpoint, if one is configured.
+pub fn apply_provider(def: &mut ModelDef, providers: &[CustomProvider]) {
+    let p = match providers.iter().find(|p| p.id == def.provider) {
+        Some(p) => p,
+        None => return,
+    };
+
+    def.api = p.api.clone();
+    def.base_url = p.base_url.clone();
+    if let Some(headers) = &p.headers {
+        def.headers
+            .get_or_insert_with(HashMap::new)
+            .extend(headers.clone());
+    }
+}
+
+/// Apply custom providers and models on top of a list of model definitions.
+///
+/// Models of overridden providers are repointed at the custom endpoint, custom
+/// models overlay matching entries, and the remaining custom models are appended.
+pub fn apply_custom(
+    mut models: Vec<ModelDef>,
+    providers: &[CustomProvider],
+    custom: &[CustomModel],
+) -> Vec<ModelDef> {
+    for def in &mut models {
+        apply_provider(def, providers);
+    }
+
+    for cm in custom {
+        if let Some(def) = models
+            .iter_mut()
+            .find(|m| m.provider == cm.provider && m.id == cm.id)
+        {
+            overlay(def, cm);
+            continue;
+        }
+
+        match new_model(cm, providers) {
+            Some(def) => models.push(def),
+            None => tracing::warn!(
+                "Custom model {}/{} has no base_url and its provider is unknown",
+                cm.provider,
+                cm.id
+            ),
+        }
+    }
+
+    models
+}
+
+/// Built-in catalog for one provider with the config's customizations applied.
+pub fn models_for_provider(
+    provider: &str,
+    providers: &[CustomProvider],
+    custom: &[CustomModel],
+) -> Vec<ModelDef> {
+    apply_custom(static_models_for_provider(provider), providers, custom)
+        .into_iter()
+        .filter(|m| m.provider == provider)
+        .collect()
+}
+
+fn overlay(def: &mut ModelDef, cm: &CustomModel) {
+    if let Some(name) = &cm.name {
+        def.name = name.clone();
+    }
+    if let Some(api) = &cm.api {
+        def.api = api.clone();
+    }
+    if let Some(base_url) = &cm.base_url {
+        def.base_url = base_url.clone();
+    }
+    if let Some(reasoning) = cm.reasoning {
+        def.reasoning = reasoning;
+    }
+    if let Some(input) = &cm.input {
+        def.input = input.clone();
+    }
+    if let Some(cost) = &cm.cost {
+        def.cost = cost.clone();
+    }
+    if let Some(ctx) = cm.context_window {
+        def.context_window = ctx;
+    }
+    if let Some(max) = cm.max_tokens {
+        def.max_tokens = max;
+    }
+    if let Some(headers) = &cm.headers {
+        def.headers
+            .get_or_insert_with(HashMap::new)
+            .extend(headers.clone());
+    }
+    if let Some(compat) = &cm.compat {
+        def.compat = Some(match &def.compat {
+            Some(existing) => existing.merged_with(compat),
+            None => compat.clone(),
+        });
+    }
+}
+
+fn new_model(cm: &CustomModel, providers: &[CustomProvider]) -> Option<ModelDef> {
+    let provider = providers.iter().find(|p| p.id == cm.provider);
+
+    let base_url = cm
+        .base_url
+        .clone()
+        .or_else(|| provider.map(|p| p.base_url.clone()))
+        .or_else(|| default_base_url(&cm.provider))?;
+    let api = provider
+        .map(|p| p.api.clone())
+        .or_else(|| {
+            static_models_for_provider(&cm.provider)
+                .into_iter()
+                .next()
+                .map(|m| m.api)
+        })
+        .unwrap_or(Api::OpenaiCompletions);
+
+    let mut def = ModelDef {
+        id: cm.id.clone(),
+        name: cm.id.clone(),
+        api,
+        provider: cm.provider.clone(),
+        base_url,
+        reasoning: false,
+        input: vec![InputModality::Text],
+        cost: ModelCost::default(),
+        context_window: 128000,
+        max_tokens: 16384,
+        headers: provider.and_then(|p| p.headers.clone()),
+        compat: None,
+    };
+    overlay(&mut def, cm);
+    Some(def)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn built_in(provider: &str, id: &str) -> ModelDef {
+        ModelDef {
+            id: id.into(),
+            name: id.into(),
+            api: Api::OpenaiCompletions,
+            provider: provider.into(),
+            base_url: "https://api.example.com/v1".into(),
+            reasoning: true,
+            input: vec![InputModality::Text, InputModality::Image],
+            cost: ModelCost::default(),
+            context_window: 200_000,
+            max_tokens: 32_000,
+            headers: None,
+            compat: Some(OpenAiCompat { supports_developer_role: Some(true), ..Default::default() }),
+        }
+    }
+
+    fn custom_model(provider: &str, id: &str) -> CustomModel {
+        CustomModel {
+            provider: provider.into(),
+            id: id.into(),
+            name: None,
+            api: None,
+            base_url: None,
+            reasoning: None,
+            input: None,
+            cost: None,
+            context_window: None,
+            max_tokens: None,
+            headers: None,
+            compat: None,
+        }
+    }
+
+    fn proxy(id: &str) -> CustomProvider {
+        CustomProvider {
+            id: id.into(),
+            name: None,
+            api: Api::AnthropicMessages,
+            base_url: "http://localhost:8080".into(),
+            headers: Some(HashMap::from([("x-team".into(), "ai".into())])),
+            auth_header: None,
+        }
+    }
+
+    #[test]
+    fn provider_overrides_repoint_their_models() {
+        let models = vec![built_in("openai", "gpt-4o"), built_in("groq", "llama")];
+        let models = apply_custom(models, &[proxy("openai")], &[]);
+
+        assert_eq!(models[0].api, Api::AnthropicMessages);
+        assert_eq!(models[0].base_url, "http://localhost:8080");
+        assert_eq!(models[0].headers.as_ref().unwrap()["x-team"], "ai");
+        assert_eq!(models[1].base_url, "https://api.example.com/v1");
+    }
+
+    #[test]
+    fn custom_models_overlay_only_the_fields_they_set() {
+        let custom = CustomModel {
+            name: Some("GPT-4o (team)".into()),
+            context_window: Some(64_000),
+            compat: Some(OpenAiCompat { strict_tools: Some(true), ..Default::default() }),
+            ..custom_model("openai", "gpt-4o")
+        };
+        let models = apply_custom(vec![built_in("openai", "gpt-4o")], &[], &[custom]);
+
+        assert_eq!(models.len(), 1);
+        let def = &models[0];
+        assert_eq!(def.name, "GPT-4o (team)");
+        assert_eq!(def.context_window, 64_000);
+        assert_eq!((def.max_tokens, def.reasoning), (32_000, true));
+        let compat = def.compat.as_ref().unwrap();
+        assert_eq!((compat.supports_developer_role, compat.strict_tools), (Some(true), Some(true)));
+    }
+
+    #[test]
+    fn new_custom_models_take_their_providers_endpoint() {
+        let custom = [
+            CustomModel { max_tokens: Some(2048), ..custom_model("team", "claude-proxy") },
+            custom_model("openai", "gpt-next"),
+            custom_model("nowhere", "orphan"),
+        ];
+        let models = apply_custom(Vec::new(), &[proxy("team")], &custom);
+
+        assert_eq!(models.len(), 2, "models without an endpoint are dropped");
+        let proxied = &models[0];
+        assert_eq!((proxied.api.clone(), proxied.base_url.as_str()), (Api::AnthropicMessages, "http://localhost:8080"));
+        assert_eq!((proxied.max_tokens, proxied.context_window), (2048, 128_000));
+        assert_eq!(proxied.headers.as_ref().unwrap()["x-team"], "ai");
+
+        // Built-in providers lend their default endpoint and wire API
+        let openai = &models[1];
+        assert_eq!(openai.base_url, default_base_url("openai").unwrap());
+        assert_eq!(openai.api, Api::OpenaiCompletions);
+    }
+}
@@ -1,3 +1,5 @@
+pub mod catalog;
+pub mod custom;
 pub mod static_models;
 
 pub use static_models::*;
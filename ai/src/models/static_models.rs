@@ -1,17 +1,23 @@
 use crate::providers::anthropic::static_anthropic_models;
 use crate::providers::google_gemini_cli::{static_antigravity_models, static_gemini_cli_models};
+use crate::providers::openai_codex::static_codex_models;
+use crate::providers::qwen::DASHSCOPE_BASE_URL;
 use crate::types::*;
 
 pub fn all_static_models() -> Vec<ModelDef> {
     let mut models = Vec::new();
     models.extend(static_openai_models());
+    models.extend(static_azure_openai_models());
+    models.extend(static_codex_models());
     models.extend(static_anthropic_models());
     models.extend(static_google_models());
+    models.extend(static_vertex_models());
     models.extend(static_gemini_cli_models());
     models.extend(static_antigravity_models());
     models.extend(static_deepseek_models());
     models.extend(static_xai_models());
     models.extend(static_groq_models());
+    models.extend(static_mistral_models());
     models.extend(static_together_models());
     models.extend(static_siliconflow_models());
     models.extend(static_zhipuai_models());
@@ -22,6 +28,7 @@ pub fn all_static_models() -> Vec<ModelDef> {
     models.extend(static_xiaomi_models());
     models.extend(static_moonshot_models());
     models.extend(static_qianfan_models());
+    models.extend(static_qwen_models());
     models.extend(static_synthetic_models());
     models.extend(static_cloudflare_models());
     models.extend(static_ollama_models());
@@ -35,13 +42,17 @@ pub fn all_static_models() -> Vec<ModelDef> {
 pub fn static_models_for_provider(provider: &str) -> Vec<ModelDef> {
     match provider {
         "openai" => static_openai_models(),
+        "azure-openai" => static_azure_openai_models(),
+        "openai-codex" => static_codex_models(),
         "anthropic" => static_anthropic_models(),
         "google" => static_google_models(),
+        "vertex" => static_vertex_models(),
         "gemini-cli" => static_gemini_cli_models(),
         "antigravity" => static_antigravity_models(),
         "deepseek" => static_deepseek_models(),
         "xai" => static_xai_models(),
         "groq" => static_groq_models(),
+        "mistral" => static_mistral_models(),
         "together" => static_together_models(),
         "siliconflow" => static_siliconflow_models(),
         "zhipuai" => static_zhipuai_models(),
@@ -52,6 +63,7 @@ pub fn static_models_for_provider(provider: &str) -> Vec<ModelDef> {
         "xiaomi" => static_xiaomi_models(),
         "moonshot" => static_moonshot_models(),
         "qianfan" => static_qianfan_models(),
+        "qwen" => static_qwen_models(),
         "synthetic" => static_synthetic_models(),
         "cloudflare-ai-gateway" => static_cloudflare_models(),
         "ollama" => static_ollama_models(),
@@ -76,6 +88,7 @@ fn oai(provider: &str, base_url: &str, id: &str, name: &str, reasoning: bool, ct
         context_window: ctx,
         max_tokens: max_tok,
         headers: None,
+        compat: None,
     }
 }
 
@@ -92,6 +105,7 @@ fn ant(provider: &str, base_url: &str, id: &str, name: &str, reasoning: bool, ct
         context_window: ctx,
         max_tokens: max_tok,
         headers: None,
+        compat: None,
     }
 }
 
@@ -117,11 +131,36 @@ pub fn static_google_models() -> Vec<ModelDef> {
             api: api.clone(), provider: provider.into(), base_url: base_url.into(),
             reasoning: false, input: vec![InputModality::Text, InputModality::Image],
             cost: ModelCost::default(),
-            context_window: 1048576, max_tokens: 8192, headers: None,
+            context_window: 1048576, max_tokens: 8192, headers: None, compat: None,
         },
     ]
 }
 
+pub fn static_vertex_models() -> Vec<ModelDef> {
+    let provider = "vertex";
+    let base_url = "https://{region:us-central1}-aiplatform.googleapis.com/v1/projects/{project}/locations/{region:us-central1}";
+    let api = Api::GoogleVertex;
+    vec![
+        ModelDef {
+            id: "gemini-2.5-pro".into(),
+            name: "Gemini 2.5 Pro (Vertex)".into(),
+            api: api.clone(), provider: provider.into(), base_url: base_url.into(),
+            reasoning: true, input: vec![InputModality::Text, InputModality::Image],
+            cost: ModelCost::default(),
+            context_window: 1048576, max_tokens: 65536, headers: None, compat: None,
+        },
+        ModelDef {
+            id: "gemini-2.5-flash".into(),
+            name: "Gemini 2.5 Flash (Vertex)".into(),
+            api: api.clone(), provider: provider.into(), base_url: base_url.into(),
+            reasoning: true, input: vec![InputModality::Text, InputModality::Image],
+            cost: ModelCost::default(),
+            context_window: 1048576, max_tokens: 65536, headers: None, compat: None,
+        },
+        ant(provider, base_url, "claude-sonnet-4@20250514", "Claude Sonnet 4 (Vertex)", true, 200000, 64000),
+    ]
+}
+
 pub fn static_deepseek_models() -> Vec<ModelDef> {
     let p = "deepseek";
     let url = "https://api.deepseek.com/v1";
@@ -148,6 +187,15 @@ pub fn static_groq_models() -> Vec<ModelDef> {
     ]
 }
 
+pub fn static_mistral_models() -> Vec<ModelDef> {
+    let p = "mistral";
+    let url = "https://api.mistral.ai/v1";
+    vec![
+        oai(p, url, "mistral-large-latest", "Mistral Large", false, 131072, 8192),
+        oai(p, url, "codestral-latest", "Codestral", false, 256000, 8192),
+    ]
+}
+
 pub fn static_together_models() -> Vec<ModelDef> {
     let p = "together";
     let url = "https://api.together.xyz/v1";
@@ -229,6 +277,15 @@ pub fn static_qianfan_models() -> Vec<ModelDef> {
     ]
 }
 
+pub fn static_qwen_models() -> Vec<ModelDef> {
+    let p = "qwen";
+    let url = DASHSCOPE_BASE_URL;
+    vec![
+        oai(p, url, "qwen3-coder-plus", "Qwen3 Coder Plus", false, 1000000, 65536),
+        oai(p, url, "qwen3-coder-flash", "Qwen3 Coder Flash", false, 1000000, 65536),
+    ]
+}
+
 pub fn static_synthetic_models() -> Vec<ModelDef> {
     let p = "synthetic";
     let url = "https://api.synthetic.ai/v1"; // Placeholder
@@ -237,19 +294,42 @@ pub fn static_synthetic_models() -> Vec<ModelDef> {
     ]
 }
 
+pub fn static_azure_openai_models() -> Vec<ModelDef> {
+    let p = "azure-openai";
+    let url = "https://{resource}.openai.azure.com";
+    vec![
+        oai(p, url, "gpt-4o", "GPT-4o (Azure)", false, 128000, 16384),
+        oai(p, url, "gpt-4.1", "GPT-4.1 (Azure)", false, 1047576, 32768),
+        oai(p, url, "o4-mini", "o4-mini (Azure)", true, 200000, 100000),
+    ]
+}
+
 pub fn static_cloudflare_models() -> Vec<ModelDef> {
     let p = "cloudflare-ai-gateway";
-    let url = "https://gateway.ai.cloudflare.com/v1"; // Needs placeholders
+    let url = "https://gateway.ai.cloudflare.com/v1/{account_id}/{gateway_id}/anthropic/v1";
     vec![
-        ant(p, url, "cloudflare-model", "Cloudflare AI Gateway", false, 128000, 8192),
+        ant(p, url, "claude-3-5-sonnet-20241022", "Claude 3.5 Sonnet (Cloudflare AI Gateway)", false, 200000, 8192),
     ]
 }
 
 pub fn static_ollama_models() -> Vec<ModelDef> {
     let p = "ollama";
-    let url = "http://127.0.0.1:11434/v1";
+    let url = "http://127.0.0.1:11434";
     vec![
-        oai(p, url, "llama3", "Llama 3 (Ollama)", false, 128000, 8192),
+        ModelDef {
+            id: "llama3".into(),
+            name: "Llama 3 (Ollama)".into(),
+            api: Api::OllamaChat,
+            provider: p.into(),
+            base_url: url.into(),
+            reasoning: false,
+            input: vec![InputModality::Text],
+            cost: ModelCost::default(),
+            context_window: 8192,
+            max_tokens: 8192,
+            headers: None,
+            compat: None,
+        },
     ]
 }
 
@@ -274,12 +354,15 @@ pub fn static_copilot_models() -> Vec<ModelDef> {
     let url = "https://api.githubcopilot.com";
     vec![
         oai(p, url, "gpt-4o", "Copilot GPT-4o", false, 128000, 8192),
+        oai(p, url, "gpt-4.1", "Copilot GPT-4.1", false, 128000, 16384),
+        oai(p, url, "claude-sonnet-4", "Copilot Claude Sonnet 4", true, 128000, 16000),
+        oai(p, url, "gemini-2.5-pro", "Copilot Gemini 2.5 Pro", true, 128000, 64000),
     ]
 }
 
 pub fn static_bedrock_models() -> Vec<ModelDef> {
     let p = "amazon-bedrock";
-    let url = "https://bedrock-runtime.us-east-1.amazonaws.com";
+    let url = "https://bedrock-runtime.{region:us-east-1}.amazonaws.com";
     vec![
         oai(p, url, "anthropic.claude-3-5-sonnet-20241022-v2:0", "Bedrock Claude 3.5 Sonnet", false, 200000, 8192),
     ]
@@ -1,4 +1,5 @@
 use super::*;
+use crate::oauth::loopback::{self, LoopbackServer};
 use crate::oauth::pkce::generate_pkce;
 use async_trait::async_trait;
 use base64::{Engine, engine::general_purpose::STANDARD};
@@ -8,7 +9,9 @@ use std::collections::HashMap;
 const CLIENT_ID: &str = "OWQxYzI1MGEtZTYxYi00NGQ5LTg4ZWQtNTk0NGQxOTYyZjVl";
 const AUTHORIZE_URL: &str = "https://claude.ai/oauth/authorize";
 const TOKEN_URL: &str = "https://console.anthropic.com/v1/oauth/token";
-const REDIRECT_URI: &str = "https://console.anthropic.com/oauth/code/callback";
+/// Page that shows the code to paste, when no loopback server can be reached.
+const MANUAL_REDIRECT_URI: &str = "https://console.anthropic.com/oauth/code/callback";
+const CALLBACK_PATH: &str = "/callback";
 const SCOPES: &str = "org:create_api_key user:profile user:inference";
 
 fn decode_client_id() -> String {
@@ -32,15 +35,28 @@ impl OAuthProvider for AnthropicOAuthProvider {
         let client_id = decode_client_id();
         let pkce = generate_pkce();
 
+        let state = pkce.verifier.clone();
+
+        // Remote sessions get the code shown on Anthropic's page to paste instead
+        let server = if loopback::is_remote_session() {
+            None
+        } else {
+            LoopbackServer::bind(0, CALLBACK_PATH).await.ok()
+        };
+        let redirect_uri = server
+            .as_ref()
+            .map(|s| s.redirect_uri())
+            .unwrap_or_else(|| MANUAL_REDIRECT_URI.to_string());
+
         let params = [
             ("code", "true"),
             ("client_id", &client_id),
             ("response_type", "code"),
-            ("redirect_uri", REDIRECT_URI),
+            ("redirect_uri", &redirect_uri),
             ("scope", SCOPES),
             ("code_challenge", &pkce.challenge),
             ("code_challenge_method", "S256"),
-            ("state", &pkce.verifier),
+            ("state", &state),
         ];
 
         let auth_url = format!(
@@ -49,21 +65,28 @@ impl OAuthProvider for AnthropicOAuthProvider {
             serde_urlencoded::to_string(&params)?
         );
 
+        let instructions = if server.is_some() {
+            let _ = open::that(&auth_url);
+            "Complete sign-in in your browser. If it cannot reach this machine, paste the URL it was sent to."
+        } else {
+            "Complete sign-in in your browser, then paste the authorization code."
+        };
         callbacks.on_auth(OAuthAuthInfo {
             url: auth_url,
-            instructions: Some("Complete sign-in in your browser, then paste the authorization code.".into()),
+            instructions: Some(instructions.into()),
         });
 
-        let auth_code = callbacks
-            .on_prompt(OAuthPrompt {
+        let code = loopback::receive_code(
+            callbacks,
+            server.as_ref(),
+            &state,
+            OAuthPrompt {
                 message: "Paste the authorization code (format: code#state):".into(),
                 placeholder: None,
-            })
-            .await?;
-
-        let splits: Vec<&str> = auth_code.split('#').collect();
-        let code = splits.first().unwrap_or(&"").to_string();
-        let state = splits.get(1).unwrap_or(&"").to_string();
+            },
+            loopback::CALLBACK_TIMEOUT,
+        )
+        .await?;
 
         callbacks.on_progress("Exchanging authorization code for tokens...");
 
@@ -75,7 +98,7 @@ impl OAuthProvider for AnthropicOAuthProvider {
                 "client_id": client_id,
                 "code": code,
                 "state": state,
-                "redirect_uri": REDIRECT_URI,
+                "redirect_uri": redirect_uri,
                 "code_verifier": pkce.verifier,
             }))
             .send()
@@ -0,0 +1,314 @@
+//! OAuth 2.0 device authorization grant (RFC 8628).
+//!
+//! The user opens a verification page on any device and enters a short code,
+//! while this side polls the token endpoint until they have approved. Providers
+//! describe their endpoints with a [`DeviceFlow`] and get the raw token response
+//! back from [`DeviceFlow::run`].
+
+use super::pkce::PkceChallenge;
+use super::{DeviceCodeInfo, OAuthCallbacks};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use std::time::{Duration, Instant};
+
+const DEVICE_CODE_GRANT: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+/// Polling interval when the server names none (RFC 8628 §3.2).
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(5);
+/// Added to the interval on each `slow_down` (RFC 8628 §3.5).
+const SLOW_DOWN_STEP: Duration = Duration::from_secs(5);
+/// Lifetime of the device code when the server names none.
+const DEFAULT_EXPIRY: Duration = Duration::from_secs(15 * 60);
+
+#[derive(Deserialize)]
+struct DeviceAuthorization {
+    device_code: String,
+    user_code: String,
+    /// Google calls it `verification_url`.
+    #[serde(alias = "verification_url")]
+    verification_uri: String,
+    #[serde(default)]
+    verification_uri_complete: Option<String>,
+    #[serde(default)]
+    interval: Option<u64>,
+    #[serde(default)]
+    expires_in: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct PollError {
+    error: String,
+    #[serde(default)]
+    error_description: Option<String>,
+    /// GitHub names the new interval on `slow_down`.
+    #[serde(default)]
+    interval: Option<u64>,
+}
+
+/// A provider's device authorization endpoints and client.
+pub struct DeviceFlow {
+    device_url: String,
+    token_url: String,
+    client_id: String,
+    scope: Option<String>,
+    /// Extra form fields for the device authorization request.
+    device_params: Vec<(String, String)>,
+    /// Extra form fields for each token request.
+    token_params: Vec<(String, String)>,
+}
+
+impl DeviceFlow {
+    pub fn new(device_url: &str, token_url: &str, client_id: &str) -> Self {
+        Self {
+            device_url: device_url.into(),
+            token_url: token_url.into(),
+            client_id: client_id.into(),
+            scope: None,
+            device_params: Vec::new(),
+            token_params: Vec::new(),
+        }
+    }
+
+    pub fn scope(mut self, scope: &str) -> Self {
+        self.scope = Some(scope.into());
+        self
+    }
+
+    /// Bind the device code to a PKCE verifier, for servers that require one.
+    pub fn pkce(mut self, pkce: &PkceChallenge) -> Self {
+        self.device_params.push(("code_challenge".into(), pkce.challenge.clone()));
+        self.device_params.push(("code_challenge_method".into(), "S256".into()));
+        self.token_params.push(("code_verifier".into(), pkce.verifier.clone()));
+        self
+    }
+
+    /// Run the flow: request a device code, show it through
+    /// `callbacks.on_device_code`, and poll until the user approves.
+    ///
+    /// Returns the token endpoint's success response. Fails when the user denies
+    /// access, the code expires, or `callbacks.is_cancelled()` turns true; drop
+    /// the future to cancel as well.
+    pub async fn run<T: DeserializeOwned>(
+        &self,
+        client: &reqwest::Client,
+        callbacks: &dyn OAuthCallbacks,
+    ) -> anyhow::Result<T> {
+        let mut form = vec![("client_id", self.client_id.as_str())];
+        if let Some(scope) = &self.scope {
+            form.push(("scope", scope));
+        }
+        form.extend(self.device_params.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+
+        let resp = client
+            .post(&self.device_url)
+            .header("Accept", "application/json")
+            .form(&form)
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            anyhow::bail!("Device authorization failed ({}): {}", resp.status(), resp.text().await.unwrap_or_default());
+        }
+        let device: DeviceAuthorization = resp.json().await?;
+
+        let expires_in = device.expires_in.map(Duration::from_secs).unwrap_or(DEFAULT_EXPIRY);
+        callbacks.on_device_code(DeviceCodeInfo {
+            user_code: device.user_code,
+            verification_uri: device.verification_uri,
+            verification_uri_complete: device.verification_uri_complete,
+            expires_in,
+        });
+        callbacks.on_progress("Waiting for authorization in browser...");
+
+        let mut form = vec![
+            ("grant_type", DEVICE_CODE_GRANT),
+            ("client_id", self.client_id.as_str()),
+            ("device_code", device.device_code.as_str()),
+        ];
+        form.extend(self.token_params.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+
+        let mut interval = device.interval.map(Duration::from_secs).unwrap_or(DEFAULT_INTERVAL);
+        let deadline = Instant::now() + expires_in;
+
+        loop {
+            tokio::time::sleep(interval).await;
+            if callbacks.is_cancelled() {
+                anyhow::bail!("Login cancelled");
+            }
+            if Instant::now() >= deadline {
+                anyhow::bail!("The device code expired before authorization completed");
+            }
+
+            let resp = client
+                .post(&self.token_url)
+                .header("Accept", "application/json")
+                .form(&form)
+                .send()
+                .await?;
+            let status = resp.status();
+            let body = resp.text().await?;
+
+            // Some servers (GitHub) report pending authorization with a 200
+            let error = serde_json::from_str::<PollError>(&body).ok();
+            let error = match error {
+                Some(e) => e,
+                None if status.is_success() => return Ok(serde_json::from_str(&body)?),
+                None => anyhow::bail!("Token request failed ({}): {}", status, body),
+            };
+
+            match error.error.as_str() {
+                "authorization_pending" => {}
+                "slow_down" => interval = slowed_down(interval, error.interval),
+                "access_denied" => anyhow::bail!("Authorization was denied"),
+                "expired_token" => anyhow::bail!("The device code expired before authorization completed"),
+                other => anyhow::bail!(
+                    "Authorization failed: {} {}",
+                    other,
+                    error.error_description.unwrap_or_default()
+                ),
+            }
+        }
+    }
+}
+
+/// Polling interval after a `slow_down`: the one the server names, if longer,
+/// or the current one plus [`SLOW_DOWN_STEP`].
+fn slowed_down(interval: Duration, named_secs: Option<u64>) -> Duration {
+    match named_secs {
+        Some(secs) => Duration::from_secs(secs).max(interval),
+        None => interval + SLOW_DOWN_STEP,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::oauth::{OAuthAuthInfo, OAuthPrompt};
+    use crate::providers::test_server::{FakeServer, Request, Response};
+    use serde_json::json;
+    use std::sync::Mutex;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    #[derive(Default)]
+    struct Callbacks {
+        codes: Mutex<Vec<DeviceCodeInfo>>,
+        cancelled: AtomicBool,
+    }
+
+    #[async_trait::async_trait]
+    impl OAuthCallbacks for Callbacks {
+        fn on_auth(&self, _info: OAuthAuthInfo) {}
+        async fn on_prompt(&self, _prompt: OAuthPrompt) -> anyhow::Result<String> {
+            anyhow::bail!("no prompts in a device flow")
+        }
+        fn on_progress(&self, _message: &str) {}
+        fn on_device_code(&self, info: DeviceCodeInfo) {
+            self.codes.lock().unwrap().push(info);
+        }
+        fn is_cancelled(&self) -> bool {
+            self.cancelled.load(Ordering::SeqCst)
+        }
+    }
+
+    #[derive(Deserialize, Debug)]
+    struct Token {
+        access_token: String,
+    }
+
+    /// A device endpoint handing out `dev-1` with `expires_in`, then the token
+    /// endpoint's answers in turn; the last one repeats.
+    async fn server(expires_in: u64, polls: Vec<Response>) -> FakeServer {
+        let polls: Vec<_> = polls.into_iter().map(|r| (r.status, r.body)).collect();
+        let count = AtomicUsize::new(0);
+        FakeServer::start(move |req: &Request| {
+            if req.target == "/device" {
+                return Response::json(200, json!({
+                    "device_code": "dev-1",
+                    "user_code": "ABCD-1234",
+                    "verification_url": "https://example.com/device",
+                    "interval": 0,
+                    "expires_in": expires_in,
+                }));
+            }
+            let (status, body) = &polls[count.fetch_add(1, Ordering::SeqCst).min(polls.len() - 1)];
+            Response { status: *status, headers: vec![("content-type".into(), "application/json".into())], body: body.clone() }
+        })
+        .await
+    }
+
+    async fn run(server: &FakeServer, callbacks: &Callbacks) -> anyhow::Result<Token> {
+        DeviceFlow::new(&format!("{}/device", server.url), &format!("{}/token", server.url), "client-1")
+            .scope("read")
+            .run(&reqwest::Client::new(), callbacks)
+            .await
+    }
+
+    fn poll_error(status: u16, error: &str) -> Response {
+        Response::json(status, json!({"error": error}))
+    }
+
+    #[tokio::test]
+    async fn polls_while_pending_until_the_token_arrives() {
+        let server = server(900, vec![
+            poll_error(400, "authorization_pending"),
+            // GitHub answers pending with a 200
+            poll_error(200, "authorization_pending"),
+            Response::json(200, json!({"access_token": "tok", "token_type": "bearer"})),
+        ])
+        .await;
+        let callbacks = Callbacks::default();
+
+        assert_eq!(run(&server, &callbacks).await.unwrap().access_token, "tok");
+        let codes = callbacks.codes.lock().unwrap();
+        assert_eq!(codes[0].user_code, "ABCD-1234");
+        assert_eq!(codes[0].verification_uri, "https://example.com/device");
+        assert_eq!(codes[0].expires_in, Duration::from_secs(900));
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 4);
+        assert_eq!(requests[0].body, "client_id=client-1&scope=read");
+        assert_eq!(
+            requests[1].body,
+            "grant_type=urn%3Aietf%3Aparams%3Aoauth%3Agrant-type%3Adevice_code&client_id=client-1&device_code=dev-1"
+        );
+    }
+
+    #[tokio::test]
+    async fn slow_down_lengthens_the_interval() {
+        assert_eq!(slowed_down(Duration::ZERO, None), SLOW_DOWN_STEP);
+        assert_eq!(slowed_down(Duration::from_secs(5), Some(8)), Duration::from_secs(8));
+        assert_eq!(slowed_down(Duration::from_secs(5), Some(2)), Duration::from_secs(5));
+
+        let server = server(900, vec![
+            Response::json(400, json!({"error": "slow_down", "interval": 1})),
+            Response::json(200, json!({"access_token": "tok"})),
+        ])
+        .await;
+        let started = Instant::now();
+        assert_eq!(run(&server, &Callbacks::default()).await.unwrap().access_token, "tok");
+        assert!(started.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn denial_expiry_and_cancellation_end_the_flow() {
+        let denied = server(900, vec![poll_error(400, "access_denied")]).await;
+        let err = run(&denied, &Callbacks::default()).await.unwrap_err();
+        assert_eq!(err.to_string(), "Authorization was denied");
+
+        let expired = server(900, vec![poll_error(400, "expired_token")]).await;
+        let err = run(&expired, &Callbacks::default()).await.unwrap_err();
+        assert!(err.to_string().contains("expired"), "{}", err);
+
+        // A code that outlives no poll is given up on without asking
+        let short_lived = server(0, vec![poll_error(400, "authorization_pending")]).await;
+        let err = run(&short_lived, &Callbacks::default()).await.unwrap_err();
+        assert!(err.to_string().contains("expired"), "{}", err);
+        assert_eq!(short_lived.requests().len(), 1);
+
+        let pending = server(900, vec![poll_error(400, "authorization_pending")]).await;
+        let callbacks = Callbacks::default();
+        callbacks.cancelled.store(true, Ordering::SeqCst);
+        let err = run(&pending, &callbacks).await.unwrap_err();
+        assert_eq!(err.to_string(), "Login cancelled");
+        assert_eq!(pending.requests().len(), 1);
+    }
+}
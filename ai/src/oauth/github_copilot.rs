@@ -1,9 +1,43 @@
 use super::*;
+use crate::oauth::device::DeviceFlow;
+use crate::providers::github_copilot::copilot_headers;
 use async_trait::async_trait;
 use serde::Deserialize;
 use std::collections::HashMap;
 
 const CLIENT_ID: &str = "Iv1.b507a08c87ecfe98";
+const DEVICE_CODE_URL: &str = "https://github.com/login/device/code";
+const TOKEN_URL: &str = "https://github.com/login/oauth/access_token";
+
+/// Exchange a GitHub token for a Copilot token and the account's API endpoint.
+async fn copilot_token(client: &reqwest::Client, github_token: &str) -> anyhow::Result<OAuthCredentials> {
+    let mut req = client.get("https://api.github.com/copilot_internal/v2/token").bearer_auth(github_token);
+    for (k, v) in copilot_headers() {
+        req = req.header(k, v);
+    }
+    let resp = req.send().await?;
+    if !resp.status().is_success() {
+        anyhow::bail!("Copilot token exchange failed ({}): {}", resp.status(), resp.text().await.unwrap_or_default());
+    }
+
+    #[derive(Deserialize)]
+    struct Endpoints { api: Option<String> }
+    #[derive(Deserialize)]
+    struct CopilotToken { token: String, expires_at: i64, endpoints: Option<Endpoints> }
+    let cp: CopilotToken = resp.json().await?;
+
+    let mut extra = HashMap::new();
+    if let Some(api) = cp.endpoints.and_then(|e| e.api) {
+        extra.insert("apiEndpoint".to_string(), serde_json::Value::String(api));
+    }
+
+    Ok(OAuthCredentials {
+        refresh: github_token.to_string(), // GitHub token acts as refresh token
+        access: cp.token,
+        expires: cp.expires_at * 1000 - 300000,
+        extra,
+    })
+}
 
 pub struct GitHubCopilotOAuthProvider;
 
@@ -14,96 +48,27 @@ impl OAuthProvider for GitHubCopilotOAuthProvider {
 
     async fn login(&self, callbacks: &dyn OAuthCallbacks) -> anyhow::Result<OAuthCredentials> {
         let client = reqwest::Client::new();
-        
-        // 1. Request device code
-        let resp = client.post("https://github.com/login/device/code")
-            .header("Accept", "application/json")
-            .json(&serde_json::json!({
-                "client_id": CLIENT_ID,
-                "scope": "read:user"
-            }))
-            .send().await?;
-            
-        #[derive(Deserialize)]
-        struct DeviceResp { device_code: String, user_code: String, verification_uri: String, interval: u64, expires_in: u64 }
-        let device: DeviceResp = resp.json().await?;
-
-        callbacks.on_auth(OAuthAuthInfo {
-            url: device.verification_uri.clone(),
-            instructions: Some(format!("Enter code: {}", device.user_code)),
-        });
-
-        // 2. Poll for token
-        callbacks.on_progress("Waiting for authorization in browser...");
-        let mut interval = tokio::time::interval(std::time::Duration::from_secs(device.interval + 1));
-        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(device.expires_in);
 
-        while std::time::Instant::now() < deadline {
-            interval.tick().await;
-            
-            let resp = client.post("https://github.com/login/oauth/access_token")
-                .header("Accept", "application/json")
-                .json(&serde_json::json!({
-                    "client_id": CLIENT_ID,
-                    "device_code": device.device_code,
-                    "grant_type": "urn:ietf:params:oauth:grant-type:device_code"
-                }))
-                .send().await?;
-
-            #[derive(Deserialize)]
-            struct TokenResp { access_token: Option<String>, error: Option<String> }
-            let token_resp: TokenResp = resp.json().await?;
-
-            if let Some(access) = token_resp.access_token {
-                // Get real Copilot token
-                callbacks.on_progress("Exchanging GitHub token for Copilot token...");
-                let copilot_resp = client.get("https://api.github.com/copilot_internal/v2/token")
-                    .bearer_auth(&access)
-                    .header("User-Agent", "GitHubCopilotChat/0.35.0")
-                    .send().await?;
-                
-                #[derive(Deserialize)]
-                struct CopilotToken { token: String, expires_at: i64 }
-                let cp: CopilotToken = copilot_resp.json().await?;
-
-                return Ok(OAuthCredentials {
-                    refresh: access, // GitHub token acts as refresh token
-                    access: cp.token,
-                    expires: cp.expires_at * 1000 - 300000,
-                    extra: HashMap::new(),
-                });
-            }
-
-            if let Some(err) = token_resp.error {
-                if err != "authorization_pending" && err != "slow_down" {
-                    anyhow::bail!("GitHub error: {}", err);
-                }
-            }
-        }
+        #[derive(Deserialize)]
+        struct TokenResp { access_token: String }
+        let token: TokenResp = DeviceFlow::new(DEVICE_CODE_URL, TOKEN_URL, CLIENT_ID)
+            .scope("read:user")
+            .run(&client, callbacks)
+            .await?;
 
-        anyhow::bail!("Device flow timed out")
+        // Get real Copilot token
+        callbacks.on_progress("Exchanging GitHub token for Copilot token...");
+        copilot_token(&client, &token.access_token).await
     }
 
     async fn refresh_token(&self, credentials: &OAuthCredentials) -> anyhow::Result<OAuthCredentials> {
-        let client = reqwest::Client::new();
-        let resp = client.get("https://api.github.com/copilot_internal/v2/token")
-            .bearer_auth(&credentials.refresh)
-            .header("User-Agent", "GitHubCopilotChat/0.35.0")
-            .send().await?;
-        
-        #[derive(Deserialize)]
-        struct CopilotToken { token: String, expires_at: i64 }
-        let cp: CopilotToken = resp.json().await?;
-
-        Ok(OAuthCredentials {
-            refresh: credentials.refresh.clone(),
-            access: cp.token,
-            expires: cp.expires_at * 1000 - 300000,
-            extra: HashMap::new(),
-        })
+        copilot_token(&reqwest::Client::new(), &credentials.refresh).await
     }
 
     fn get_api_key(&self, credentials: &OAuthCredentials) -> String {
-        credentials.access.clone()
+        match credentials.extra.get("apiEndpoint").and_then(|v| v.as_str()) {
+            Some(endpoint) => serde_json::json!({ "token": credentials.access, "endpoint": endpoint }).to_string(),
+            None => credentials.access.clone(),
+        }
     }
 }
@@ -1,4 +1,5 @@
 use super::*;
+use crate::oauth::loopback::{self, LoopbackServer};
 use crate::oauth::pkce::generate_pkce;
 use async_trait::async_trait;
 use base64::{Engine, engine::general_purpose::STANDARD};
@@ -23,7 +24,9 @@ fn get_client_secret() -> String {
         .collect::<Vec<u8>>();
     String::from_utf8(bytes).unwrap_or_default()
 }
-const REDIRECT_URI: &str = "http://localhost:51121/oauth-callback";
+/// Preferred callback port; Google accepts any loopback port if it is taken.
+const CALLBACK_PORT: u16 = 51121;
+const CALLBACK_PATH: &str = "/oauth-callback";
 const SCOPES: &[&str] = &[
     "https://www.googleapis.com/auth/cloud-platform",
     "https://www.googleapis.com/auth/userinfo.email",
@@ -56,41 +59,46 @@ impl OAuthProvider for AntigravityOAuthProvider {
         let client_id = get_client_id();
         let pkce = generate_pkce();
 
+        let state = pkce.verifier.clone();
+
+        let server = LoopbackServer::bind_preferred(CALLBACK_PORT, CALLBACK_PATH).await?;
+        let redirect_uri = server.redirect_uri();
+
         let scopes = SCOPES.join(" ");
         let params = [
             ("client_id", client_id.as_str()),
             ("response_type", "code"),
-            ("redirect_uri", REDIRECT_URI),
+            ("redirect_uri", &redirect_uri),
             ("scope", &scopes),
             ("code_challenge", &pkce.challenge),
             ("code_challenge_method", "S256"),
-            ("state", &pkce.verifier),
+            ("state", &state),
             ("access_type", "offline"),
             ("prompt", "consent"),
         ];
 
         let auth_url = format!("{}?{}", AUTH_URL, serde_urlencoded::to_string(&params)?);
 
-        let _ = open::that(&auth_url);
+        if !loopback::is_remote_session() {
+            let _ = open::that(&auth_url);
+        }
 
         callbacks.on_auth(OAuthAuthInfo {
             url: auth_url,
-            instructions: Some("Complete the sign-in in your browser.".into()),
+            instructions: Some("Complete the sign-in in your browser. If it cannot reach this machine, paste the URL it was sent to.".into()),
         });
 
-        let redirect_url = callbacks
-            .on_prompt(OAuthPrompt {
+        let code = loopback::receive_code(
+            callbacks,
+            Some(&server),
+            &state,
+            OAuthPrompt {
                 message: "Paste the redirect URL from your browser:".into(),
-                placeholder: Some("http://localhost:51121/oauth-callback?code=...&state=...".into()),
-            })
-            .await?;
-
-        let parsed = url::Url::parse(&redirect_url)?;
-        let code = parsed
-            .query_pairs()
-            .find(|(k, _)| k == "code")
-            .map(|(_, v)| v.to_string())
-            .ok_or_else(|| anyhow::anyhow!("No authorization code in redirect URL"))?;
+                placeholder: Some(format!("{}?code=...&state=...", redirect_uri)),
+            },
+            loopback::CALLBACK_TIMEOUT,
+        )
+        .await?;
 
         callbacks.on_progress("Exchanging authorization code for tokens...");
 
@@ -103,7 +111,7 @@ impl OAuthProvider for AntigravityOAuthProvider {
                 ("client_secret", client_secret.as_str()),
                 ("code", &code),
                 ("grant_type", "authorization_code"),
-                ("redirect_uri", REDIRECT_URI),
+                ("redirect_uri", &redirect_uri),
                 ("code_verifier", &pkce.verifier),
             ])
             .send()
@@ -147,13 +155,6 @@ impl OAuthProvider for AntigravityOAuthProvider {
         &self,
         credentials: &OAuthCredentials,
     ) -> anyhow::Result<OAuthCredentials> {
-        let project_id = credentials
-            .extra
-            .get("projectId")
-            .and_then(|v| v.as_str())
-            .ok_or_else(|| anyhow::anyhow!("Missing projectId in credentials"))?
-            .to_string();
-
         let client_id = get_client_id();
         let client_secret = get_client_secret();
         let client = reqwest::Client::new();
@@ -185,16 +186,14 @@ impl OAuthProvider for AntigravityOAuthProvider {
         let expires =
             chrono::Utc::now().timestamp_millis() + data.expires_in * 1000 - 5 * 60 * 1000;
 
-        let mut extra = HashMap::new();
-        extra.insert("projectId".into(), serde_json::json!(project_id));
-
+        // The project is optional; the provider discovers it when missing
         Ok(OAuthCredentials {
             refresh: data
                 .refresh_token
                 .unwrap_or_else(|| credentials.refresh.clone()),
             access: data.access_token,
             expires,
-            extra,
+            extra: credentials.extra.clone(),
         })
     }
 
@@ -1,5 +1,7 @@
 use super::*;
+use crate::oauth::loopback::{self, LoopbackServer};
 use crate::oauth::pkce::generate_pkce;
+use crate::providers::code_assist::{self, CodeAssistSetup};
 use async_trait::async_trait;
 use base64::{Engine, engine::general_purpose::STANDARD};
 use serde::Deserialize;
@@ -24,7 +26,9 @@ fn get_client_secret() -> String {
     String::from_utf8(bytes).unwrap_or_default()
 }
 
+/// Page that shows the code to paste, when no loopback server can be reached.
 const REDIRECT_URI_OOB: &str = "https://codeassist.google.com/authcode";
+const CALLBACK_PATH: &str = "/oauth2callback";
 const SCOPES: &[&str] = &[
     "https://www.googleapis.com/auth/cloud-platform",
     "https://www.googleapis.com/auth/userinfo.email",
@@ -32,7 +36,6 @@ const SCOPES: &[&str] = &[
 ];
 const AUTH_URL: &str = "https://accounts.google.com/o/oauth2/v2/auth";
 const TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
-const CODE_ASSIST_ENDPOINT: &str = "https://cloudcode-pa.googleapis.com";
 
 /// Google Gemini CLI OAuth provider (Cloud Code Assist).
 pub struct GeminiCliOAuthProvider;
@@ -51,14 +54,28 @@ impl OAuthProvider for GeminiCliOAuthProvider {
         let client_id = get_client_id();
         let pkce = generate_pkce();
 
+        let state = uuid::Uuid::new_v4().simple().to_string();
+
+        // Remote sessions get the code shown on Google's page to paste instead
+        let server = if loopback::is_remote_session() {
+            None
+        } else {
+            LoopbackServer::bind(0, CALLBACK_PATH).await.ok()
+        };
+        let redirect_uri = server
+            .as_ref()
+            .map(|s| s.redirect_uri())
+            .unwrap_or_else(|| REDIRECT_URI_OOB.to_string());
+
         let scopes = SCOPES.join(" ");
         let params = [
             ("client_id", client_id.as_str()),
             ("response_type", "code"),
-            ("redirect_uri", REDIRECT_URI_OOB),
+            ("redirect_uri", &redirect_uri),
             ("scope", &scopes),
             ("code_challenge", &pkce.challenge),
             ("code_challenge_method", "S256"),
+            ("state", &state),
             ("access_type", "offline"),
             ("prompt", "consent"),
         ];
@@ -67,17 +84,27 @@ impl OAuthProvider for GeminiCliOAuthProvider {
 
         let _ = open::that(&auth_url);
 
+        let instructions = if server.is_some() {
+            "Authorization page opened in your browser. If not, visit the URL below. If the browser cannot reach this machine, paste the URL it was sent to."
+        } else {
+            "Authorization page opened in your browser. If not, visit the URL below. Paste the code from the success page into the input box."
+        };
         callbacks.on_auth(OAuthAuthInfo {
             url: auth_url,
-            instructions: Some("Authorization page opened in your browser. If not, visit the URL below. Paste the code from the success page into the input box.".into()),
+            instructions: Some(instructions.into()),
         });
 
-        let code = callbacks
-            .on_prompt(OAuthPrompt {
+        let code = loopback::receive_code(
+            callbacks,
+            server.as_ref(),
+            &state,
+            OAuthPrompt {
                 message: "Enter authorization code:".into(),
                 placeholder: None,
-            })
-            .await?;
+            },
+            loopback::CALLBACK_TIMEOUT,
+        )
+        .await?;
 
         callbacks.on_progress("Exchanging code for tokens...");
 
@@ -90,7 +117,7 @@ impl OAuthProvider for GeminiCliOAuthProvider {
                 ("client_secret", client_secret.as_str()),
                 ("code", &code),
                 ("grant_type", "authorization_code"),
-                ("redirect_uri", REDIRECT_URI_OOB),
+                ("redirect_uri", &redirect_uri),
                 ("code_verifier", &pkce.verifier),
             ])
             .send()
@@ -113,12 +140,15 @@ impl OAuthProvider for GeminiCliOAuthProvider {
             .ok_or_else(|| anyhow::anyhow!("No refresh token received"))?;
 
         callbacks.on_progress("Discovering project...");
-        let project_id = discover_project(&token.access_token, callbacks).await?;
+        let setup = discover_project(&token.access_token, callbacks).await?;
 
         let expires = chrono::Utc::now().timestamp_millis() + token.expires_in * 1000 - 5 * 60 * 1000;
 
         let mut extra = HashMap::new();
-        extra.insert("projectId".into(), serde_json::json!(project_id));
+        extra.insert("projectId".into(), serde_json::json!(setup.project_id));
+        if let Some(tier) = setup.tier {
+            extra.insert("tier".into(), serde_json::json!(tier.id));
+        }
 
         Ok(OAuthCredentials {
             refresh,
@@ -129,13 +159,6 @@ impl OAuthProvider for GeminiCliOAuthProvider {
     }
 
     async fn refresh_token(&self, credentials: &OAuthCredentials) -> anyhow::Result<OAuthCredentials> {
-        let project_id = credentials
-            .extra
-            .get("projectId")
-            .and_then(|v| v.as_str())
-            .ok_or_else(|| anyhow::anyhow!("Missing projectId"))?
-            .to_string();
-
         let client_id = get_client_id();
         let client_secret = get_client_secret();
         let client = reqwest::Client::new();
@@ -166,14 +189,12 @@ impl OAuthProvider for GeminiCliOAuthProvider {
         let data: RefreshResp = resp.json().await?;
         let expires = chrono::Utc::now().timestamp_millis() + data.expires_in * 1000 - 5 * 60 * 1000;
 
-        let mut extra = HashMap::new();
-        extra.insert("projectId".into(), serde_json::json!(project_id));
-
+        // The project is optional; the provider discovers it when missing
         Ok(OAuthCredentials {
             refresh: data.refresh_token.unwrap_or_else(|| credentials.refresh.clone()),
             access: data.access_token,
             expires,
-            extra,
+            extra: credentials.extra.clone(),
         })
     }
 
@@ -183,47 +204,17 @@ impl OAuthProvider for GeminiCliOAuthProvider {
     }
 }
 
-async fn discover_project(access_token: &str, callbacks: &dyn OAuthCallbacks) -> anyhow::Result<String> {
-    if let Ok(project) = std::env::var("GOOGLE_CLOUD_PROJECT") { return Ok(project); }
-    let client = reqwest::Client::new();
-    let resp = client
-        .post(format!("{}/v1internal:loadCodeAssist", CODE_ASSIST_ENDPOINT))
-        .header("Authorization", format!("Bearer {}", access_token))
-        .json(&serde_json::json!({ "metadata": { "ideType": "IDE_UNSPECIFIED", "platform": "PLATFORM_UNSPECIFIED", "pluginType": "GEMINI" } }))
-        .send()
-        .await?;
-
-    if resp.status().is_success() {
-        #[derive(Deserialize)]
-        #[serde(rename_all = "camelCase")]
-        struct LoadResp { cloudaicompanion_project: Option<String> }
-        if let Ok(data) = resp.json::<LoadResp>().await {
-            if let Some(p) = data.cloudaicompanion_project { return Ok(p); }
-        }
-    }
-
-    callbacks.on_progress("Provisioning project...");
-    let onboard_resp = client
-        .post(format!("{}/v1internal:onboardUser", CODE_ASSIST_ENDPOINT))
-        .header("Authorization", format!("Bearer {}", access_token))
-        .json(&serde_json::json!({ "tierId": "free-tier", "metadata": { "ideType": "IDE_UNSPECIFIED", "platform": "PLATFORM_UNSPECIFIED", "pluginType": "GEMINI" } }))
-        .send()
-        .await?;
-
-    #[derive(Deserialize)]
-    struct OnboardResp { response: Option<OnboardResponse> }
-    #[derive(Deserialize)]
-    #[serde(rename_all = "camelCase")]
-    struct OnboardResponse { cloudaicompanion_project: Option<ProjectId> }
-    #[derive(Deserialize)]
-    struct ProjectId { id: Option<String> }
-
-    if let Ok(data) = onboard_resp.json::<OnboardResp>().await {
-        if let Some(r) = data.response {
-            if let Some(p) = r.cloudaicompanion_project {
-                if let Some(id) = p.id { return Ok(id); }
-            }
-        }
+async fn discover_project(
+    access_token: &str,
+    callbacks: &dyn OAuthCallbacks,
+) -> anyhow::Result<CodeAssistSetup> {
+    let hint = std::env::var("GOOGLE_CLOUD_PROJECT").ok();
+    let endpoints = code_assist::endpoints(code_assist::PROD_ENDPOINT);
+    let setup = code_assist::setup_user(&reqwest::Client::new(), &endpoints, access_token, hint.as_deref())
+        .await
+        .map_err(|e| anyhow::anyhow!("Project discovery failed: {}", e))?;
+    if let Some(tier) = &setup.tier {
+        callbacks.on_progress(&format!("Using {} ({})", setup.project_id, tier.name.as_deref().unwrap_or(&tier.id)));
     }
-    anyhow::bail!("Project discovery failed")
+    Ok(setup)
 }
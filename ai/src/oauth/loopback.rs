@@ -0,0 +1,361 @@
+//! Local redirect server for OAuth authorization-code flows.
+//!
+//! The browser is sent back to `http://localhost:<port><path>?code=...&state=...`,
+//! where a [`LoopbackServer`] picks up the code. On machines the browser cannot
+//! reach (SSH, containers) the user pastes the redirect URL or code instead, which
+//! [`receive_code`] accepts at the same time.
+
+use super::{OAuthCallbacks, OAuthPrompt};
+use futures::StreamExt;
+use futures::stream::FuturesUnordered;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// How long to wait for the browser before giving up.
+pub const CALLBACK_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// How long a connection may take to send its request. Browsers open spare
+/// connections that never send one.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Largest request head read from the browser.
+const MAX_REQUEST_BYTES: usize = 16 * 1024;
+
+const SUCCESS_PAGE: &str = "<!doctype html><html><head><title>Signed in</title></head>\
+<body><h3>Sign-in complete.</h3><p>You can close this window and return to the terminal.</p></body></html>";
+
+/// Whether this looks like a remote shell, where a browser on the user's machine
+/// cannot reach a server listening here.
+pub fn is_remote_session() -> bool {
+    ["SSH_CONNECTION", "SSH_CLIENT", "SSH_TTY"]
+        .iter()
+        .any(|v| std::env::var_os(v).is_some())
+}
+
+/// HTTP listener on 127.0.0.1 that receives the authorization redirect.
+pub struct LoopbackServer {
+    listener: TcpListener,
+    port: u16,
+    path: String,
+}
+
+impl LoopbackServer {
+    /// Listen on `port` (0 for any free port) for redirects to `path`.
+    pub async fn bind(port: u16, path: &str) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port)).await?;
+        let port = listener.local_addr()?.port();
+        Ok(Self {
+            listener,
+            port,
+            path: path.to_string(),
+        })
+    }
+
+    /// Listen on `port`, or on any free port if it is taken. Only for clients
+    /// that accept any loopback port, as Google's desktop clients do.
+    pub async fn bind_preferred(port: u16, path: &str) -> std::io::Result<Self> {
+        match Self::bind(port, path).await {
+            Ok(server) => Ok(server),
+            Err(e) => {
+                tracing::debug!("OAuth callback port {} unavailable ({}), using any free port", port, e);
+                Self::bind(0, path).await
+            }
+        }
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Redirect URI to register in the authorization request.
+    pub fn redirect_uri(&self) -> String {
+        format!("http://localhost:{}{}", self.port, self.path)
+    }
+
+    /// Serve requests until one on the callback path carries `state`, and return
+    /// its code. Requests with another state are refused and waiting continues; an
+    /// `error` from the authorization server ends the wait. Connections are served
+    /// concurrently, so an idle one does not hold up the redirect.
+    ///
+    /// Waits forever: bound it with a timeout, and drop the future to cancel.
+    pub async fn accept_code(&self, state: &str) -> anyhow::Result<String> {
+        let mut connections = FuturesUnordered::new();
+        loop {
+            tokio::select! {
+                accepted = self.listener.accept() => {
+                    let (stream, _) = accepted?;
+                    connections.push(self.serve(stream, state));
+                }
+                Some(outcome) = connections.next(), if !connections.is_empty() => {
+                    if let Some(result) = outcome {
+                        return result;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Answer one connection. Returns `None` when waiting should continue.
+    async fn serve(&self, mut stream: TcpStream, state: &str) -> Option<anyhow::Result<String>> {
+        let target = match tokio::time::timeout(REQUEST_TIMEOUT, read_request_target(&mut stream)).await {
+            Ok(Some(t)) => t,
+            _ => return None,
+        };
+        let url = match url::Url::parse(&format!("http://localhost{}", target)) {
+            Ok(u) if u.path() == self.path => u,
+            _ => {
+                respond(&mut stream, "404 Not Found", "Not found").await;
+                return None;
+            }
+        };
+
+        let param = |name: &str| {
+            url.query_pairs()
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| v.into_owned())
+        };
+        if param("state").as_deref() != Some(state) {
+            respond(&mut stream, "400 Bad Request", "Sign-in state mismatch. Start the sign-in again.").await;
+            return None;
+        }
+        if let Some(error) = param("error") {
+            let description = param("error_description").unwrap_or_default();
+            respond(&mut stream, "400 Bad Request", &format!("Sign-in failed: {} {}", error, description)).await;
+            return Some(Err(anyhow::anyhow!("Authorization failed: {} {}", error, description)));
+        }
+        match param("code").filter(|c| !c.is_empty()) {
+            Some(code) => {
+                respond_html(&mut stream, "200 OK", SUCCESS_PAGE).await;
+                Some(Ok(code))
+            }
+            None => {
+                respond(&mut stream, "400 Bad Request", "No authorization code in the redirect.").await;
+                Some(Err(anyhow::anyhow!("No authorization code in the redirect")))
+            }
+        }
+    }
+}
+
+/// Request target (`/path?query`) from the request line, or `None` for a request
+/// that is not a readable GET.
+async fn read_request_target(stream: &mut TcpStream) -> Option<String> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 1024];
+    while !buf.windows(4).any(|w| w == b"\r\n\r\n") && buf.len() < MAX_REQUEST_BYTES {
+        let n = stream.read(&mut chunk).await.ok()?;
+        if n == 0 {
+            break;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+
+    let head = String::from_utf8_lossy(&buf);
+    let mut parts = head.lines().next()?.split_whitespace();
+    match (parts.next(), parts.next()) {
+        (Some("GET"), Some(target)) => Some(target.to_string()),
+        _ => None,
+    }
+}
+
+async fn respond(stream: &mut TcpStream, status: &str, message: &str) {
+    let page = format!(
+        "<!doctype html><html><body><p>{}</p></body></html>",
+        message.replace('&', "&amp;").replace('<', "&lt;")
+    );
+    respond_html(stream, status, &page).await;
+}
+
+async fn respond_html(stream: &mut TcpStream, status: &str, body: &str) {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    let _ = stream.write_all(response.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
+
+/// Authorization code from pasted input: a redirect URL, a query string,
+/// `code#state`, or the bare code. A state in the input must match `state`.
+pub fn parse_pasted_code(input: &str, state: &str) -> anyhow::Result<String> {
+    let input = input.trim();
+    if input.is_empty() {
+        anyhow::bail!("No authorization code entered");
+    }
+
+    let (code, pasted_state) = if let Ok(url) = url::Url::parse(input) {
+        let param = |name: &str| {
+            url.query_pairs()
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| v.into_owned())
+        };
+        if let Some(error) = param("error") {
+            anyhow::bail!("Authorization failed: {}", error);
+        }
+        let code = param("code").ok_or_else(|| anyhow::anyhow!("No authorization code in the pasted URL"))?;
+        (code, param("state"))
+    } else if input.contains("code=") {
+        let pairs: Vec<(String, String)> = serde_urlencoded::from_str(input.trim_start_matches('?'))?;
+        let param = |name: &str| pairs.iter().find(|(k, _)| k == name).map(|(_, v)| v.clone());
+        let code = param("code").ok_or_else(|| anyhow::anyhow!("No authorization code in the pasted text"))?;
+        (code, param("state"))
+    } else {
+        match input.split_once('#') {
+            Some((code, s)) => (code.to_string(), Some(s.to_string())),
+            None => (input.to_string(), None),
+        }
+    };
+
+    if pasted_state.as_deref().is_some_and(|s| s != state) {
+        anyhow::bail!("Sign-in state mismatch. Start the sign-in again.");
+    }
+    Ok(code)
+}
+
+/// Wait for the authorization code, from `server` when there is one and pasted
+/// through `callbacks.on_prompt` in any case, whichever comes first. The prompt
+/// is dropped once the browser has called back.
+///
+/// Fails after `timeout` without a redirect; drop the future to cancel.
+pub async fn receive_code(
+    callbacks: &dyn OAuthCallbacks,
+    server: Option<&LoopbackServer>,
+    state: &str,
+    prompt: OAuthPrompt,
+    timeout: Duration,
+) -> anyhow::Result<String> {
+    let pasted = async {
+        let input = callbacks.on_prompt(prompt).await?;
+        parse_pasted_code(&input, state)
+    };
+
+    let server = match server {
+        Some(s) => s,
+        None => return pasted.await,
+    };
+
+    let redirected = async {
+        match tokio::time::timeout(timeout, server.accept_code(state)).await {
+            Ok(result) => result,
+            Err(_) => anyhow::bail!("Timed out waiting for the browser sign-in"),
+        }
+    };
+
+    tokio::select! {
+        code = redirected => code,
+        code = pasted => code,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::OAuthAuthInfo;
+    use async_trait::async_trait;
+
+    /// Callbacks whose prompt never gets an answer.
+    struct NoInput;
+
+    #[async_trait]
+    impl OAuthCallbacks for NoInput {
+        fn on_auth(&self, _info: OAuthAuthInfo) {}
+        async fn on_prompt(&self, _prompt: OAuthPrompt) -> anyhow::Result<String> {
+            futures::future::pending().await
+        }
+        fn on_progress(&self, _message: &str) {}
+    }
+
+    fn prompt() -> OAuthPrompt {
+        OAuthPrompt { message: "Paste the code".into(), placeholder: None }
+    }
+
+    /// Send the browser's redirect and return the status code.
+    async fn redirect(server: &LoopbackServer, query: &str) -> u16 {
+        let url = format!("{}?{}", server.redirect_uri(), query);
+        reqwest::get(url).await.unwrap().status().as_u16()
+    }
+
+    #[tokio::test]
+    async fn accepts_code_with_matching_state() {
+        let server = LoopbackServer::bind(0, "/callback").await.unwrap();
+        let (code, status) = tokio::join!(server.accept_code("s1"), redirect(&server, "code=abc&state=s1"));
+        assert_eq!(code.unwrap(), "abc");
+        assert_eq!(status, 200);
+    }
+
+    #[tokio::test]
+    async fn refuses_mismatched_state_and_keeps_waiting() {
+        let server = LoopbackServer::bind(0, "/callback").await.unwrap();
+        let browser = async {
+            let refused = redirect(&server, "code=evil&state=other").await;
+            let accepted = redirect(&server, "code=abc&state=s1").await;
+            (refused, accepted)
+        };
+        let (code, (refused, accepted)) = tokio::join!(server.accept_code("s1"), browser);
+        assert_eq!(code.unwrap(), "abc");
+        assert_eq!((refused, accepted), (400, 200));
+    }
+
+    #[tokio::test]
+    async fn error_redirect_ends_the_wait() {
+        let server = LoopbackServer::bind(0, "/callback").await.unwrap();
+        let (code, status) = tokio::join!(
+            server.accept_code("s1"),
+            redirect(&server, "error=access_denied&error_description=nope&state=s1")
+        );
+        let err = code.unwrap_err().to_string();
+        assert!(err.contains("access_denied"), "{}", err);
+        assert_eq!(status, 400);
+    }
+
+    #[tokio::test]
+    async fn idle_connection_does_not_block_redirect() {
+        let server = LoopbackServer::bind(0, "/callback").await.unwrap();
+        let _idle = TcpStream::connect(("127.0.0.1", server.port())).await.unwrap();
+        let result = tokio::time::timeout(
+            Duration::from_secs(2),
+            async { tokio::join!(server.accept_code("s1"), redirect(&server, "code=abc&state=s1")) },
+        )
+        .await
+        .expect("redirect waited behind the idle connection");
+        assert_eq!(result.0.unwrap(), "abc");
+    }
+
+    #[tokio::test]
+    async fn receive_code_times_out() {
+        let server = LoopbackServer::bind(0, "/callback").await.unwrap();
+        let err = receive_code(&NoInput, Some(&server), "s1", prompt(), Duration::from_millis(100))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Timed out"), "{}", err);
+    }
+
+    #[test]
+    fn parses_pasted_redirect_url() {
+        let code = parse_pasted_code("http://localhost:1455/callback?code=abc&state=s1", "s1").unwrap();
+        assert_eq!(code, "abc");
+        assert!(parse_pasted_code("http://localhost:1455/callback?code=abc&state=s2", "s1").is_err());
+        assert!(parse_pasted_code("http://localhost:1455/callback?error=access_denied", "s1").is_err());
+    }
+
+    #[test]
+    fn parses_pasted_query_string() {
+        assert_eq!(parse_pasted_code("?code=abc&state=s1", "s1").unwrap(), "abc");
+        assert_eq!(parse_pasted_code("code=abc", "s1").unwrap(), "abc");
+        assert!(parse_pasted_code("code=abc&state=s2", "s1").is_err());
+    }
+
+    #[test]
+    fn parses_pasted_code_and_state() {
+        assert_eq!(parse_pasted_code("abc#s1", "s1").unwrap(), "abc");
+        assert!(parse_pasted_code("abc#s2", "s1").is_err());
+    }
+
+    #[test]
+    fn parses_bare_code() {
+        assert_eq!(parse_pasted_code("  abc \n", "s1").unwrap(), "abc");
+        assert!(parse_pasted_code("   ", "s1").is_err());
+    }
+}
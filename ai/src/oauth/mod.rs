@@ -1,7 +1,9 @@
 pub mod anthropic;
+pub mod device;
 pub mod github_copilot;
 pub mod google_antigravity;
 pub mod google_gemini_cli;
+pub mod loopback;
 pub mod openai_codex;
 pub mod pkce;
 pub mod qwen_portal;
@@ -15,6 +17,17 @@ pub struct OAuthAuthInfo {
     pub instructions: Option<String>,
 }
 
+/// Code the user enters on the verification page during a device flow.
+#[derive(Debug, Clone)]
+pub struct DeviceCodeInfo {
+    pub user_code: String,
+    pub verification_uri: String,
+    /// Verification page with the code filled in, when the server offers one.
+    pub verification_uri_complete: Option<String>,
+    /// How long the code stays valid.
+    pub expires_in: std::time::Duration,
+}
+
 /// Prompt to show to the user during OAuth.
 #[derive(Debug, Clone)]
 pub struct OAuthPrompt {
@@ -31,6 +44,18 @@ pub trait OAuthCallbacks: Send + Sync {
     async fn on_prompt(&self, prompt: OAuthPrompt) -> anyhow::Result<String>;
     /// Called with progress messages.
     fn on_progress(&self, message: &str);
+    /// Called when the user should enter a code on the verification page of a
+    /// device flow. Defaults to `on_auth` with the page and the code.
+    fn on_device_code(&self, info: DeviceCodeInfo) {
+        self.on_auth(OAuthAuthInfo {
+            url: info.verification_uri_complete.unwrap_or(info.verification_uri),
+            instructions: Some(format!("Enter code: {}", info.user_code)),
+        });
+    }
+    /// Whether the user gave up on the login; polling flows stop when it is.
+    fn is_cancelled(&self) -> bool {
+        false
+    }
 }
 
 /// Credentials returned from OAuth login.
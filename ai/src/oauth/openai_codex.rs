@@ -1,5 +1,7 @@
 use super::*;
+use crate::oauth::loopback::{self, LoopbackServer};
 use crate::oauth::pkce::generate_pkce;
+use crate::providers::openai_codex::chatgpt_account_id;
 use async_trait::async_trait;
 use serde::Deserialize;
 use std::collections::HashMap;
@@ -7,11 +9,39 @@ use std::collections::HashMap;
 const CLIENT_ID: &str = "app_EMoamEEZ73f0CkXaXp7hrann";
 const AUTHORIZE_URL: &str = "https://auth.openai.com/oauth/authorize";
 const TOKEN_URL: &str = "https://auth.openai.com/oauth/token";
+/// The client only accepts this exact redirect, so the port cannot change.
+const CALLBACK_PORT: u16 = 1455;
 const REDIRECT_URI: &str = "http://localhost:1455/auth/callback";
+const CALLBACK_PATH: &str = "/auth/callback";
 const SCOPE: &str = "openid profile email offline_access";
 
 pub struct OpenAiCodexOAuthProvider;
 
+#[derive(Deserialize)]
+struct TokenResp { access_token: String, refresh_token: String, expires_in: i64, id_token: Option<String> }
+
+impl TokenResp {
+    /// Credentials with the ChatGPT account id the backend requires, from the id
+    /// token's claims. `extra` keeps the previous account id if none is found.
+    fn into_credentials(self, mut extra: HashMap<String, serde_json::Value>) -> anyhow::Result<OAuthCredentials> {
+        let account_id = self.id_token.as_deref().and_then(chatgpt_account_id)
+            .or_else(|| chatgpt_account_id(&self.access_token));
+        if let Some(account_id) = account_id {
+            extra.insert("accountId".into(), serde_json::Value::String(account_id));
+        }
+        if !extra.contains_key("accountId") {
+            anyhow::bail!("No ChatGPT account id in the OpenAI token");
+        }
+
+        Ok(OAuthCredentials {
+            refresh: self.refresh_token,
+            access: self.access_token,
+            expires: chrono::Utc::now().timestamp_millis() + self.expires_in * 1000 - 300000,
+            extra,
+        })
+    }
+}
+
 impl OpenAiCodexOAuthProvider {
     pub fn new() -> Self {
         Self
@@ -41,19 +71,33 @@ impl OAuthProvider for OpenAiCodexOAuthProvider {
 
         let auth_url = format!("{}?{}", AUTHORIZE_URL, serde_urlencoded::to_string(&params)?);
 
+        // Another login (e.g. the Codex CLI) may hold the port; the user then pastes the URL
+        let server = match LoopbackServer::bind(CALLBACK_PORT, CALLBACK_PATH).await {
+            Ok(server) => Some(server),
+            Err(e) => {
+                tracing::debug!("OAuth callback port {} unavailable: {}", CALLBACK_PORT, e);
+                None
+            }
+        };
+        if server.is_some() && !loopback::is_remote_session() {
+            let _ = open::that(&auth_url);
+        }
+
         callbacks.on_auth(OAuthAuthInfo {
             url: auth_url,
-            instructions: Some("A browser should open. If not, visit the URL and paste the redirect URL here.".into()),
+            instructions: Some("A browser should open. If not, visit the URL; if it cannot reach this machine, paste the redirect URL here.".into()),
         });
 
-        let input = callbacks.on_prompt(OAuthPrompt {
-            message: "Paste the redirect URL (contains code=...):".into(),
-            placeholder: None,
-        }).await?;
-
-        let parsed = url::Url::parse(&input)?;
-        let code = parsed.query_pairs().find(|(k, _)| k == "code").map(|(_, v)| v.to_string())
-            .ok_or_else(|| anyhow::anyhow!("Missing code"))?;
+        let code = loopback::receive_code(
+            callbacks,
+            server.as_ref(),
+            &state,
+            OAuthPrompt {
+                message: "Paste the redirect URL (contains code=...):".into(),
+                placeholder: None,
+            },
+            loopback::CALLBACK_TIMEOUT,
+        ).await?;
 
         callbacks.on_progress("Exchanging code for tokens...");
 
@@ -70,17 +114,8 @@ impl OAuthProvider for OpenAiCodexOAuthProvider {
             anyhow::bail!("Token exchange failed: {}", resp.text().await?);
         }
 
-        #[derive(Deserialize)]
-        struct TokenResp { access_token: String, refresh_token: String, expires_in: i64 }
         let token: TokenResp = resp.json().await?;
-        let expires = chrono::Utc::now().timestamp_millis() + token.expires_in * 1000 - 300000;
-
-        Ok(OAuthCredentials {
-            refresh: token.refresh_token,
-            access: token.access_token,
-            expires,
-            extra: HashMap::new(),
-        })
+        token.into_credentials(HashMap::new())
     }
 
     async fn refresh_token(&self, credentials: &OAuthCredentials) -> anyhow::Result<OAuthCredentials> {
@@ -91,20 +126,18 @@ impl OAuthProvider for OpenAiCodexOAuthProvider {
             ("client_id", CLIENT_ID),
         ]).send().await?;
 
-        #[derive(Deserialize)]
-        struct TokenResp { access_token: String, refresh_token: String, expires_in: i64 }
-        let token: TokenResp = resp.json().await?;
-        let expires = chrono::Utc::now().timestamp_millis() + token.expires_in * 1000 - 300000;
+        if !resp.status().is_success() {
+            anyhow::bail!("Token refresh failed: {}", resp.text().await?);
+        }
 
-        Ok(OAuthCredentials {
-            refresh: token.refresh_token,
-            access: token.access_token,
-            expires,
-            extra: HashMap::new(),
-        })
+        let token: TokenResp = resp.json().await?;
+        token.into_credentials(credentials.extra.clone())
     }
 
     fn get_api_key(&self, credentials: &OAuthCredentials) -> String {
-        credentials.access.clone()
+        match credentials.extra.get("accountId").and_then(|v| v.as_str()) {
+            Some(account_id) => serde_json::json!({ "token": credentials.access, "accountId": account_id }).to_string(),
+            None => credentials.access.clone(),
+        }
     }
 }
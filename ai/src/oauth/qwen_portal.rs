@@ -1,99 +1,82 @@
 use super::*;
+use crate::oauth::device::DeviceFlow;
 use crate::oauth::pkce::generate_pkce;
 use async_trait::async_trait;
 use serde::Deserialize;
-use std::collections::HashMap;
 
 const CLIENT_ID: &str = "f0304373b74a44d2b584a3fb70ca9e56";
-const AUTHORIZE_URL: &str = "https://portal.qwen.ai/oauth/authorize";
-const TOKEN_URL: &str = "https://portal.qwen.ai/v1/oauth/token";
-const REDIRECT_URI: &str = "http://localhost:1455/auth/callback";
+const DEVICE_CODE_URL: &str = "https://chat.qwen.ai/api/v1/oauth2/device/code";
+const TOKEN_URL: &str = "https://chat.qwen.ai/api/v1/oauth2/token";
+const SCOPE: &str = "openid profile email model.completion";
+/// API host used when the token response names none.
+const DEFAULT_RESOURCE_URL: &str = "portal.qwen.ai";
 
 pub struct QwenPortalOAuthProvider;
 
+#[derive(Deserialize)]
+struct TokenResp { access_token: String, refresh_token: Option<String>, expires_in: i64, resource_url: Option<String> }
+
+impl TokenResp {
+    /// Credentials with the API host to send requests to. A refresh may omit the
+    /// refresh token and host, in which case the previous ones are kept.
+    fn into_credentials(self, previous: Option<&OAuthCredentials>) -> anyhow::Result<OAuthCredentials> {
+        let mut extra = previous.map(|c| c.extra.clone()).unwrap_or_default();
+        let resource_url = self.resource_url.filter(|r| !r.is_empty());
+        if resource_url.is_some() || !extra.contains_key("resourceUrl") {
+            let resource_url = resource_url.unwrap_or_else(|| DEFAULT_RESOURCE_URL.into());
+            extra.insert("resourceUrl".into(), serde_json::Value::String(resource_url));
+        }
+
+        let refresh = match (self.refresh_token, previous) {
+            (Some(r), _) => r,
+            (None, Some(p)) => p.refresh.clone(),
+            (None, None) => anyhow::bail!("Qwen returned no refresh token"),
+        };
+
+        Ok(OAuthCredentials {
+            refresh,
+            access: self.access_token,
+            expires: chrono::Utc::now().timestamp_millis() + self.expires_in * 1000 - 300000,
+            extra,
+        })
+    }
+}
+
 #[async_trait]
 impl OAuthProvider for QwenPortalOAuthProvider {
     fn id(&self) -> &str { "qwen-portal" }
-    fn name(&self) -> &str { "Qwen Portal OAuth" }
+    fn name(&self) -> &str { "Qwen OAuth (Device Flow)" }
 
     async fn login(&self, callbacks: &dyn OAuthCallbacks) -> anyhow::Result<OAuthCredentials> {
-        let pkce = generate_pkce();
-        let state = uuid::Uuid::new_v4().to_string();
-
-        let params = [
-            ("response_type", "code"),
-            ("client_id", CLIENT_ID),
-            ("redirect_uri", REDIRECT_URI),
-            ("scope", "openid profile email"),
-            ("code_challenge", &pkce.challenge),
-            ("code_challenge_method", "S256"),
-            ("state", &state),
-        ];
-
-        let auth_url = format!("{}?{}", AUTHORIZE_URL, serde_urlencoded::to_string(&params)?);
-
-        callbacks.on_auth(OAuthAuthInfo {
-            url: auth_url,
-            instructions: Some("Authorization page opened. Login and paste the redirect URL here.".into()),
-        });
-
-        let input = callbacks.on_prompt(OAuthPrompt {
-            message: "Paste the redirect URL:".into(),
-            placeholder: None,
-        }).await?;
-
-        let parsed = url::Url::parse(&input)?;
-        let code = parsed.query_pairs().find(|(k, _)| k == "code").map(|(_, v)| v.to_string())
-            .ok_or_else(|| anyhow::anyhow!("Missing code"))?;
+        let token: TokenResp = DeviceFlow::new(DEVICE_CODE_URL, TOKEN_URL, CLIENT_ID)
+            .scope(SCOPE)
+            .pkce(&generate_pkce())
+            .run(&reqwest::Client::new(), callbacks)
+            .await?;
+        token.into_credentials(None)
+    }
 
+    async fn refresh_token(&self, credentials: &OAuthCredentials) -> anyhow::Result<OAuthCredentials> {
         let client = reqwest::Client::new();
-        let resp = client.post(TOKEN_URL).form(&[
-            ("grant_type", "authorization_code"),
-            ("client_id", CLIENT_ID),
-            ("code", &code),
-            ("code_verifier", &pkce.verifier),
-            ("redirect_uri", REDIRECT_URI),
-        ]).send().await?;
+        let resp = client.post(TOKEN_URL)
+            .header("Accept", "application/json")
+            .form(&[
+                ("grant_type", "refresh_token"),
+                ("refresh_token", &credentials.refresh),
+                ("client_id", CLIENT_ID),
+            ])
+            .send().await?;
 
         if !resp.status().is_success() {
-            anyhow::bail!("Token exchange failed: {}", resp.text().await?);
+            anyhow::bail!("Token refresh failed: {}", resp.text().await?);
         }
 
-        #[derive(Deserialize)]
-        struct TokenResp { access_token: String, refresh_token: String, expires_in: i64 }
         let token: TokenResp = resp.json().await?;
-        let expires = chrono::Utc::now().timestamp_millis() + token.expires_in * 1000 - 300000;
-
-        Ok(OAuthCredentials {
-            refresh: token.refresh_token,
-            access: token.access_token,
-            expires,
-            extra: HashMap::new(),
-        })
-    }
-
-    async fn refresh_token(&self, credentials: &OAuthCredentials) -> anyhow::Result<OAuthCredentials> {
-        let client = reqwest::Client::new();
-        let resp = client.post(TOKEN_URL).form(&[
-            ("grant_type", "refresh_token"),
-            ("refresh_token", &credentials.refresh),
-            ("client_id", CLIENT_ID),
-        ]).send().await?;
-
-        #[derive(Deserialize)]
-        struct TokenResp { access_token: String, refresh_token: String, expires_in: i64 }
-        let token: TokenResp = resp.json().await?;
-        let expires = chrono::Utc::now().timestamp_millis() + token.expires_in * 1000 - 300000;
-
-        Ok(OAuthCredentials {
-            refresh: token.refresh_token,
-            access: token.access_token,
-            expires,
-            extra: HashMap::new(),
-        })
+        token.into_credentials(Some(credentials))
     }
 
     fn get_api_key(&self, credentials: &OAuthCredentials) -> String {
-        credentials.access.clone()
+        let resource_url = credentials.extra.get("resourceUrl").and_then(|v| v.as_str()).unwrap_or(DEFAULT_RESOURCE_URL);
+        serde_json::json!({ "token": credentials.access, "resourceUrl": resource_url }).to_string()
     }
 }
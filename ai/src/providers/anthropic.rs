@@ -1,19 +1,47 @@
 use super::{Provider, ProviderError};
+use crate::auth::config::ConfigManager;
 use crate::types::*;
 use async_trait::async_trait;
-use futures::stream::{self, BoxStream, StreamExt};
+use futures::stream::{BoxStream, StreamExt};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::collections::HashMap;
 
+/// Beta flags Anthropic requires for requests made with a Claude subscription token.
+const OAUTH_BETA_FLAGS: &str = "claude-code-20250219,oauth-2025-04-20,interleaved-thinking-2025-05-14";
+const CLAUDE_CODE_USER_AGENT: &str = "claude-cli/2.1.2 (external, cli)";
+const CLAUDE_CODE_IDENTITY: &str = "You are Claude Code, Anthropic's official CLI for Claude.";
+
 pub struct AnthropicProvider {
     client: Client,
+    /// Claude on Vertex AI: `rawPredict` URLs, model in the path, Vertex API version.
+    vertex: bool,
+    tool_names: ToolNamePolicy,
+    /// Where OAuth credentials are refreshed when the server rejects the access token.
+    config: Option<ConfigManager>,
 }
 
 impl AnthropicProvider {
     pub fn new() -> Self {
-        Self { client: Client::new() }
+        Self { client: Client::new(), vertex: false, tool_names: ToolNamePolicy::default(), config: None }
+    }
+
+    /// Variant for Claude on Vertex AI. Model base URLs point at the Vertex location.
+    pub fn vertex() -> Self {
+        Self { client: Client::new(), vertex: true, tool_names: ToolNamePolicy::default(), config: None }
+    }
+
+    /// Apply the Anthropic settings from the config file.
+    pub fn with_settings(mut self, settings: AnthropicConfig) -> Self {
+        self.tool_names = settings.tool_names;
+        self
+    }
+
+    /// Refresh OAuth credentials through this config manager on a 401.
+    pub fn with_config(mut self, config: ConfigManager) -> Self {
+        self.config = Some(config);
+        self
     }
 }
 
@@ -21,9 +49,47 @@ impl Default for AnthropicProvider {
     fn default() -> Self { Self::new() }
 }
 
+/// How client tool names are sent to Anthropic.
+///
+/// Requests made with a Claude subscription are expected to come from Claude Code,
+/// so tools matching Claude Code's own (`read`, `bash`, ...) can be renamed to its
+/// casing (`Read`, `Bash`). Tool calls in the response get the caller's names back.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolNamePolicy {
+    /// Claude Code casing with OAuth and setup tokens, names as given with API keys.
+    #[default]
+    Auto,
+    /// Claude Code casing whatever the credential.
+    ClaudeCode,
+    /// Names as given whatever the credential.
+    Preserve,
+}
+
+impl ToolNamePolicy {
+    fn maps_names(self, subscription: bool) -> bool {
+        match self {
+            ToolNamePolicy::Auto => subscription,
+            ToolNamePolicy::ClaudeCode => true,
+            ToolNamePolicy::Preserve => false,
+        }
+    }
+}
+
+/// Anthropic settings from the config file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AnthropicConfig {
+    #[serde(default)]
+    pub tool_names: ToolNamePolicy,
+}
+
 #[derive(Serialize)]
 struct MessagesRequest {
-    model: String,
+    /// Omitted on Vertex, where the model is part of the URL.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    anthropic_version: Option<String>,
     messages: Vec<AnthropicMessage>,
     max_tokens: u64,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -43,10 +109,72 @@ struct AnthropicMessage {
 
 #[derive(Serialize)]
 struct AnthropicTool {
+    /// Versioned server tool type; `None` for client tools.
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    tool_type: Option<String>,
     name: String,
-    description: String,
-    #[serde(rename = "input_schema")]
-    parameters: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    #[serde(rename = "input_schema", skip_serializing_if = "Option::is_none")]
+    parameters: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_uses: Option<u32>,
+}
+
+/// Server tool declaration and the beta flag it needs, if any.
+fn server_tool(tool: &HostedTool) -> (AnthropicTool, Option<&'static str>) {
+    let (tool_type, name, max_uses, beta) = match tool {
+        HostedTool::WebSearch { max_uses } => ("web_search_20250305", "web_search", *max_uses, None),
+        HostedTool::CodeExecution => ("code_execution_20250522", "code_execution", None, Some("code-execution-2025-05-22")),
+        HostedTool::UrlContext => ("web_fetch_20250910", "web_fetch", None, Some("web-fetch-2025-09-10")),
+    };
+    let def = AnthropicTool { tool_type: Some(tool_type.into()), name: name.into(), description: None, parameters: None, max_uses };
+    (def, beta)
+}
+
+/// Typed block for a `*_tool_result` content block. `inputs` holds the inputs of the
+/// server tool calls seen so far, by id, to pair search results with their query.
+fn server_tool_result(block: &ContentBlockData, inputs: &HashMap<String, serde_json::Value>) -> Option<ContentBlock> {
+    let content = block.content.as_ref()?;
+    let tool_use_id = block.tool_use_id.clone();
+    let error = content.get("error_code").and_then(|e| e.as_str()).map(String::from);
+    let source = |r: &serde_json::Value| CitationSource {
+        uri: r.get("url").and_then(|u| u.as_str()).map(String::from),
+        title: r.get("title").and_then(|t| t.as_str()).map(String::from),
+        license: None,
+        start_index: None,
+        end_index: None,
+    };
+
+    match block.block_type.as_str() {
+        "web_search_tool_result" => {
+            let query = tool_use_id.as_ref().and_then(|id| inputs.get(id)).and_then(|i| i.get("query")).and_then(|q| q.as_str()).map(String::from);
+            let results = content.as_array().map(|a| a.iter().map(source).collect()).unwrap_or_default();
+            Some(ContentBlock::WebSearchResult(WebSearchResult { tool_use_id, query, results, error }))
+        }
+        "web_fetch_tool_result" => {
+            let results = if error.is_none() { vec![source(content)] } else { Vec::new() };
+            Some(ContentBlock::WebSearchResult(WebSearchResult { tool_use_id, query: None, results, error }))
+        }
+        "code_execution_tool_result" => {
+            let field = |k: &str| content.get(k).and_then(|v| v.as_str()).unwrap_or_default().to_string();
+            let output = match &error {
+                Some(code) => code.clone(),
+                None => [field("stdout"), field("stderr")].iter().filter(|s| !s.is_empty()).cloned().collect::<Vec<_>>().join("\n"),
+            };
+            let success = error.is_none() && content.get("return_code").and_then(|c| c.as_i64()) == Some(0);
+            Some(ContentBlock::CodeExecutionResult(CodeExecutionResult { tool_use_id, success, output }))
+        }
+        _ => None,
+    }
+}
+
+/// Close the text streamed so far into a block, keeping it ahead of the hosted
+/// block that follows it.
+fn flush_text(text: &mut String, blocks: &mut Vec<ContentBlock>) {
+    if !text.is_empty() {
+        blocks.push(ContentBlock::Text(TextContent { text: std::mem::take(text), thought_signature: None }));
+    }
 }
 
 #[derive(Deserialize)]
@@ -73,6 +201,10 @@ struct ContentBlockData {
     id: Option<String>,
     #[serde(default)]
     name: Option<String>,
+    #[serde(default)]
+    tool_use_id: Option<String>,
+    #[serde(default)]
+    content: Option<serde_json::Value>,
 }
 
 #[derive(Deserialize)]
@@ -146,21 +278,31 @@ impl Provider for AnthropicProvider {
         context: &ChatContext,
         options: &StreamOptions,
     ) -> BoxStream<'static, Result<StreamEvent, ProviderError>> {
-        let api_key = match &options.api_key {
-            Some(k) => k.clone(),
-            None => return Box::pin(stream::once(async { Err(ProviderError::AuthRequired("API key required".into())) })),
-        };
+        // A missing key means auth is carried in extra headers
+        let api_key = options.api_key.clone().unwrap_or_default();
 
-        let is_setup_token = api_key.contains("sk-ant-sid");
+        // Subscription tokens authenticate as Claude Code, with a bearer token
+        let kind = options.credential_kind.unwrap_or(CredentialKind::ApiKey);
+        let subscription = kind != CredentialKind::ApiKey;
+        let map_names = self.tool_names.maps_names(subscription);
         let mut headers = HashMap::new();
-        headers.insert("x-api-key".to_string(), api_key.clone());
-        headers.insert("anthropic-version".to_string(), "2023-06-01".to_string());
+        if !api_key.is_empty() {
+            if subscription {
+                headers.insert("authorization".to_string(), format!("Bearer {}", api_key));
+            } else {
+                headers.insert("x-api-key".to_string(), api_key.clone());
+            }
+        }
+        if !self.vertex {
+            headers.insert("anthropic-version".to_string(), "2023-06-01".to_string());
+        }
         
         let mut system_blocks = Vec::new();
-        if is_setup_token {
-            headers.insert("anthropic-beta".to_string(), "claude-code-20250219,interleaved-thinking-2025-05-14".to_string());
-            headers.insert("user-agent".to_string(), "claude-cli/2.1.2 (external, cli)".to_string());
-            system_blocks.push(json!({"type": "text", "text": "You are Claude Code, Anthropic's official CLI for Claude."}));
+        if subscription {
+            headers.insert("anthropic-beta".to_string(), OAUTH_BETA_FLAGS.to_string());
+            headers.insert("user-agent".to_string(), CLAUDE_CODE_USER_AGENT.to_string());
+            headers.insert("x-app".to_string(), "cli".to_string());
+            system_blocks.push(json!({"type": "text", "text": CLAUDE_CODE_IDENTITY}));
         }
         if let Some(sys) = &context.system_prompt {
             system_blocks.push(json!({"type": "text", "text": sys}));
@@ -169,37 +311,87 @@ impl Provider for AnthropicProvider {
         let system = if system_blocks.is_empty() { None } else { Some(json!(system_blocks)) };
         let requested_tools = context.tools.clone();
         
+        let mut tools: Vec<AnthropicTool> = context.tools.iter().map(|t| AnthropicTool {
+            tool_type: None,
+            name: if map_names { to_claude_code_name(&t.name) } else { t.name.clone() },
+            description: Some(t.description.clone()),
+            parameters: Some(t.parameters.clone()),
+            max_uses: None,
+        }).collect();
+        // Other Messages-API backends do not run Anthropic's server tools
+        let hosted_tools = if model.provider == "anthropic" { context.hosted_tools.as_slice() } else { &[] };
+        for hosted in hosted_tools {
+            let (def, beta) = server_tool(hosted);
+            tools.push(def);
+            if let Some(beta) = beta {
+                let flags = headers.entry("anthropic-beta".to_string()).or_default();
+                if !flags.is_empty() { flags.push(','); }
+                flags.push_str(beta);
+            }
+        }
+
+        if let Some(model_headers) = &model.headers {
+            headers.extend(model_headers.clone());
+        }
+        if let Some(extra) = &options.extra_headers {
+            headers.extend(extra.clone());
+        }
+
         let req_body = MessagesRequest {
-            model: model.id.clone(),
-            messages: convert_messages(context, is_setup_token),
+            model: if self.vertex { None } else { Some(model.id.clone()) },
+            anthropic_version: if self.vertex { Some("vertex-2023-10-16".into()) } else { None },
+            messages: convert_messages(context, map_names),
             max_tokens: options.max_tokens.unwrap_or(model.max_tokens),
             system,
             temperature: options.temperature,
             stream: true,
-            tools: if context.tools.is_empty() { None } else { 
-                Some(context.tools.iter().map(|t| AnthropicTool {
-                    name: if is_setup_token { to_claude_code_name(&t.name) } else { t.name.clone() },
-                    description: t.description.clone(),
-                    parameters: t.parameters.clone(),
-                }).collect())
-            },
+            tools: if tools.is_empty() { None } else { Some(tools) },
         };
 
         let client = self.client.clone();
-        let url = format!("{}/messages", model.base_url.trim_end_matches('/'));
+        let url = if self.vertex {
+            format!(
+                "{}/publishers/anthropic/models/{}:streamRawPredict",
+                model.base_url.trim_end_matches('/'),
+                model.id
+            )
+        } else {
+            format!("{}/messages", model.base_url.trim_end_matches('/'))
+        };
         let model_id = model.id.clone();
         let provider_id = model.provider.clone();
 
+        // Only OAuth access tokens can be refreshed; setup tokens and keys cannot
+        let config = self.config.clone().filter(|_| kind == CredentialKind::OAuth);
+
         let s = async_stream::stream! {
-            let mut req = client.post(&url);
-            for (k, v) in &headers { req = req.header(k, v); }
-            let resp = match req.json(&req_body).send().await {
-                Ok(r) => r,
-                Err(e) => { yield Err(ProviderError::Network(e)); return; }
+            let mut headers = headers;
+            let mut refreshed = false;
+            let resp = loop {
+                let mut req = client.post(&url);
+                for (k, v) in &headers { req = req.header(k, v); }
+                let resp = match req.json(&req_body).send().await {
+                    Ok(r) => r,
+                    Err(e) => { yield Err(ProviderError::Network(e)); return; }
+                };
+                // The access token may have been revoked or expired early: refresh once and retry
+                let config = config.as_ref().filter(|_| resp.status().as_u16() == 401 && !refreshed);
+                if let Some(config) = config {
+                    refreshed = true;
+                    match config.refresh_api_key(&provider_id, &api_key).await {
+                        Ok(Some(key)) => {
+                            headers.insert("authorization".to_string(), format!("Bearer {}", key));
+                            continue;
+                        }
+                        Ok(None) => {}
+                        Err(e) => tracing::warn!("Failed to refresh OAuth token for {}: {}", provider_id, e),
+                    }
+                }
+                break resp;
             };
             let status = resp.status();
             if !status.is_success() {
-                yield Err(ProviderError::Http { status: status.as_u16(), body: resp.text().await.unwrap_or_default() });
+                yield Err(ProviderError::from_response(resp).await);
                 return;
             }
             yield Ok(StreamEvent::Start);
@@ -208,6 +400,13 @@ impl Provider for AnthropicProvider {
             let mut thinking_buf = String::new();
             let mut signature_buf: Option<String> = None;
             let mut tool_calls: Vec<(String, String, String)> = Vec::new();
+            // Content block index -> position in tool_calls
+            let mut tool_blocks: HashMap<usize, usize> = HashMap::new();
+            // Server tool calls run by Anthropic; kept apart from client tool calls
+            let mut server_calls: HashMap<usize, (String, String, String)> = HashMap::new();
+            let mut server_inputs: HashMap<String, serde_json::Value> = HashMap::new();
+            // Text and hosted tool blocks, in arrival order
+            let mut blocks: Vec<ContentBlock> = Vec::new();
             let mut usage = Usage::default();
             let mut stop_reason = StopReason::Stop;
             let mut line_buf = String::new();
@@ -227,15 +426,29 @@ impl Provider for AnthropicProvider {
                         "message_start" => { if let Some(m) = evt.message { if let Some(u) = m.usage { usage.input_tokens = u.input_tokens; } } }
                         "content_block_start" => {
                             if let Some(b) = evt.content_block {
-                                if b.block_type == "tool_use" {
-                                    let id = b.id.unwrap_or_default();
-                                    let mut name = b.name.unwrap_or_default();
-                                    if is_setup_token {
-                                        name = from_claude_code_name(&name, &requested_tools);
+                                match b.block_type.as_str() {
+                                    "tool_use" => {
+                                        let id = b.id.unwrap_or_default();
+                                        let mut name = b.name.unwrap_or_default();
+                                        if map_names {
+                                            name = from_claude_code_name(&name, &requested_tools);
+                                        }
+                                        let idx = tool_calls.len();
+                                        tool_blocks.insert(evt.index.unwrap_or(idx), idx);
+                                        tool_calls.push((id.clone(), name.clone(), String::new()));
+                                        yield Ok(StreamEvent::ToolCallStart { index: idx, id, name });
+                                    }
+                                    "server_tool_use" => {
+                                        if let Some(idx) = evt.index {
+                                            server_calls.insert(idx, (b.id.unwrap_or_default(), b.name.unwrap_or_default(), String::new()));
+                                        }
+                                    }
+                                    _ => {
+                                        if let Some(block) = server_tool_result(&b, &server_inputs) {
+                                            flush_text(&mut text_buf, &mut blocks);
+                                            blocks.push(block);
+                                        }
                                     }
-                                    let idx = tool_calls.len();
-                                    tool_calls.push((id.clone(), name.clone(), String::new()));
-                                    yield Ok(StreamEvent::ToolCallStart { index: idx, id, name });
                                 }
                             }
                         }
@@ -248,7 +461,9 @@ impl Provider for AnthropicProvider {
                                     signature_buf.as_mut().unwrap().push_str(&sig);
                                 }
                                 if let Some(pj) = d.partial_json {
-                                    if let Some(last) = tool_calls.last_mut() {
+                                    if let Some(call) = evt.index.and_then(|i| server_calls.get_mut(&i)) {
+                                        call.2.push_str(&pj);
+                                    } else if let Some(last) = tool_calls.last_mut() {
                                         last.2.push_str(&pj);
                                         yield Ok(StreamEvent::ToolCallDelta { index: tool_calls.len()-1, delta: pj });
                                     }
@@ -256,15 +471,20 @@ impl Provider for AnthropicProvider {
                             }
                         }
                         "content_block_stop" => {
-                            if let Some(idx) = evt.index {
-                                if idx < tool_calls.len() {
-                                    let (id, name, args) = &tool_calls[idx];
-                                    yield Ok(StreamEvent::ToolCallEnd { index: idx, tool_call: ToolCall { id: id.clone(), name: name.clone(), arguments: serde_json::from_str(args).unwrap_or(json!({})) } });
+                            if let Some((id, name, args)) = evt.index.and_then(|i| server_calls.remove(&i)) {
+                                let input: serde_json::Value = serde_json::from_str(&args).unwrap_or(json!({}));
+                                if let Some(code) = input.get("code").and_then(|c| c.as_str()).filter(|_| name == "code_execution") {
+                                    flush_text(&mut text_buf, &mut blocks);
+                                    blocks.push(ContentBlock::ExecutableCode(ExecutableCode { id: Some(id.clone()), language: Some("python".into()), code: code.to_string() }));
                                 }
+                                server_inputs.insert(id, input);
+                            } else if let Some(&idx) = evt.index.and_then(|i| tool_blocks.get(&i)) {
+                                let (id, name, args) = &tool_calls[idx];
+                                yield Ok(StreamEvent::ToolCallEnd { index: idx, tool_call: ToolCall { id: id.clone(), name: name.clone(), arguments: serde_json::from_str(args).unwrap_or(json!({})), thought_signature: None } });
                             }
                         }
                         "message_delta" => {
-                            if let Some(d) = evt.delta { if let Some(sr) = d.stop_reason { stop_reason = match sr.as_str() { "end_turn" => StopReason::Stop, "tool_use" => StopReason::ToolUse, _ => StopReason::Stop }; } }
+                            if let Some(d) = evt.delta { if let Some(sr) = d.stop_reason { stop_reason = match sr.as_str() { "end_turn" => StopReason::Stop, "tool_use" => StopReason::ToolUse, "refusal" => StopReason::ContentFilter, _ => StopReason::Stop }; } }
                             if let Some(u) = evt.usage { usage.output_tokens = u.output_tokens; }
                         }
                         _ => {}
@@ -274,33 +494,40 @@ impl Provider for AnthropicProvider {
             
             let mut content = Vec::new();
             if !thinking_buf.is_empty() { content.push(ContentBlock::Thinking(ThinkingContent { thinking: thinking_buf, signature: signature_buf })); }
-            if !text_buf.is_empty() { content.push(ContentBlock::Text(TextContent { text: text_buf })); }
-            for (id, name, args) in tool_calls { content.push(ContentBlock::ToolCall(ToolCall { id, name, arguments: serde_json::from_str(&args).unwrap_or(json!({})) })); }
+            flush_text(&mut text_buf, &mut blocks);
+            content.extend(blocks);
+            for (id, name, args) in tool_calls { content.push(ContentBlock::ToolCall(ToolCall { id, name, arguments: serde_json::from_str(&args).unwrap_or(json!({})), thought_signature: None })); }
             
             usage.total_tokens = usage.input_tokens + usage.output_tokens;
-            yield Ok(StreamEvent::Done { message: AssistantMessage { content, model: model_id, provider: provider_id, usage: Some(usage), stop_reason } });
+            yield Ok(StreamEvent::Done { message: AssistantMessage { content, model: model_id, provider: provider_id, usage: Some(usage), stop_reason, error_message: None } });
         };
         Box::pin(s)
     }
 
-    async fn list_models(&self, _api_key: &str) -> Result<Vec<ModelDef>, ProviderError> {
-        Ok(static_anthropic_models())
+    async fn list_models(
+        &self,
+        provider: &str,
+        _base_url: &str,
+        _api_key: &str,
+    ) -> Result<Vec<ModelDef>, ProviderError> {
+        Ok(crate::models::static_models_for_provider(provider))
     }
 }
 
-fn convert_messages(context: &ChatContext, is_setup_token: bool) -> Vec<AnthropicMessage> {
+fn convert_messages(context: &ChatContext, map_names: bool) -> Vec<AnthropicMessage> {
     context.messages.iter().map(|m| match m {
         Message::User(u) => AnthropicMessage { role: "user".into(), content: json!(u.content.iter().filter_map(|b| match b {
             ContentBlock::Text(t) => Some(json!({"type": "text", "text": t.text})),
             _ => None
         }).collect::<Vec<_>>()) },
-        Message::Assistant(a) => AnthropicMessage { role: "assistant".into(), content: json!(a.content.iter().map(|b| match b {
-            ContentBlock::Text(t) => json!({"type": "text", "text": t.text}),
+        Message::Assistant(a) => AnthropicMessage { role: "assistant".into(), content: json!(a.content.iter().filter_map(|b| match b {
+            ContentBlock::Text(t) => Some(json!({"type": "text", "text": t.text})),
             ContentBlock::ToolCall(tc) => {
-                let name = if is_setup_token { to_claude_code_name(&tc.name) } else { tc.name.clone() };
-                json!({"type": "tool_use", "id": tc.id, "name": name, "input": tc.arguments})
+                let name = if map_names { to_claude_code_name(&tc.name) } else { tc.name.clone() };
+                Some(json!({"type": "tool_use", "id": tc.id, "name": name, "input": tc.arguments}))
             },
-            _ => json!({})
+            // Hosted tool blocks and citations are informational; Anthropic does not need them back
+            _ => None
         }).collect::<Vec<_>>()) },
         Message::ToolResult(tr) => AnthropicMessage { role: "user".into(), content: json!([{"type": "tool_result", "tool_use_id": tr.tool_call_id, "content": user_content_to_text(&tr.content), "is_error": tr.is_error}]) },
     }).collect()
@@ -314,6 +541,161 @@ pub fn static_anthropic_models() -> Vec<ModelDef> {
     let p = "anthropic";
     let url = "https://api.anthropic.com/v1";
     vec![
-        ModelDef { id: "claude-3-5-sonnet-20241022".into(), name: "Claude 3.5 Sonnet".into(), api: Api::AnthropicMessages, provider: p.into(), base_url: url.into(), reasoning: false, input: vec![InputModality::Text], cost: ModelCost::default(), context_window: 200000, max_tokens: 8192, headers: None },
+        ModelDef { id: "claude-3-5-sonnet-20241022".into(), name: "Claude 3.5 Sonnet".into(), api: Api::AnthropicMessages, provider: p.into(), base_url: url.into(), reasoning: false, input: vec![InputModality::Text], cost: ModelCost::default(), context_window: 200000, max_tokens: 8192, headers: None, compat: None },
     ]
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{Credential, OAuthCredential};
+    use crate::providers::test_server::{FakeOAuth, FakeServer, Response};
+
+    fn model(base_url: &str) -> ModelDef {
+        ModelDef {
+            id: "claude-sonnet-4-5".into(),
+            name: "Claude Sonnet 4.5".into(),
+            api: Api::AnthropicMessages,
+            provider: "anthropic".into(),
+            base_url: base_url.into(),
+            reasoning: true,
+            input: vec![InputModality::Text],
+            cost: ModelCost::default(),
+            context_window: 200_000,
+            max_tokens: 8192,
+            headers: None,
+            compat: None,
+        }
+    }
+
+    fn sse(events: &[serde_json::Value]) -> Response {
+        Response {
+            status: 200,
+            headers: vec![("content-type".into(), "text/event-stream".into())],
+            body: events.iter().map(|e| format!("event: {}\ndata: {}\n\n", e["type"].as_str().unwrap(), e)).collect(),
+        }
+    }
+
+    fn hello() -> ChatContext {
+        ChatContext {
+            system_prompt: None,
+            messages: vec![Message::User(UserMessage {
+                content: vec![ContentBlock::Text(TextContent { text: "Hi".into(), thought_signature: None })],
+            })],
+            tools: Vec::new(),
+            hosted_tools: Vec::new(),
+        }
+    }
+
+    async fn done_message(provider: &AnthropicProvider, model: &ModelDef, options: &StreamOptions) -> AssistantMessage {
+        let events: Vec<_> = provider.stream(model, &hello(), options).collect().await;
+        match events.into_iter().last() {
+            Some(Ok(StreamEvent::Done { message })) => message,
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn rejected_oauth_tokens_are_refreshed_and_retried_once() {
+        let server = FakeServer::start(|req| {
+            if req.target == "/token" {
+                return Response::json(200, json!({"access_token": "fresh-token", "refresh_token": "r2"}));
+            }
+            if req.headers["authorization"] != "Bearer fresh-token" {
+                return Response::json(401, json!({"type": "error", "error": {"type": "authentication_error"}}));
+            }
+            sse(&[
+                json!({"type": "message_start", "message": {"usage": {"input_tokens": 12}}}),
+                json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}}),
+                json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Hello"}}),
+                json!({"type": "content_block_stop", "index": 0}),
+                json!({"type": "message_delta", "delta": {"stop_reason": "end_turn"}, "usage": {"output_tokens": 3}}),
+                json!({"type": "message_stop"}),
+            ])
+        })
+        .await;
+        let dir = std::env::temp_dir().join(format!("ai-rs-test-{}", uuid::Uuid::new_v4().simple()));
+        let config = ConfigManager::new(dir.join("config.json")).with_oauth_provider("anthropic", FakeOAuth::new(&server.url));
+        let stored = OAuthCredential {
+            refresh: "r1".into(),
+            access: "old-token".into(),
+            expires: chrono::Utc::now().timestamp_millis() + 3_600_000,
+            extra: HashMap::new(),
+        };
+        config.set_credential("anthropic", Credential::OAuth(stored)).unwrap();
+
+        let provider = AnthropicProvider::new().with_config(config.clone());
+        let options = StreamOptions {
+            api_key: Some("old-token".into()),
+            credential_kind: Some(CredentialKind::OAuth),
+            ..Default::default()
+        };
+        let message = done_message(&provider, &model(&server.url), &options).await;
+        assert!(matches!(&message.content[..], [ContentBlock::Text(t)] if t.text == "Hello"));
+
+        let requests = server.requests();
+        let targets: Vec<_> = requests.iter().map(|r| r.target.as_str()).collect();
+        assert_eq!(targets, ["/messages", "/token", "/messages"]);
+        let retry = &requests[2];
+        assert_eq!(retry.headers["authorization"], "Bearer fresh-token");
+        assert!(!retry.headers.contains_key("x-api-key"));
+        assert!(retry.headers["anthropic-beta"].contains("oauth-2025-04-20"));
+        assert_eq!(config.resolve_api_key("anthropic").await.unwrap().as_deref(), Some("fresh-token"));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn api_keys_are_sent_as_is_and_never_refreshed() {
+        let server = FakeServer::start(|_| Response::json(401, json!({"type": "error", "error": {"type": "authentication_error"}}))).await;
+        let dir = std::env::temp_dir().join(format!("ai-rs-test-{}", uuid::Uuid::new_v4().simple()));
+        let config = ConfigManager::new(dir.join("config.json")).with_oauth_provider("anthropic", FakeOAuth::new(&server.url));
+        let provider = AnthropicProvider::new().with_config(config);
+        let options = StreamOptions { api_key: Some("sk-ant-key".into()), ..Default::default() };
+
+        let events: Vec<_> = provider.stream(&model(&server.url), &hello(), &options).collect().await;
+        assert!(matches!(events.last(), Some(Err(ProviderError::Http { status: 401, .. }))), "{:?}", events.last());
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].headers["x-api-key"], "sk-ant-key");
+        assert!(!requests[0].headers.contains_key("authorization"));
+        assert!(!requests[0].headers.contains_key("anthropic-beta"));
+    }
+
+    #[tokio::test]
+    async fn server_tool_blocks_keep_their_place_among_the_text() {
+        let server = FakeServer::start(|_| {
+            sse(&[
+                json!({"type": "message_start", "message": {"usage": {"input_tokens": 20}}}),
+                json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}}),
+                json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Let me compute it."}}),
+                json!({"type": "content_block_stop", "index": 0}),
+                json!({"type": "content_block_start", "index": 1, "content_block": {"type": "server_tool_use", "id": "srv_1", "name": "code_execution", "input": {}}}),
+                json!({"type": "content_block_delta", "index": 1, "delta": {"type": "input_json_delta", "partial_json": "{\"code\": \"print(2 ** 10)\"}"}}),
+                json!({"type": "content_block_stop", "index": 1}),
+                json!({"type": "content_block_start", "index": 2, "content_block": {"type": "code_execution_tool_result", "tool_use_id": "srv_1", "content": {"type": "code_execution_result", "stdout": "1024", "stderr": "", "return_code": 0}}}),
+                json!({"type": "content_block_stop", "index": 2}),
+                json!({"type": "content_block_start", "index": 3, "content_block": {"type": "text", "text": ""}}),
+                json!({"type": "content_block_delta", "index": 3, "delta": {"type": "text_delta", "text": "It is 1024."}}),
+                json!({"type": "content_block_stop", "index": 3}),
+                json!({"type": "message_delta", "delta": {"stop_reason": "end_turn"}, "usage": {"output_tokens": 30}}),
+                json!({"type": "message_stop"}),
+            ])
+        })
+        .await;
+        let options = StreamOptions { api_key: Some("sk-ant-key".into()), ..Default::default() };
+        let message = done_message(&AnthropicProvider::new(), &model(&server.url), &options).await;
+
+        match &message.content[..] {
+            [ContentBlock::Text(before), ContentBlock::ExecutableCode(code), ContentBlock::CodeExecutionResult(result), ContentBlock::Text(after)] => {
+                assert_eq!(before.text, "Let me compute it.");
+                assert_eq!(code.id.as_deref(), Some("srv_1"));
+                assert_eq!(code.code, "print(2 ** 10)");
+                assert_eq!(result.tool_use_id.as_deref(), Some("srv_1"));
+                assert!(result.success);
+                assert_eq!(result.output, "1024");
+                assert_eq!(after.text, "It is 1024.");
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+}
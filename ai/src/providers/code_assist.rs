@@ -0,0 +1,378 @@
+use super::ProviderError;
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::json;
+use uuid::Uuid;
+
+pub const PROD_ENDPOINT: &str = "https://cloudcode-pa.googleapis.com";
+pub const DAILY_ENDPOINT: &str = "https://daily-cloudcode-pa.sandbox.googleapis.com";
+pub const AUTOPUSH_ENDPOINT: &str = "https://autopush-cloudcode-pa.sandbox.googleapis.com";
+
+/// Overrides every Cloud Code Assist endpoint, e.g. to point at a local stand-in server.
+pub const ENDPOINT_ENV: &str = "CODE_ASSIST_ENDPOINT";
+
+/// Tier used for onboarding when the account reports no default.
+const FREE_TIER: &str = "free-tier";
+const ONBOARD_ATTEMPTS: usize = 10;
+const ONBOARD_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Endpoints to try in order: the model's own first, then the other environments.
+///
+/// A base URL that is not a Cloud Code Assist environment (a local stand-in, or
+/// one set through `CODE_ASSIST_ENDPOINT`) is used alone.
+pub fn endpoints(base_url: &str) -> Vec<String> {
+    if let Ok(endpoint) = std::env::var(ENDPOINT_ENV) {
+        return vec![endpoint.trim_end_matches('/').to_string()];
+    }
+
+    let base_url = base_url.trim_end_matches('/');
+    let known = [PROD_ENDPOINT, DAILY_ENDPOINT, AUTOPUSH_ENDPOINT];
+    if !base_url.is_empty() && !known.contains(&base_url) {
+        return vec![base_url.to_string()];
+    }
+
+    let mut list = Vec::new();
+    if !base_url.is_empty() {
+        list.push(base_url.to_string());
+    }
+    list.extend(
+        known
+            .iter()
+            .filter(|e| **e != base_url)
+            .map(|e| e.to_string()),
+    );
+    list
+}
+
+/// Whether a failed request should be retried on the next endpoint.
+pub fn should_fail_over(status: u16) -> bool {
+    status == 429 || status >= 500
+}
+
+/// Namespace of the name-based session ids.
+const SESSION_NAMESPACE: Uuid = Uuid::from_u128(0xf35bb4ca_7d3e_4da3_a3a3_49985de515b0);
+
+/// Session id for a conversation, stable across its turns so the server can reuse
+/// the context it already processed.
+pub fn session_id(conversation_id: &str) -> String {
+    Uuid::new_v5(&SESSION_NAMESPACE, conversation_id.as_bytes()).to_string()
+}
+
+// ---------------------------------------------------------------------------
+// Project setup
+// ---------------------------------------------------------------------------
+
+/// A Cloud Code Assist subscription tier, e.g. `free-tier` or `standard-tier`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserTier {
+    pub id: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub is_default: bool,
+    /// Paid tiers run on the user's own Cloud project instead of a managed one.
+    #[serde(default)]
+    pub user_defined_cloudaicompanion_project: bool,
+}
+
+/// The project requests are billed to, and the tier it belongs to.
+#[derive(Debug, Clone)]
+pub struct CodeAssistSetup {
+    pub project_id: String,
+    pub tier: Option<UserTier>,
+}
+
+/// `cloudaicompanionProject` is a bare id in `loadCodeAssist` and an object in `onboardUser`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ProjectRef {
+    Id(String),
+    Object { id: Option<String> },
+}
+
+impl ProjectRef {
+    fn id(self) -> Option<String> {
+        match self {
+            ProjectRef::Id(id) => Some(id),
+            ProjectRef::Object { id } => id,
+        }
+        .filter(|id| !id.is_empty())
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LoadCodeAssistResponse {
+    current_tier: Option<UserTier>,
+    #[serde(default)]
+    allowed_tiers: Vec<UserTier>,
+    cloudaicompanion_project: Option<ProjectRef>,
+}
+
+#[derive(Deserialize)]
+struct OnboardOperation {
+    #[serde(default)]
+    done: bool,
+    response: Option<OnboardResult>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OnboardResult {
+    cloudaicompanion_project: Option<ProjectRef>,
+}
+
+/// Request body with the client metadata, and the project when there is one.
+fn request_body(project: Option<&str>, tier_id: Option<&str>) -> serde_json::Value {
+    let mut metadata = json!({
+        "ideType": "IDE_UNSPECIFIED",
+        "platform": "PLATFORM_UNSPECIFIED",
+        "pluginType": "GEMINI",
+    });
+    let mut body = json!({});
+    if let Some(project) = project {
+        metadata["duetProject"] = json!(project);
+        body["cloudaicompanionProject"] = json!(project);
+    }
+    if let Some(tier_id) = tier_id {
+        body["tierId"] = json!(tier_id);
+    }
+    body["metadata"] = metadata;
+    body
+}
+
+/// POST a `v1internal` method, failing over across endpoints on 429 / 5xx.
+async fn call(
+    client: &Client,
+    endpoints: &[String],
+    method: &str,
+    token: &str,
+    body: &serde_json::Value,
+) -> Result<serde_json::Value, ProviderError> {
+    let mut last_err = ProviderError::Other("No Cloud Code Assist endpoint configured".into());
+    for endpoint in endpoints {
+        let resp = client
+            .post(format!("{}/v1internal:{}", endpoint, method))
+            .bearer_auth(token)
+            .json(body)
+            .send()
+            .await;
+
+        let resp = match resp {
+            Ok(r) => r,
+            Err(e) => {
+                tracing::warn!("Cloud Code Assist {} unreachable at {}: {}", method, endpoint, e);
+                last_err = ProviderError::Network(e);
+                continue;
+            }
+        };
+
+        let status = resp.status();
+        if status.is_success() {
+            return Ok(resp.json().await?);
+        }
+
+        let err = ProviderError::from_response(resp).await;
+        if !should_fail_over(status.as_u16()) {
+            return Err(err);
+        }
+        tracing::warn!("Cloud Code Assist {} failed at {}: {}", method, endpoint, err);
+        last_err = err;
+    }
+    Err(last_err)
+}
+
+/// Find the account's Cloud Code Assist project, onboarding the user if needed.
+///
+/// `project_hint` is the user's own Cloud project (`GOOGLE_CLOUD_PROJECT`); paid
+/// tiers require one, the free tier ignores it.
+pub async fn setup_user(
+    client: &Client,
+    endpoints: &[String],
+    token: &str,
+    project_hint: Option<&str>,
+) -> Result<CodeAssistSetup, ProviderError> {
+    let load = call(
+        client,
+        endpoints,
+        "loadCodeAssist",
+        token,
+        &request_body(project_hint, None),
+    )
+    .await?;
+    let load: LoadCodeAssistResponse = serde_json::from_value(load)?;
+
+    // Already onboarded
+    if let Some(tier) = load.current_tier {
+        let project_id = load
+            .cloudaicompanion_project
+            .and_then(ProjectRef::id)
+            .or_else(|| project_hint.map(String::from));
+        return match project_id {
+            Some(project_id) => Ok(CodeAssistSetup {
+                project_id,
+                tier: Some(tier),
+            }),
+            None => Err(ProviderError::Other(format!(
+                "Cloud Code Assist tier {} needs a Google Cloud project; set GOOGLE_CLOUD_PROJECT",
+                tier.id
+            ))),
+        };
+    }
+
+    let tier = load
+        .allowed_tiers
+        .into_iter()
+        .find(|t| t.is_default)
+        .unwrap_or(UserTier {
+            id: FREE_TIER.into(),
+            name: None,
+            is_default: true,
+            user_defined_cloudaicompanion_project: false,
+        });
+
+    // The free tier provisions a managed project and rejects a user-supplied one
+    let onboard_project = if tier.id == FREE_TIER {
+        None
+    } else if tier.user_defined_cloudaicompanion_project && project_hint.is_none() {
+        return Err(ProviderError::Other(format!(
+            "Cloud Code Assist tier {} needs a Google Cloud project; set GOOGLE_CLOUD_PROJECT",
+            tier.id
+        )));
+    } else {
+        project_hint
+    };
+
+    let body = request_body(onboard_project, Some(&tier.id));
+
+    // onboardUser is a long-running operation; repeating the call polls it
+    for attempt in 0..ONBOARD_ATTEMPTS {
+        if attempt > 0 {
+            tokio::time::sleep(ONBOARD_POLL_INTERVAL).await;
+        }
+        let op: OnboardOperation =
+            serde_json::from_value(call(client, endpoints, "onboardUser", token, &body).await?)?;
+        if !op.done {
+            continue;
+        }
+
+        let project_id = op
+            .response
+            .and_then(|r| r.cloudaicompanion_project)
+            .and_then(ProjectRef::id)
+            .or_else(|| onboard_project.map(String::from));
+        return match project_id {
+            Some(project_id) => Ok(CodeAssistSetup {
+                project_id,
+                tier: Some(tier),
+            }),
+            None => Err(ProviderError::Other(
+                "Cloud Code Assist onboarding returned no project".into(),
+            )),
+        };
+    }
+
+    Err(ProviderError::Other(
+        "Timed out waiting for Cloud Code Assist onboarding".into(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::test_server::{FakeServer, Response};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn endpoints_start_with_the_model_endpoint() {
+        assert_eq!(endpoints(DAILY_ENDPOINT), vec![DAILY_ENDPOINT, PROD_ENDPOINT, AUTOPUSH_ENDPOINT]);
+        assert_eq!(endpoints(""), vec![PROD_ENDPOINT, DAILY_ENDPOINT, AUTOPUSH_ENDPOINT]);
+    }
+
+    #[test]
+    fn session_ids_follow_the_conversation() {
+        let id = session_id("conv-1");
+        assert_eq!(id, session_id("conv-1"));
+        assert_ne!(id, session_id("conv-2"));
+        let uuid = Uuid::parse_str(&id).unwrap();
+        assert_eq!(uuid.get_version(), Some(uuid::Version::Sha1));
+    }
+
+    #[test]
+    fn stand_in_endpoint_is_used_alone() {
+        assert_eq!(endpoints("http://127.0.0.1:9999/"), vec!["http://127.0.0.1:9999"]);
+    }
+
+    #[tokio::test]
+    async fn setup_finds_onboarded_project() {
+        let server = FakeServer::start(|req| {
+            assert_eq!(req.method, "POST");
+            assert!(req.target.ends_with("/v1internal:loadCodeAssist"), "{}", req.target);
+            assert_eq!(req.headers["authorization"], "Bearer tok");
+            Response::json(200, json!({
+                "currentTier": {"id": "standard-tier", "name": "Standard"},
+                "cloudaicompanionProject": "proj-1",
+            }))
+        })
+        .await;
+
+        let setup = setup_user(&Client::new(), std::slice::from_ref(&server.url), "tok", None).await.unwrap();
+        assert_eq!(setup.project_id, "proj-1");
+        assert_eq!(setup.tier.unwrap().id, "standard-tier");
+    }
+
+    #[tokio::test]
+    async fn setup_polls_onboarding_until_done() {
+        let polls = Arc::new(AtomicUsize::new(0));
+        let counter = polls.clone();
+        let server = FakeServer::start(move |req| {
+            if req.target.ends_with(":loadCodeAssist") {
+                return Response::json(200, json!({"allowedTiers": [{"id": "free-tier", "isDefault": true}]}));
+            }
+            match counter.fetch_add(1, Ordering::SeqCst) {
+                0 => Response::json(200, json!({"done": false})),
+                _ => Response::json(200, json!({
+                    "done": true,
+                    "response": {"cloudaicompanionProject": {"id": "managed-1"}},
+                })),
+            }
+        })
+        .await;
+
+        let setup = setup_user(&Client::new(), std::slice::from_ref(&server.url), "tok", Some("my-project")).await.unwrap();
+        assert_eq!(setup.project_id, "managed-1");
+        assert_eq!(polls.load(Ordering::SeqCst), 2);
+
+        // The free tier is onboarded without the user's project
+        let onboard = server.requests().into_iter().find(|r| r.target.ends_with(":onboardUser")).unwrap().json();
+        assert_eq!(onboard["tierId"], "free-tier");
+        assert!(onboard.get("cloudaicompanionProject").is_none());
+    }
+
+    #[tokio::test]
+    async fn setup_fails_over_on_server_errors() {
+        let down = FakeServer::start(|_| Response::json(503, json!({"error": "unavailable"}))).await;
+        let up = FakeServer::start(|_| {
+            Response::json(200, json!({"currentTier": {"id": "free-tier"}, "cloudaicompanionProject": "proj-2"}))
+        })
+        .await;
+
+        let endpoints = [down.url.clone(), up.url.clone()];
+        let setup = setup_user(&Client::new(), &endpoints, "tok", None).await.unwrap();
+        assert_eq!(setup.project_id, "proj-2");
+        assert_eq!(down.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn setup_does_not_fail_over_on_client_errors() {
+        let denied = FakeServer::start(|_| Response::json(403, json!({"error": "denied"}))).await;
+        let up = FakeServer::start(|_| Response::json(200, json!({}))).await;
+
+        let endpoints = [denied.url.clone(), up.url.clone()];
+        let err = setup_user(&Client::new(), &endpoints, "tok", None).await.unwrap_err();
+        assert!(matches!(err, ProviderError::Http { status: 403, .. }), "{}", err);
+        assert!(up.requests().is_empty());
+    }
+}
@@ -0,0 +1,414 @@
+use super::openai::OpenAiProvider;
+use super::{Provider, ProviderError};
+use crate::models::catalog::{DiscoveredModel, merge_discovered};
+use crate::types::*;
+use async_trait::async_trait;
+use futures::stream::{self, BoxStream};
+use reqwest::Client;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub const EDITOR_VERSION: &str = "vscode/1.104.1";
+pub const EDITOR_PLUGIN_VERSION: &str = "copilot-chat/0.35.0";
+pub const USER_AGENT: &str = "GitHubCopilotChat/0.35.0";
+const INTEGRATION_ID: &str = "vscode-chat";
+
+/// GitHub Copilot chat provider.
+///
+/// Copilot serves OpenAI, Claude and Gemini models behind one OpenAI-compatible
+/// API, on an endpoint that depends on the account's plan. The credential is the
+/// Copilot token, or JSON `{"token", "endpoint"}` when the token exchange
+/// reported the endpoint.
+pub struct GitHubCopilotProvider {
+    client: Client,
+    openai: Arc<OpenAiProvider>,
+}
+
+impl GitHubCopilotProvider {
+    pub fn new() -> Self {
+        Self {
+            client: Client::new(),
+            openai: Arc::new(OpenAiProvider::new()),
+        }
+    }
+}
+
+impl Default for GitHubCopilotProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Headers identifying the client, which Copilot requires on every request.
+pub fn copilot_headers() -> HashMap<String, String> {
+    let mut h = HashMap::new();
+    h.insert("Editor-Version".into(), EDITOR_VERSION.into());
+    h.insert("Editor-Plugin-Version".into(), EDITOR_PLUGIN_VERSION.into());
+    h.insert("Copilot-Integration-Id".into(), INTEGRATION_ID.into());
+    h.insert("User-Agent".into(), USER_AGENT.into());
+    h.insert("Openai-Intent".into(), "conversation-edits".into());
+    h
+}
+
+/// Parse the API key into (token, endpoint).
+fn parse_copilot_api_key(api_key: &str) -> Result<(String, Option<String>), ProviderError> {
+    #[derive(Deserialize)]
+    struct CopilotKey {
+        token: String,
+        #[serde(default)]
+        endpoint: Option<String>,
+    }
+
+    let parsed = if api_key.trim_start().starts_with('{') {
+        serde_json::from_str(api_key).map_err(|_| {
+            ProviderError::AuthRequired(
+                "Invalid GitHub Copilot credentials. Expected JSON {token, endpoint}.".into(),
+            )
+        })?
+    } else {
+        CopilotKey {
+            token: api_key.trim().to_string(),
+            endpoint: None,
+        }
+    };
+
+    if parsed.token.is_empty() {
+        return Err(ProviderError::AuthRequired(
+            "Missing token in GitHub Copilot credentials.".into(),
+        ));
+    }
+
+    let endpoint = parsed
+        .endpoint
+        .filter(|e| !e.is_empty())
+        .or_else(|| endpoint_from_token(&parsed.token));
+    Ok((parsed.token, endpoint))
+}
+
+/// The API endpoint named in the token itself (`proxy-ep=proxy.<host>`), for
+/// credentials stored before the endpoint was recorded.
+fn endpoint_from_token(token: &str) -> Option<String> {
+    token
+        .split(';')
+        .find_map(|field| field.strip_prefix("proxy-ep="))
+        .map(|host| format!("https://{}", host.replacen("proxy.", "api.", 1)))
+}
+
+/// Copilot bills requests the user typed differently from the follow-ups an
+/// agent makes on its own, such as sending tool results back.
+fn initiator(context: &ChatContext) -> &'static str {
+    match context.messages.last() {
+        Some(Message::User(_)) | None => "user",
+        _ => "agent",
+    }
+}
+
+fn has_images(context: &ChatContext) -> bool {
+    context.messages.iter().any(|m| {
+        let content = match m {
+            Message::User(u) => &u.content,
+            Message::Assistant(a) => &a.content,
+            Message::ToolResult(t) => &t.content,
+        };
+        content.iter().any(|b| matches!(b, ContentBlock::Image(_)))
+    })
+}
+
+// ---------------------------------------------------------------------------
+// Models list response
+// ---------------------------------------------------------------------------
+
+#[derive(Deserialize)]
+struct ModelsResponse {
+    data: Vec<CopilotModel>,
+}
+
+#[derive(Deserialize)]
+struct CopilotModel {
+    id: String,
+    name: Option<String>,
+    #[serde(default)]
+    model_picker_enabled: bool,
+    capabilities: Option<Capabilities>,
+    policy: Option<Policy>,
+    supported_endpoints: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+struct Capabilities {
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    limits: Option<Limits>,
+    supports: Option<Supports>,
+}
+
+#[derive(Deserialize)]
+struct Limits {
+    max_context_window_tokens: Option<u64>,
+    max_output_tokens: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct Supports {
+    #[serde(default)]
+    vision: bool,
+    #[serde(default)]
+    reasoning_effort: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct Policy {
+    state: String,
+}
+
+impl CopilotModel {
+    /// Chat models the account can use through `/chat/completions`.
+    fn is_usable(&self) -> bool {
+        let chat = self
+            .capabilities
+            .as_ref()
+            .and_then(|c| c.kind.as_deref())
+            .is_some_and(|k| k == "chat");
+        let enabled = self.policy.as_ref().is_none_or(|p| p.state != "disabled");
+        let completions = self
+            .supported_endpoints
+            .as_ref()
+            .is_none_or(|e| e.iter().any(|e| e == "/chat/completions"));
+        chat && enabled && completions && self.model_picker_enabled
+    }
+
+    fn into_discovered(self) -> DiscoveredModel {
+        let limits = self.capabilities.as_ref().and_then(|c| c.limits.as_ref());
+        let supports = self.capabilities.as_ref().and_then(|c| c.supports.as_ref());
+        DiscoveredModel {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            reasoning: supports.map(|s| !s.reasoning_effort.is_empty()),
+            input: supports.map(|s| {
+                if s.vision {
+                    vec![InputModality::Text, InputModality::Image]
+                } else {
+                    vec![InputModality::Text]
+                }
+            }),
+            cost: None,
+            context_window: limits.and_then(|l| l.max_context_window_tokens),
+            max_tokens: limits.and_then(|l| l.max_output_tokens),
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Provider impl
+// ---------------------------------------------------------------------------
+
+#[async_trait]
+impl Provider for GitHubCopilotProvider {
+    fn stream(
+        &self,
+        model: &ModelDef,
+        context: &ChatContext,
+        options: &StreamOptions,
+    ) -> BoxStream<'static, Result<StreamEvent, ProviderError>> {
+        let api_key = options.api_key.clone().unwrap_or_default();
+        let (token, endpoint) = match parse_copilot_api_key(&api_key) {
+            Ok(parsed) => parsed,
+            Err(e) => return Box::pin(stream::once(async move { Err(e) })),
+        };
+
+        let mut model = model.clone();
+        if let Some(endpoint) = endpoint {
+            model.base_url = endpoint;
+        }
+
+        let mut options = options.clone();
+        options.api_key = Some(token);
+        let headers = options.extra_headers.get_or_insert_with(HashMap::new);
+        for (k, v) in copilot_headers() {
+            headers.entry(k).or_insert(v);
+        }
+        headers.insert("X-Initiator".into(), initiator(context).into());
+        if has_images(context) {
+            headers.insert("Copilot-Vision-Request".into(), "true".into());
+        }
+
+        self.openai.stream(&model, context, &options)
+    }
+
+    async fn list_models(
+        &self,
+        provider: &str,
+        base_url: &str,
+        api_key: &str,
+    ) -> Result<Vec<ModelDef>, ProviderError> {
+        let (token, endpoint) = parse_copilot_api_key(api_key)?;
+        let base_url = endpoint.unwrap_or_else(|| base_url.to_string());
+        let base_url = base_url.trim_end_matches('/');
+
+        let mut req = self.client.get(format!("{}/models", base_url)).bearer_auth(&token);
+        for (k, v) in copilot_headers() {
+            req = req.header(k, v);
+        }
+        let resp = req.send().await?;
+
+        let status = resp.status();
+        if !status.is_success() {
+            return Err(ProviderError::from_response(resp).await);
+        }
+
+        let models: ModelsResponse = resp.json().await?;
+        let discovered = models
+            .data
+            .into_iter()
+            .filter(CopilotModel::is_usable)
+            .map(CopilotModel::into_discovered)
+            .collect();
+
+        Ok(merge_discovered(
+            provider,
+            base_url,
+            Api::OpenaiCompletions,
+            discovered,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::test_server::{FakeServer, Response};
+    use futures::StreamExt;
+    use serde_json::json;
+
+    fn model(base_url: &str) -> ModelDef {
+        ModelDef {
+            id: "gpt-4.1".into(),
+            name: "GPT-4.1".into(),
+            api: Api::OpenaiCompletions,
+            provider: "github-copilot".into(),
+            base_url: base_url.into(),
+            reasoning: false,
+            input: vec![InputModality::Text, InputModality::Image],
+            cost: ModelCost::default(),
+            context_window: 128_000,
+            max_tokens: 16_384,
+            headers: None,
+            compat: None,
+        }
+    }
+
+    fn user(content: Vec<ContentBlock>) -> Message {
+        Message::User(UserMessage { content })
+    }
+
+    fn text(text: &str) -> ContentBlock {
+        ContentBlock::Text(TextContent { text: text.into(), thought_signature: None })
+    }
+
+    fn context(messages: Vec<Message>) -> ChatContext {
+        ChatContext { system_prompt: None, messages, tools: Vec::new(), hosted_tools: Vec::new() }
+    }
+
+    #[test]
+    fn api_keys_are_a_token_or_token_and_endpoint() {
+        let (token, endpoint) = parse_copilot_api_key(r#"{"token": "tid=1;exp=2", "endpoint": "https://api.business.githubcopilot.com"}"#).unwrap();
+        assert_eq!(token, "tid=1;exp=2");
+        assert_eq!(endpoint.as_deref(), Some("https://api.business.githubcopilot.com"));
+
+        // The endpoint named in the token stands in when none was recorded
+        let (token, endpoint) = parse_copilot_api_key("tid=1;exp=2;proxy-ep=proxy.individual.githubcopilot.com;sku=free").unwrap();
+        assert_eq!(token, "tid=1;exp=2;proxy-ep=proxy.individual.githubcopilot.com;sku=free");
+        assert_eq!(endpoint.as_deref(), Some("https://api.individual.githubcopilot.com"));
+        let (_, endpoint) = parse_copilot_api_key(r#"{"token": "tid=1;proxy-ep=proxy.enterprise.githubcopilot.com", "endpoint": ""}"#).unwrap();
+        assert_eq!(endpoint.as_deref(), Some("https://api.enterprise.githubcopilot.com"));
+        assert_eq!(parse_copilot_api_key("tid=1;exp=2").unwrap().1, None);
+
+        assert!(matches!(parse_copilot_api_key(r#"{"token": ""}"#), Err(ProviderError::AuthRequired(_))));
+        assert!(matches!(parse_copilot_api_key("{not json"), Err(ProviderError::AuthRequired(_))));
+    }
+
+    #[test]
+    fn follow_ups_are_initiated_by_the_agent() {
+        assert_eq!(initiator(&context(Vec::new())), "user");
+        assert_eq!(initiator(&context(vec![user(vec![text("Hi")])])), "user");
+        let tool_result = Message::ToolResult(ToolResultMessage {
+            tool_call_id: "call_1".into(),
+            tool_name: "read".into(),
+            content: vec![text("file contents")],
+            is_error: false,
+        });
+        assert_eq!(initiator(&context(vec![user(vec![text("Hi")]), tool_result])), "agent");
+    }
+
+    #[tokio::test]
+    async fn requests_carry_the_copilot_headers_and_go_to_the_token_endpoint() {
+        let server = FakeServer::start(|_| Response::json(400, json!({"error": {"message": "stop"}}))).await;
+        let image = ContentBlock::Image(ImageContent { data: "aGVsbG8=".into(), mime_type: "image/png".into() });
+        let options = StreamOptions {
+            api_key: Some(json!({"token": "tid=1", "endpoint": server.url}).to_string()),
+            ..Default::default()
+        };
+
+        let provider = GitHubCopilotProvider::new();
+        let with_image = context(vec![user(vec![text("What is this?"), image])]);
+        let _: Vec<_> = provider.stream(&model("https://unused.example"), &with_image, &options).collect().await;
+        let _: Vec<_> = provider.stream(&model("https://unused.example"), &context(vec![user(vec![text("Hi")])]), &options).collect().await;
+
+        let requests = server.requests();
+        assert_eq!(requests[0].target, "/chat/completions");
+        let headers = &requests[0].headers;
+        assert_eq!(headers["authorization"], "Bearer tid=1");
+        assert_eq!(headers["editor-version"], EDITOR_VERSION);
+        assert_eq!(headers["copilot-integration-id"], INTEGRATION_ID);
+        assert_eq!(headers["x-initiator"], "user");
+        assert_eq!(headers["copilot-vision-request"], "true");
+        assert!(!requests[1].headers.contains_key("copilot-vision-request"));
+    }
+
+    #[tokio::test]
+    async fn discovery_lists_the_usable_chat_models() {
+        let server = FakeServer::start(|_| {
+            Response::json(200, json!({"data": [
+                {
+                    "id": "gpt-4.1", "name": "GPT-4.1", "model_picker_enabled": true,
+                    "capabilities": {"type": "chat", "limits": {"max_context_window_tokens": 128000, "max_output_tokens": 16384},
+                        "supports": {"vision": true}},
+                    "policy": {"state": "enabled"},
+                },
+                {
+                    "id": "claude-sonnet-4", "name": "Claude Sonnet 4", "model_picker_enabled": true,
+                    "capabilities": {"type": "chat", "supports": {"reasoning_effort": ["low", "high"]}},
+                    "supported_endpoints": ["/chat/completions", "/v1/messages"],
+                },
+                // Not enabled by the organization
+                {"id": "gemini-2.5-pro", "model_picker_enabled": true, "capabilities": {"type": "chat"}, "policy": {"state": "disabled"}},
+                // Hidden from the picker
+                {"id": "gpt-4o-mini", "model_picker_enabled": false, "capabilities": {"type": "chat"}},
+                {"id": "text-embedding-3-small", "model_picker_enabled": true, "capabilities": {"type": "embeddings"}},
+                // Responses API only
+                {"id": "gpt-5-codex", "model_picker_enabled": true, "capabilities": {"type": "chat"}, "supported_endpoints": ["/responses"]},
+            ]}))
+        })
+        .await;
+
+        let models = GitHubCopilotProvider::new()
+            .list_models("github-copilot", "https://unused.example", &json!({"token": "tid=1", "endpoint": server.url}).to_string())
+            .await
+            .unwrap();
+        let mut ids: Vec<_> = models.iter().map(|m| m.id.as_str()).collect();
+        ids.sort();
+        assert_eq!(ids, ["claude-sonnet-4", "gpt-4.1"]);
+
+        let gpt = models.iter().find(|m| m.id == "gpt-4.1").unwrap();
+        assert_eq!((gpt.context_window, gpt.max_tokens), (128_000, 16_384));
+        assert!(gpt.input.contains(&InputModality::Image));
+        assert!(models.iter().find(|m| m.id == "claude-sonnet-4").unwrap().reasoning);
+        assert!(models.iter().all(|m| m.base_url == server.url));
+
+        let request = &server.requests()[0];
+        assert_eq!(request.target, "/models");
+        assert_eq!(request.headers["authorization"], "Bearer tid=1");
+        assert_eq!(request.headers["editor-version"], EDITOR_VERSION);
+    }
+}
@@ -1,22 +1,35 @@
-use super::{Provider, ProviderError};
+use super::{Provider, ProviderError, retry_after_ms};
 use crate::types::*;
 use async_trait::async_trait;
-use futures::stream::{self, BoxStream, StreamExt};
+use futures::stream::{BoxStream, StreamExt};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
 
 /// Google Generative AI (Gemini API key) provider.
 pub struct GoogleProvider {
     client: Client,
+    /// `cachedContents` by prompt hash; `None` when context caching is off.
+    caches: Option<Arc<Mutex<HashMap<String, CacheEntry>>>>,
 }
 
 impl GoogleProvider {
     pub fn new() -> Self {
         Self {
             client: Client::new(),
+            caches: Some(Arc::new(Mutex::new(HashMap::new()))),
         }
     }
+
+    /// Always send the system prompt and tools inline.
+    pub fn without_context_cache(mut self) -> Self {
+        self.caches = None;
+        self
+    }
 }
 
 impl Default for GoogleProvider {
@@ -39,6 +52,13 @@ struct GenerateContentRequest {
     generation_config: Option<GenerationConfig>,
     #[serde(skip_serializing_if = "Option::is_none")]
     tools: Option<Vec<ToolDeclaration>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_config: Option<ToolConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    safety_settings: Option<Vec<SafetySetting>>,
+    /// Replaces `system_instruction`, `tools` and `tool_config`, which may not be sent alongside it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cached_content: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -58,6 +78,10 @@ struct Part {
     function_response: Option<FunctionResponsePart>,
     #[serde(skip_serializing_if = "Option::is_none")]
     inline_data: Option<InlineData>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thought: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thought_signature: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -103,10 +127,14 @@ struct ThinkingConfig {
     thinking_budget: Option<u64>,
 }
 
+/// One entry of `tools`: either the function declarations or a single built-in tool.
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
 struct ToolDeclaration {
-    function_declarations: Vec<FunctionDeclaration>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    function_declarations: Option<Vec<FunctionDeclaration>>,
+    #[serde(flatten)]
+    builtin: BuiltinTool,
 }
 
 #[derive(Serialize)]
@@ -125,6 +153,7 @@ struct FunctionDeclaration {
 struct StreamChunk {
     candidates: Option<Vec<Candidate>>,
     usage_metadata: Option<UsageMetadata>,
+    prompt_feedback: Option<PromptFeedback>,
 }
 
 #[derive(Deserialize)]
@@ -132,6 +161,11 @@ struct StreamChunk {
 struct Candidate {
     content: Option<CandidateContent>,
     finish_reason: Option<String>,
+    grounding_metadata: Option<GroundingMetadata>,
+    citation_metadata: Option<CitationMetadata>,
+    url_context_metadata: Option<UrlContextMetadata>,
+    #[serde(default)]
+    safety_ratings: Vec<SafetyRating>,
 }
 
 #[derive(Deserialize)]
@@ -144,13 +178,10 @@ struct CandidateContent {
 struct ResponsePart {
     text: Option<String>,
     thought: Option<bool>,
+    thought_signature: Option<String>,
     function_call: Option<FunctionCallResponse>,
-}
-
-#[derive(Deserialize)]
-struct FunctionCallResponse {
-    name: String,
-    args: Option<serde_json::Value>,
+    executable_code: Option<ExecutableCodePart>,
+    code_execution_result: Option<CodeExecutionResultPart>,
 }
 
 #[derive(Deserialize)]
@@ -163,6 +194,856 @@ struct UsageMetadata {
     cached_content_token_count: Option<u64>,
 }
 
+// ---------------------------------------------------------------------------
+// Thought signatures (shared with the Cloud Code Assist provider)
+// ---------------------------------------------------------------------------
+
+/// Placeholder Gemini accepts in place of a signature it did not issue.
+const SKIP_SIGNATURE_VALIDATOR: &str = "skip_thought_signature_validator";
+
+/// Whether signatures in an assistant message can be replayed to `model_id`.
+/// Signatures only validate on the model that produced them.
+pub(crate) fn signatures_valid_for(msg: &AssistantMessage, model_id: &str) -> bool {
+    msg.model == model_id
+}
+
+/// Signature to send with a replayed function call. Gemini 3 rejects unsigned
+/// calls in the current turn, so calls from elsewhere get the placeholder.
+pub(crate) fn function_call_signature(model_id: &str, signature: Option<&String>) -> Option<String> {
+    match signature {
+        Some(sig) => Some(sig.clone()),
+        None if model_id.contains("gemini-3") => Some(SKIP_SIGNATURE_VALIDATOR.into()),
+        None => None,
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Function calls (shared with the Cloud Code Assist provider)
+// ---------------------------------------------------------------------------
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ToolConfig {
+    function_calling_config: FunctionCallingConfig,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FunctionCallingConfig {
+    stream_function_call_arguments: bool,
+}
+
+/// Asks for function-call arguments to be streamed. Only Gemini 3 models support
+/// it; older ones send each call whole.
+pub(crate) fn tool_config(model_id: &str, tools: &[ToolDef]) -> Option<ToolConfig> {
+    if tools.is_empty() || !model_id.contains("gemini-3") {
+        return None;
+    }
+    Some(ToolConfig {
+        function_calling_config: FunctionCallingConfig {
+            stream_function_call_arguments: true,
+        },
+    })
+}
+
+/// A `functionCall` part. A streamed call sends its name first, then its
+/// arguments as `partialArgs` in later parts until `willContinue` is unset.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct FunctionCallResponse {
+    id: Option<String>,
+    name: Option<String>,
+    args: Option<serde_json::Value>,
+    #[serde(default)]
+    partial_args: Vec<PartialArg>,
+    #[serde(default)]
+    will_continue: bool,
+}
+
+/// One argument value, addressed by a JSON path such as `$.items[0].name`.
+/// Long strings arrive in pieces, each continuing the previous one; a `nullValue`
+/// sets none of the fields.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PartialArg {
+    json_path: String,
+    string_value: Option<String>,
+    number_value: Option<serde_json::Number>,
+    bool_value: Option<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+impl PathSegment {
+    fn opener(&self) -> char {
+        match self {
+            PathSegment::Key(_) => '{',
+            PathSegment::Index(_) => '[',
+        }
+    }
+
+    fn closer(&self) -> char {
+        match self {
+            PathSegment::Key(_) => '}',
+            PathSegment::Index(_) => ']',
+        }
+    }
+}
+
+fn parse_json_path(path: &str) -> Vec<PathSegment> {
+    let mut segments = Vec::new();
+    let mut rest = path.strip_prefix('$').unwrap_or(path);
+    while !rest.is_empty() {
+        if let Some(r) = rest.strip_prefix('.') {
+            let end = r.find(['.', '[']).unwrap_or(r.len());
+            segments.push(PathSegment::Key(r[..end].to_string()));
+            rest = &r[end..];
+        } else if let Some(r) = rest.strip_prefix('[') {
+            let end = r.find(']').unwrap_or(r.len());
+            let inner = &r[..end];
+            segments.push(match inner.parse() {
+                Ok(i) => PathSegment::Index(i),
+                Err(_) => PathSegment::Key(inner.trim_matches(['\'', '"']).to_string()),
+            });
+            rest = r.get(end + 1..).unwrap_or("");
+        } else {
+            break;
+        }
+    }
+    segments
+}
+
+/// Arguments of a call still being streamed, written out as JSON text as
+/// they arrive so the deltas concatenate to the final arguments.
+struct StreamingArgs {
+    index: usize,
+    json: String,
+    /// Path of the value written last
+    path: Vec<PathSegment>,
+    in_string: bool,
+    /// Complete arguments, when the server sends them as well
+    args: Option<serde_json::Value>,
+}
+
+impl StreamingArgs {
+    fn new(index: usize) -> Self {
+        Self {
+            index,
+            json: String::new(),
+            path: Vec::new(),
+            in_string: false,
+            args: None,
+        }
+    }
+
+    /// Write one value, returning the text appended.
+    fn write(&mut self, arg: &PartialArg) -> String {
+        let start = self.json.len();
+        let path = parse_json_path(&arg.json_path);
+        if path.is_empty() {
+            return String::new();
+        }
+
+        let continues = self.in_string && path == self.path && arg.string_value.is_some();
+        if !continues {
+            if self.in_string {
+                self.json.push('"');
+                self.in_string = false;
+            }
+
+            // Close the containers the new value is outside of, then open its own
+            let common = if self.path.is_empty() {
+                self.json.push(path[0].opener());
+                0
+            } else {
+                let shared = self.path.iter().zip(&path).take_while(|(a, b)| a == b).count();
+                let common = shared.min(self.path.len() - 1).min(path.len() - 1);
+                for segment in self.path[common + 1..].iter().rev() {
+                    self.json.push(segment.closer());
+                }
+                self.json.push(',');
+                common
+            };
+            for (i, segment) in path.iter().enumerate().skip(common) {
+                if let PathSegment::Key(key) = segment {
+                    self.json.push_str(&json!(key).to_string());
+                    self.json.push(':');
+                }
+                if let Some(next) = path.get(i + 1) {
+                    self.json.push(next.opener());
+                }
+            }
+        }
+
+        if let Some(text) = &arg.string_value {
+            if !continues {
+                self.json.push('"');
+                self.in_string = true;
+            }
+            let escaped = json!(text).to_string();
+            self.json.push_str(&escaped[1..escaped.len() - 1]);
+        } else if let Some(n) = &arg.number_value {
+            self.json.push_str(&n.to_string());
+        } else if let Some(b) = arg.bool_value {
+            self.json.push_str(if b { "true" } else { "false" });
+        } else {
+            self.json.push_str("null");
+        }
+
+        self.path = path;
+        self.json[start..].to_string()
+    }
+
+    /// Close everything still open, returning the text appended and the arguments.
+    fn close(mut self) -> (String, serde_json::Value) {
+        let start = self.json.len();
+        if self.in_string {
+            self.json.push('"');
+        }
+        if self.path.is_empty() {
+            self.json.push_str("{}");
+        }
+        for segment in self.path.iter().rev() {
+            self.json.push(segment.closer());
+        }
+
+        let args = self
+            .args
+            .take()
+            .or_else(|| serde_json::from_str(&self.json).ok())
+            .unwrap_or(json!({}));
+        (self.json[start..].to_string(), args)
+    }
+}
+
+/// Collects the tool calls of a response and the events announcing them.
+#[derive(Default)]
+pub(crate) struct FunctionCalls {
+    calls: Vec<ToolCall>,
+    streaming: Option<StreamingArgs>,
+}
+
+impl FunctionCalls {
+    /// Handle one `functionCall` part.
+    pub(crate) fn push(&mut self, fc: &FunctionCallResponse, signature: Option<&String>) -> Vec<StreamEvent> {
+        let mut events = Vec::new();
+
+        if let Some(name) = &fc.name {
+            events.extend(self.finish());
+
+            let index = self.calls.len();
+            let id = fc
+                .id
+                .clone()
+                .unwrap_or_else(|| format!("call_{}", uuid::Uuid::new_v4().simple()));
+            self.calls.push(ToolCall {
+                id: id.clone(),
+                name: name.clone(),
+                arguments: json!({}),
+                thought_signature: signature.cloned(),
+            });
+            events.push(StreamEvent::ToolCallStart {
+                index,
+                id,
+                name: name.clone(),
+            });
+
+            if !fc.will_continue && fc.partial_args.is_empty() {
+                let args = fc.args.clone().unwrap_or(json!({}));
+                self.calls[index].arguments = args.clone();
+                events.push(StreamEvent::ToolCallDelta {
+                    index,
+                    delta: args.to_string(),
+                });
+                events.push(StreamEvent::ToolCallEnd {
+                    index,
+                    tool_call: self.calls[index].clone(),
+                });
+                return events;
+            }
+            self.streaming = Some(StreamingArgs::new(index));
+        }
+
+        let streaming = match self.streaming.as_mut() {
+            Some(s) => s,
+            None => return events,
+        };
+        let call = &mut self.calls[streaming.index];
+        if call.thought_signature.is_none() {
+            call.thought_signature = signature.cloned();
+        }
+        for arg in &fc.partial_args {
+            let delta = streaming.write(arg);
+            if !delta.is_empty() {
+                events.push(StreamEvent::ToolCallDelta {
+                    index: streaming.index,
+                    delta,
+                });
+            }
+        }
+        if fc.args.is_some() {
+            streaming.args = fc.args.clone();
+        }
+        if !fc.will_continue {
+            events.extend(self.finish());
+        }
+        events
+    }
+
+    /// End the call whose arguments are still streaming, if any.
+    pub(crate) fn finish(&mut self) -> Vec<StreamEvent> {
+        let streaming = match self.streaming.take() {
+            Some(s) => s,
+            None => return Vec::new(),
+        };
+        let index = streaming.index;
+        let (delta, args) = streaming.close();
+        self.calls[index].arguments = args;
+
+        let mut events = Vec::new();
+        if !delta.is_empty() {
+            events.push(StreamEvent::ToolCallDelta { index, delta });
+        }
+        events.push(StreamEvent::ToolCallEnd {
+            index,
+            tool_call: self.calls[index].clone(),
+        });
+        events
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.calls.is_empty()
+    }
+
+    pub(crate) fn into_calls(self) -> Vec<ToolCall> {
+        self.calls
+    }
+}
+
+/// Collects the text, thought and code execution parts of a response. A signature
+/// closes the block it arrived on, so it is replayed with exactly that text.
+#[derive(Default)]
+pub(crate) struct SignedParts {
+    thinking: Vec<ThinkingContent>,
+    /// Text still open for more deltas
+    text: Option<TextContent>,
+    /// Closed text blocks and code execution blocks, in arrival order
+    blocks: Vec<ContentBlock>,
+}
+
+impl SignedParts {
+    /// Handle one part without a function call, returning the delta to emit.
+    pub(crate) fn push(&mut self, text: Option<&String>, thought: bool, signature: Option<&String>) -> Option<StreamEvent> {
+        let delta = text.filter(|t| !t.is_empty());
+        if delta.is_none() && signature.is_none() {
+            return None;
+        }
+        let delta = delta.cloned().unwrap_or_default();
+
+        if thought {
+            if self.thinking.last().is_none_or(|b| b.signature.is_some()) {
+                self.thinking.push(ThinkingContent { thinking: String::new(), signature: None });
+            }
+            let block = self.thinking.last_mut().unwrap();
+            block.thinking.push_str(&delta);
+            block.signature = signature.cloned();
+            (!delta.is_empty()).then_some(StreamEvent::ThinkingDelta(delta))
+        } else {
+            let block = self.text.get_or_insert_with(|| TextContent { text: String::new(), thought_signature: None });
+            block.text.push_str(&delta);
+            if signature.is_some() {
+                block.thought_signature = signature.cloned();
+                self.close_text();
+            }
+            (!delta.is_empty()).then_some(StreamEvent::TextDelta(delta))
+        }
+    }
+
+    /// Handle generated code or its result, placed after the text before it.
+    pub(crate) fn push_block(&mut self, block: ContentBlock) {
+        self.close_text();
+        self.blocks.push(block);
+    }
+
+    fn close_text(&mut self) {
+        if let Some(text) = self.text.take() {
+            self.blocks.push(ContentBlock::Text(text));
+        }
+    }
+
+    /// Thinking blocks, then text and code execution blocks, each in arrival order.
+    pub(crate) fn into_blocks(mut self) -> (Vec<ContentBlock>, Vec<ContentBlock>) {
+        self.close_text();
+        (self.thinking.into_iter().map(ContentBlock::Thinking).collect(), self.blocks)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Built-in tools (shared with the Cloud Code Assist provider)
+// ---------------------------------------------------------------------------
+
+/// A Gemini built-in tool declaration; exactly one field is set per entry.
+#[derive(Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct BuiltinTool {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    google_search: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code_execution: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url_context: Option<serde_json::Value>,
+}
+
+/// Translate hosted tools to Gemini built-ins. Gemini has no per-request search cap.
+pub(crate) fn builtin_tools(hosted: &[HostedTool]) -> Vec<BuiltinTool> {
+    hosted
+        .iter()
+        .map(|t| match t {
+            HostedTool::WebSearch { .. } => BuiltinTool {
+                google_search: Some(json!({})),
+                ..Default::default()
+            },
+            HostedTool::CodeExecution => BuiltinTool {
+                code_execution: Some(json!({})),
+                ..Default::default()
+            },
+            HostedTool::UrlContext => BuiltinTool {
+                url_context: Some(json!({})),
+                ..Default::default()
+            },
+        })
+        .collect()
+}
+
+#[derive(Deserialize)]
+pub(crate) struct ExecutableCodePart {
+    language: Option<String>,
+    code: String,
+}
+
+impl ExecutableCodePart {
+    pub(crate) fn to_block(&self) -> ContentBlock {
+        ContentBlock::ExecutableCode(ExecutableCode {
+            id: None,
+            language: self.language.as_ref().map(|l| l.to_lowercase()),
+            code: self.code.clone(),
+        })
+    }
+}
+
+#[derive(Deserialize)]
+pub(crate) struct CodeExecutionResultPart {
+    outcome: Option<String>,
+    #[serde(default)]
+    output: String,
+}
+
+impl CodeExecutionResultPart {
+    pub(crate) fn to_block(&self) -> ContentBlock {
+        ContentBlock::CodeExecutionResult(CodeExecutionResult {
+            tool_use_id: None,
+            success: self.outcome.as_deref() == Some("OUTCOME_OK"),
+            output: self.output.clone(),
+        })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct UrlContextMetadata {
+    #[serde(default)]
+    url_metadata: Vec<UrlMetadata>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UrlMetadata {
+    retrieved_url: Option<String>,
+    url_retrieval_status: Option<String>,
+}
+
+// ---------------------------------------------------------------------------
+// Safety, grounding and citations (shared with the Cloud Code Assist provider)
+// ---------------------------------------------------------------------------
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct GroundingMetadata {
+    #[serde(default)]
+    grounding_chunks: Vec<GroundingChunk>,
+    #[serde(default)]
+    grounding_supports: Vec<GroundingSupport>,
+    #[serde(default)]
+    web_search_queries: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GroundingChunk {
+    web: Option<GroundingSource>,
+    retrieved_context: Option<GroundingSource>,
+}
+
+#[derive(Deserialize)]
+struct GroundingSource {
+    uri: Option<String>,
+    title: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GroundingSupport {
+    segment: Option<Segment>,
+    #[serde(default)]
+    grounding_chunk_indices: Vec<usize>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Segment {
+    start_index: Option<u64>,
+    end_index: Option<u64>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct CitationMetadata {
+    #[serde(default, alias = "citationSources")]
+    citations: Vec<Citation>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Citation {
+    start_index: Option<u64>,
+    end_index: Option<u64>,
+    uri: Option<String>,
+    title: Option<String>,
+    license: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SafetyRating {
+    category: String,
+    #[serde(default)]
+    blocked: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PromptFeedback {
+    block_reason: Option<String>,
+    #[serde(default)]
+    safety_ratings: Vec<SafetyRating>,
+}
+
+/// Map a Gemini `finishReason` to a stop reason.
+pub(crate) fn finish_stop_reason(reason: &str) -> StopReason {
+    match reason {
+        "STOP" => StopReason::Stop,
+        "MAX_TOKENS" => StopReason::Length,
+        "SAFETY" | "RECITATION" | "PROHIBITED_CONTENT" | "BLOCKLIST" | "SPII" | "IMAGE_SAFETY" => {
+            StopReason::ContentFilter
+        }
+        _ => StopReason::Stop,
+    }
+}
+
+fn blocked_message(prefix: &str, reason: &str, ratings: &[SafetyRating]) -> String {
+    let categories: Vec<&str> = ratings
+        .iter()
+        .filter(|r| r.blocked)
+        .map(|r| r.category.as_str())
+        .collect();
+    if categories.is_empty() {
+        format!("{} blocked: {}", prefix, reason)
+    } else {
+        format!("{} blocked: {} ({})", prefix, reason, categories.join(", "))
+    }
+}
+
+/// Sources and block reasons collected across the chunks of one response.
+#[derive(Default)]
+pub(crate) struct ResponseMetadata {
+    grounding: Vec<CitationSource>,
+    citations: Vec<CitationSource>,
+    search_results: Vec<CitationSource>,
+    fetched: Vec<CitationSource>,
+    queries: Vec<String>,
+    error_message: Option<String>,
+}
+
+impl ResponseMetadata {
+    /// Grounding metadata describes the whole response so far; the latest one wins.
+    pub(crate) fn set_grounding(&mut self, g: &GroundingMetadata) {
+        let source = |c: &GroundingChunk| {
+            c.web
+                .as_ref()
+                .or(c.retrieved_context.as_ref())
+                .map(|s| (s.uri.clone(), s.title.clone()))
+        };
+
+        let mut sources = Vec::new();
+        let mut used = vec![false; g.grounding_chunks.len()];
+        for support in &g.grounding_supports {
+            for &i in &support.grounding_chunk_indices {
+                let (uri, title) = match g.grounding_chunks.get(i).and_then(source) {
+                    Some(s) => s,
+                    None => continue,
+                };
+                used[i] = true;
+                sources.push(CitationSource {
+                    uri,
+                    title,
+                    license: None,
+                    start_index: support.segment.as_ref().and_then(|s| s.start_index),
+                    end_index: support.segment.as_ref().and_then(|s| s.end_index),
+                });
+            }
+        }
+        // Chunks no segment points at are still sources of the answer
+        for (i, chunk) in g.grounding_chunks.iter().enumerate() {
+            if let Some((uri, title)) = source(chunk).filter(|_| !used[i]) {
+                sources.push(CitationSource {
+                    uri,
+                    title,
+                    license: None,
+                    start_index: None,
+                    end_index: None,
+                });
+            }
+        }
+
+        self.grounding = sources;
+        self.search_results = g
+            .grounding_chunks
+            .iter()
+            .filter_map(source)
+            .map(|(uri, title)| CitationSource {
+                uri,
+                title,
+                license: None,
+                start_index: None,
+                end_index: None,
+            })
+            .collect();
+        if !g.web_search_queries.is_empty() {
+            self.queries = g.web_search_queries.clone();
+        }
+    }
+
+    pub(crate) fn add_citations(&mut self, c: &CitationMetadata) {
+        for cit in &c.citations {
+            let source = CitationSource {
+                uri: cit.uri.clone(),
+                title: cit.title.clone(),
+                license: cit.license.clone(),
+                start_index: cit.start_index,
+                end_index: cit.end_index,
+            };
+            if !self.citations.contains(&source) {
+                self.citations.push(source);
+            }
+        }
+    }
+
+    /// URLs fetched by the URL context tool; failed retrievals are left out.
+    pub(crate) fn set_url_context(&mut self, u: &UrlContextMetadata) {
+        self.fetched = u
+            .url_metadata
+            .iter()
+            .filter(|m| m.url_retrieval_status.as_deref() != Some("URL_RETRIEVAL_STATUS_ERROR"))
+            .map(|m| CitationSource {
+                uri: m.retrieved_url.clone(),
+                title: None,
+                license: None,
+                start_index: None,
+                end_index: None,
+            })
+            .collect();
+    }
+
+    /// Record the candidate's finish reason, explaining it when the response was filtered.
+    pub(crate) fn finish(&mut self, reason: &str, ratings: &[SafetyRating]) -> StopReason {
+        let stop = finish_stop_reason(reason);
+        if stop == StopReason::ContentFilter {
+            self.error_message = Some(blocked_message("Response", reason, ratings));
+        }
+        stop
+    }
+
+    /// Record a prompt-level block. Returns true if the prompt was rejected.
+    pub(crate) fn prompt_feedback(&mut self, f: &PromptFeedback) -> bool {
+        match &f.block_reason {
+            Some(reason) => {
+                self.error_message = Some(blocked_message("Prompt", reason, &f.safety_ratings));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Blocks for the final message (search results, then citations), and the
+    /// block explanation if any.
+    pub(crate) fn into_parts(self) -> (Vec<ContentBlock>, Option<String>) {
+        let mut blocks = Vec::new();
+        if !self.search_results.is_empty() {
+            blocks.push(ContentBlock::WebSearchResult(WebSearchResult {
+                tool_use_id: None,
+                query: self.queries.first().cloned(),
+                results: self.search_results,
+                error: None,
+            }));
+        }
+        if !self.fetched.is_empty() {
+            blocks.push(ContentBlock::WebSearchResult(WebSearchResult {
+                tool_use_id: None,
+                query: None,
+                results: self.fetched,
+                error: None,
+            }));
+        }
+
+        let mut sources = self.grounding;
+        sources.extend(self.citations);
+        if !sources.is_empty() || !self.queries.is_empty() {
+            blocks.push(ContentBlock::Citations(CitationContent {
+                sources,
+                queries: self.queries,
+            }));
+        }
+        (blocks, self.error_message)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Context caching
+// ---------------------------------------------------------------------------
+
+/// Prompts estimated below this many tokens are sent inline; smaller caches are rejected.
+const MIN_CACHE_TOKENS: usize = 4096;
+const CACHE_TTL_SECS: i64 = 3600;
+/// Caches this close to expiry are replaced rather than reused.
+const CACHE_EXPIRY_MARGIN_MS: i64 = 60_000;
+/// How long to send inline after a cache could not be created.
+const CACHE_RETRY_MS: i64 = 300_000;
+
+/// A cache created for one system prompt and tool set; `name` is `None` after a
+/// failed creation, until `expires_at`.
+pub(crate) struct CacheEntry {
+    name: Option<String>,
+    expires_at: i64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CreateCachedContentRequest<'a> {
+    model: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system_instruction: Option<&'a SystemInstruction>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<&'a Vec<ToolDeclaration>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_config: Option<&'a ToolConfig>,
+    ttl: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CachedContentResponse {
+    name: String,
+    usage_metadata: Option<CachedContentUsage>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CachedContentUsage {
+    total_token_count: Option<u64>,
+}
+
+/// Key for the cache holding this prompt, or `None` if it is too small to cache.
+fn cache_key(
+    base_url: &str,
+    api_key: &str,
+    model_id: &str,
+    system_instruction: Option<&SystemInstruction>,
+    tools: Option<&Vec<ToolDeclaration>>,
+) -> Option<String> {
+    let prompt = serde_json::to_string(&(system_instruction, tools)).ok()?;
+    // Roughly four characters per token
+    if prompt.len() / 4 < MIN_CACHE_TOKENS {
+        return None;
+    }
+
+    let mut hasher = Sha256::new();
+    for part in [base_url, api_key, model_id, &prompt] {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+    Some(format!("{:x}", hasher.finalize()))
+}
+
+/// Whether a failed request was rejected because its cache is gone.
+fn is_cache_error(status: u16, body: &str) -> bool {
+    matches!(status, 400 | 403 | 404) && body.to_lowercase().contains("cachedcontent")
+}
+
+/// Name of a live cache for the request's system prompt and tools, created if
+/// needed, with the tokens written when it was created by this call.
+///
+/// Holding the lock while creating keeps concurrent requests from creating twice.
+async fn cached_content(
+    client: &Client,
+    caches: &Mutex<HashMap<String, CacheEntry>>,
+    key: &str,
+    base_url: &str,
+    headers: &HashMap<String, String>,
+    create: &CreateCachedContentRequest<'_>,
+) -> Option<(String, u64)> {
+    let mut caches = caches.lock().await;
+    let now = chrono::Utc::now().timestamp_millis();
+    caches.retain(|_, e| e.expires_at > now);
+    if let Some(entry) = caches
+        .get(key)
+        .filter(|e| e.name.is_none() || e.expires_at - CACHE_EXPIRY_MARGIN_MS > now)
+    {
+        return entry.name.clone().map(|name| (name, 0));
+    }
+
+    let mut req = client.post(format!("{}/cachedContents", base_url));
+    for (k, v) in headers {
+        req = req.header(k.as_str(), v.as_str());
+    }
+    let created = match req.json(create).send().await {
+        Ok(resp) if resp.status().is_success() => resp.json::<CachedContentResponse>().await.ok(),
+        Ok(resp) => {
+            let status = resp.status();
+            let body = resp.text().await.unwrap_or_default();
+            tracing::warn!("Could not create Gemini context cache ({}): {}", status, body);
+            None
+        }
+        Err(e) => {
+            tracing::warn!("Could not create Gemini context cache: {}", e);
+            None
+        }
+    };
+
+    let (name, expires_at, written) = match created {
+        Some(c) => (
+            Some(c.name),
+            now + CACHE_TTL_SECS * 1000,
+            c.usage_metadata.and_then(|u| u.total_token_count).unwrap_or(0),
+        ),
+        None => (None, now + CACHE_RETRY_MS, 0),
+    };
+    caches.insert(key.to_string(), CacheEntry { name: name.clone(), expires_at });
+    name.map(|name| (name, written))
+}
+
 // ---------------------------------------------------------------------------
 // Models list response
 // ---------------------------------------------------------------------------
@@ -186,7 +1067,7 @@ struct ModelInfo {
 // Conversion helpers
 // ---------------------------------------------------------------------------
 
-fn convert_messages(context: &ChatContext) -> Vec<Content> {
+fn convert_messages(context: &ChatContext, model_id: &str) -> Vec<Content> {
     let mut contents = Vec::new();
 
     for msg in &context.messages {
@@ -201,6 +1082,8 @@ fn convert_messages(context: &ChatContext) -> Vec<Content> {
                             function_call: None,
                             function_response: None,
                             inline_data: None,
+                            thought: None,
+                            thought_signature: None,
                         }),
                         ContentBlock::Image(img) => Some(Part {
                             text: None,
@@ -210,6 +1093,8 @@ fn convert_messages(context: &ChatContext) -> Vec<Content> {
                                 mime_type: img.mime_type.clone(),
                                 data: img.data.clone(),
                             }),
+                            thought: None,
+                            thought_signature: None,
                         }),
                         _ => None,
                     })
@@ -221,15 +1106,29 @@ fn convert_messages(context: &ChatContext) -> Vec<Content> {
                 });
             }
             Message::Assistant(a) => {
+                let own = signatures_valid_for(a, model_id);
                 let mut parts = Vec::new();
                 for block in &a.content {
                     match block {
+                        // Thought text is only replayed as the carrier of its signature
+                        ContentBlock::Thinking(th) if own && th.signature.is_some() => {
+                            parts.push(Part {
+                                text: Some(th.thinking.clone()),
+                                function_call: None,
+                                function_response: None,
+                                inline_data: None,
+                                thought: Some(true),
+                                thought_signature: th.signature.clone(),
+                            });
+                        }
                         ContentBlock::Text(t) => {
                             parts.push(Part {
                                 text: Some(t.text.clone()),
                                 function_call: None,
                                 function_response: None,
                                 inline_data: None,
+                                thought: None,
+                                thought_signature: t.thought_signature.clone().filter(|_| own),
                             });
                         }
                         ContentBlock::ToolCall(tc) => {
@@ -241,6 +1140,11 @@ fn convert_messages(context: &ChatContext) -> Vec<Content> {
                                 }),
                                 function_response: None,
                                 inline_data: None,
+                                thought: None,
+                                thought_signature: function_call_signature(
+                                    model_id,
+                                    tc.thought_signature.as_ref().filter(|_| own),
+                                ),
                             });
                         }
                         _ => {}
@@ -275,6 +1179,8 @@ fn convert_messages(context: &ChatContext) -> Vec<Content> {
                             response: json!({"result": text}),
                         }),
                         inline_data: None,
+                        thought: None,
+                        thought_signature: None,
                     }],
                 });
             }
@@ -284,25 +1190,34 @@ fn convert_messages(context: &ChatContext) -> Vec<Content> {
     contents
 }
 
-fn convert_tools(tools: &[ToolDef]) -> Vec<ToolDeclaration> {
-    vec![ToolDeclaration {
-        function_declarations: tools
-            .iter()
-            .map(|t| FunctionDeclaration {
-                name: t.name.clone(),
-                description: t.description.clone(),
-                parameters: t.parameters.clone(),
-            })
-            .collect(),
-    }]
+fn convert_tools(tools: &[ToolDef], hosted: &[HostedTool]) -> Vec<ToolDeclaration> {
+    let mut decls = Vec::new();
+    if !tools.is_empty() {
+        decls.push(ToolDeclaration {
+            function_declarations: Some(
+                tools
+                    .iter()
+                    .map(|t| FunctionDeclaration {
+                        name: t.name.clone(),
+                        description: t.description.clone(),
+                        parameters: t.parameters.clone(),
+                    })
+                    .collect(),
+            ),
+            builtin: BuiltinTool::default(),
+        });
+    }
+    decls.extend(builtin_tools(hosted).into_iter().map(|builtin| ToolDeclaration {
+        function_declarations: None,
+        builtin,
+    }));
+    decls
 }
 
 // ---------------------------------------------------------------------------
 // Provider impl
 // ---------------------------------------------------------------------------
 
-static TOOL_CALL_COUNTER: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
-
 #[async_trait]
 impl Provider for GoogleProvider {
     fn stream(
@@ -311,24 +1226,16 @@ impl Provider for GoogleProvider {
         context: &ChatContext,
         options: &StreamOptions,
     ) -> BoxStream<'static, Result<StreamEvent, ProviderError>> {
-        let api_key = match &options.api_key {
-            Some(k) => k.clone(),
-            None => {
-                return Box::pin(stream::once(async {
-                    Err(ProviderError::AuthRequired(
-                        "API key required for Google".into(),
-                    ))
-                }));
-            }
-        };
+        // A missing key means auth is carried in extra headers
+        let api_key = options.api_key.clone().unwrap_or_default();
 
         let base_url = model.base_url.trim_end_matches('/').to_string();
         let url = format!(
-            "{}/models/{}:streamGenerateContent?alt=sse&key={}",
-            base_url, model.id, api_key
+            "{}/models/{}:streamGenerateContent?alt=sse",
+            base_url, model.id
         );
 
-        let contents = convert_messages(context);
+        let contents = convert_messages(context, &model.id);
 
         let system_instruction = context.system_prompt.as_ref().map(|sp| SystemInstruction {
             parts: vec![Part {
@@ -336,6 +1243,8 @@ impl Provider for GoogleProvider {
                 function_call: None,
                 function_response: None,
                 inline_data: None,
+                thought: None,
+                thought_signature: None,
             }],
         });
 
@@ -360,55 +1269,115 @@ impl Provider for GoogleProvider {
             }
         }
 
-        let tools = if context.tools.is_empty() {
+        let tools = if context.tools.is_empty() && context.hosted_tools.is_empty() {
             None
         } else {
-            Some(convert_tools(&context.tools))
+            Some(convert_tools(&context.tools, &context.hosted_tools))
         };
 
-        let body = GenerateContentRequest {
+        let cache_key = self.caches.as_ref().and_then(|_| {
+            cache_key(&base_url, &api_key, &model.id, system_instruction.as_ref(), tools.as_ref())
+        });
+
+        let mut body = GenerateContentRequest {
             contents,
             system_instruction,
             generation_config: Some(gen_config),
             tools,
+            tool_config: tool_config(&model.id, &context.tools),
+            safety_settings: options.safety_settings.clone(),
+            cached_content: None,
         };
 
+        // The key goes in a header so it never ends up in logged or reported URLs
+        let mut headers_map = HashMap::new();
+        if !api_key.is_empty() {
+            headers_map.insert("x-goog-api-key".to_string(), api_key);
+        }
+        if let Some(model_headers) = &model.headers {
+            headers_map.extend(model_headers.clone());
+        }
+        if let Some(extra) = &options.extra_headers {
+            headers_map.extend(extra.clone());
+        }
+
         let client = self.client.clone();
+        let caches = self.caches.clone();
         let model_id = model.id.clone();
         let provider_id = model.provider.clone();
 
         let s = async_stream::stream! {
-            let resp = match client
-                .post(&url)
-                .header("Content-Type", "application/json")
-                .json(&body)
-                .send()
-                .await
-            {
-                Ok(r) => r,
-                Err(e) => {
-                    yield Err(ProviderError::Network(e));
-                    return;
+            // Tokens written to a cache created for this request
+            let mut cache_written = 0;
+            if let (Some(caches), Some(key)) = (&caches, &cache_key) {
+                let create = CreateCachedContentRequest {
+                    model: format!("models/{}", model_id),
+                    system_instruction: body.system_instruction.as_ref(),
+                    tools: body.tools.as_ref(),
+                    tool_config: body.tool_config.as_ref(),
+                    ttl: format!("{}s", CACHE_TTL_SECS),
+                };
+                if let Some((name, written)) =
+                    cached_content(&client, caches, key, &base_url, &headers_map, &create).await
+                {
+                    body.cached_content = Some(name);
+                    cache_written = written;
                 }
-            };
+            }
+            // Kept to resend inline if the cache has gone away
+            let (mut inline_system, mut inline_tools, mut inline_tool_config) = (None, None, None);
+            if body.cached_content.is_some() {
+                inline_system = body.system_instruction.take();
+                inline_tools = body.tools.take();
+                inline_tool_config = body.tool_config.take();
+            }
 
-            let status = resp.status();
-            if !status.is_success() {
+            let resp = loop {
+                let mut req = client.post(&url).header("Content-Type", "application/json");
+                for (k, v) in &headers_map {
+                    req = req.header(k.as_str(), v.as_str());
+                }
+
+                let resp = match req.json(&body).send().await {
+                    Ok(r) => r,
+                    Err(e) => {
+                        yield Err(ProviderError::Network(e));
+                        return;
+                    }
+                };
+
+                let status = resp.status();
+                if status.is_success() {
+                    break resp;
+                }
+
+                let retry_after = retry_after_ms(resp.headers());
                 let body_text = resp.text().await.unwrap_or_default();
-                yield Err(ProviderError::Http {
-                    status: status.as_u16(),
-                    body: body_text,
-                });
+                if body.cached_content.is_some() && is_cache_error(status.as_u16(), &body_text) {
+                    tracing::warn!("Gemini context cache rejected, sending inline: {}", body_text);
+                    if let (Some(caches), Some(key)) = (&caches, &cache_key) {
+                        caches.lock().await.remove(key);
+                    }
+                    body.cached_content = None;
+                    body.system_instruction = inline_system.take();
+                    body.tools = inline_tools.take();
+                    body.tool_config = inline_tool_config.take();
+                    cache_written = 0;
+                    continue;
+                }
+
+                yield Err(ProviderError::from_status(status.as_u16(), retry_after, body_text));
                 return;
-            }
+            };
 
             yield Ok(StreamEvent::Start);
 
-            let mut text_buf = String::new();
-            let mut thinking_buf = String::new();
-            let mut tool_calls: Vec<ToolCall> = Vec::new();
+            // Function-call signatures stay on the call
+            let mut signed_parts = SignedParts::default();
+            let mut function_calls = FunctionCalls::default();
             let mut usage = Usage::default();
             let mut stop_reason = StopReason::Stop;
+            let mut metadata = ResponseMetadata::default();
             let mut line_buf = String::new();
 
             let mut byte_stream = resp.bytes_stream();
@@ -448,61 +1417,52 @@ impl Provider for GoogleProvider {
                         let cached = um.cached_content_token_count.unwrap_or(0);
                         usage.input_tokens = prompt.saturating_sub(cached);
                         usage.cache_read_tokens = cached;
+                        usage.cache_write_tokens = cache_written;
                         usage.output_tokens = um.candidates_token_count.unwrap_or(0)
                             + um.thoughts_token_count.unwrap_or(0);
                         usage.total_tokens = um.total_token_count.unwrap_or(0);
                     }
 
+                    if chunk.prompt_feedback.as_ref().is_some_and(|f| metadata.prompt_feedback(f)) {
+                        stop_reason = StopReason::ContentFilter;
+                    }
+
                     if let Some(candidates) = &chunk.candidates {
                         for candidate in candidates {
                             if let Some(reason) = &candidate.finish_reason {
-                                stop_reason = match reason.as_str() {
-                                    "STOP" => StopReason::Stop,
-                                    "MAX_TOKENS" => StopReason::Length,
-                                    _ => StopReason::Stop,
-                                };
+                                stop_reason = metadata.finish(reason, &candidate.safety_ratings);
+                            }
+                            if let Some(g) = &candidate.grounding_metadata {
+                                metadata.set_grounding(g);
+                            }
+                            if let Some(c) = &candidate.citation_metadata {
+                                metadata.add_citations(c);
+                            }
+                            if let Some(u) = &candidate.url_context_metadata {
+                                metadata.set_url_context(u);
                             }
 
                             if let Some(content) = &candidate.content {
                                 if let Some(parts) = &content.parts {
                                     for part in parts {
-                                        if let Some(text) = &part.text {
-                                            let is_thinking = part.thought.unwrap_or(false);
-                                            if is_thinking {
-                                                thinking_buf.push_str(text);
-                                                yield Ok(StreamEvent::ThinkingDelta(text.clone()));
-                                            } else {
-                                                text_buf.push_str(text);
-                                                yield Ok(StreamEvent::TextDelta(text.clone()));
+                                        if part.function_call.is_none() {
+                                            let thought = part.thought.unwrap_or(false);
+                                            if let Some(event) = signed_parts.push(part.text.as_ref(), thought, part.thought_signature.as_ref()) {
+                                                yield Ok(event);
                                             }
                                         }
 
+                                        if let Some(code) = &part.executable_code {
+                                            signed_parts.push_block(code.to_block());
+                                        }
+                                        if let Some(result) = &part.code_execution_result {
+                                            signed_parts.push_block(result.to_block());
+                                        }
+
                                         if let Some(fc) = &part.function_call {
-                                            let counter = TOOL_CALL_COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-                                            let tc_id = format!("{}_{}", fc.name, counter);
-                                            let args = fc.args.clone().unwrap_or(json!({}));
-                                            let idx = tool_calls.len();
-
-                                            let tc = ToolCall {
-                                                id: tc_id.clone(),
-                                                name: fc.name.clone(),
-                                                arguments: args.clone(),
-                                            };
-                                            tool_calls.push(tc.clone());
-
-                                            yield Ok(StreamEvent::ToolCallStart {
-                                                index: idx,
-                                                id: tc_id,
-                                                name: fc.name.clone(),
-                                            });
-                                            yield Ok(StreamEvent::ToolCallDelta {
-                                                index: idx,
-                                                delta: args.to_string(),
-                                            });
-                                            yield Ok(StreamEvent::ToolCallEnd {
-                                                index: idx,
-                                                tool_call: tc,
-                                            });
+                                            for event in function_calls.push(fc, part.thought_signature.as_ref()) {
+                                                yield Ok(event);
+                                            }
                                         }
                                     }
                                 }
@@ -512,20 +1472,22 @@ impl Provider for GoogleProvider {
                 }
             }
 
-            if !tool_calls.is_empty() {
+            for event in function_calls.finish() {
+                yield Ok(event);
+            }
+            if !function_calls.is_empty() {
                 stop_reason = StopReason::ToolUse;
             }
 
+            let (metadata_blocks, error_message) = metadata.into_parts();
             let mut content = Vec::new();
-            if !thinking_buf.is_empty() {
-                content.push(ContentBlock::Thinking(ThinkingContent { thinking: thinking_buf, signature: None }));
-            }
-            if !text_buf.is_empty() {
-                content.push(ContentBlock::Text(TextContent { text: text_buf }));
-            }
-            for tc in tool_calls {
+            let (thinking_blocks, blocks) = signed_parts.into_blocks();
+            content.extend(thinking_blocks);
+            content.extend(blocks);
+            for tc in function_calls.into_calls() {
                 content.push(ContentBlock::ToolCall(tc));
             }
+            content.extend(metadata_blocks);
 
             let msg = AssistantMessage {
                 content,
@@ -533,6 +1495,7 @@ impl Provider for GoogleProvider {
                 provider: provider_id,
                 usage: Some(usage),
                 stop_reason,
+                error_message,
             };
 
             yield Ok(StreamEvent::Done { message: msg });
@@ -541,20 +1504,25 @@ impl Provider for GoogleProvider {
         Box::pin(s)
     }
 
-    async fn list_models(&self, api_key: &str) -> Result<Vec<ModelDef>, ProviderError> {
-        let url = format!(
-            "https://generativelanguage.googleapis.com/v1beta/models?key={}",
-            api_key
-        );
-
-        let resp = self.client.get(&url).send().await?;
-
-        if !resp.status().is_success() {
-            let body = resp.text().await.unwrap_or_default();
-            return Err(ProviderError::Http {
-                status: 400,
-                body,
-            });
+    async fn list_models(
+        &self,
+        provider: &str,
+        base_url: &str,
+        api_key: &str,
+    ) -> Result<Vec<ModelDef>, ProviderError> {
+        let base_url = base_url.trim_end_matches('/');
+        let url = format!("{}/models", base_url);
+
+        let resp = self
+            .client
+            .get(&url)
+            .header("x-goog-api-key", api_key)
+            .send()
+            .await?;
+
+        let status = resp.status();
+        if !status.is_success() {
+            return Err(ProviderError::from_response(resp).await);
         }
 
         let list: ModelsListResponse = resp.json().await?;
@@ -580,14 +1548,15 @@ impl Provider for GoogleProvider {
                     id,
                     name,
                     api: Api::GoogleGenerativeAi,
-                    provider: "google".into(),
-                    base_url: "https://generativelanguage.googleapis.com/v1beta".into(),
+                    provider: provider.into(),
+                    base_url: base_url.into(),
                     reasoning,
                     input: vec![InputModality::Text, InputModality::Image],
                     cost: ModelCost::default(),
                     context_window: m.input_token_limit.unwrap_or(128000),
                     max_tokens: m.output_token_limit.unwrap_or(8192),
                     headers: None,
+                    compat: None,
                 }
             })
             .collect();
@@ -595,3 +1564,231 @@ impl Provider for GoogleProvider {
         Ok(models)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::test_server::{FakeServer, Response};
+
+    fn model(base_url: &str) -> ModelDef {
+        ModelDef {
+            id: "gemini-2.5-pro".into(),
+            name: "Gemini 2.5 Pro".into(),
+            api: Api::GoogleGenerativeAi,
+            provider: "google".into(),
+            base_url: base_url.into(),
+            reasoning: true,
+            input: vec![InputModality::Text],
+            cost: ModelCost::default(),
+            context_window: 1_000_000,
+            max_tokens: 8192,
+            headers: None,
+            compat: None,
+        }
+    }
+
+    fn sse(chunks: &[serde_json::Value]) -> Response {
+        Response {
+            status: 200,
+            headers: vec![("content-type".into(), "text/event-stream".into())],
+            body: chunks.iter().map(|c| format!("data: {}\n\n", c)).collect(),
+        }
+    }
+
+    fn partial_args(args: serde_json::Value) -> Vec<PartialArg> {
+        serde_json::from_value(args).unwrap()
+    }
+
+    #[tokio::test]
+    async fn signatures_are_replayed_on_the_parts_that_carried_them() {
+        let server = FakeServer::start(|_| {
+            sse(&[
+                json!({"candidates": [{"content": {"parts": [{"text": "plan", "thought": true, "thoughtSignature": "sig-think"}]}}]}),
+                json!({"candidates": [{"content": {"parts": [{"text": "Hello "}]}}]}),
+                json!({"candidates": [{"content": {"parts": [{"text": "world", "thoughtSignature": "sig-a"}]}}]}),
+                json!({"candidates": [{"content": {"parts": [{"text": "Second"}, {"text": "", "thoughtSignature": "sig-b"}]}}]}),
+                json!({"candidates": [{"content": {"parts": [
+                    {"functionCall": {"name": "lookup", "args": {"q": "x"}}, "thoughtSignature": "sig-call"}
+                ]}, "finishReason": "STOP"}]}),
+            ])
+        })
+        .await;
+
+        let model = model(&server.url);
+        let context = ChatContext { system_prompt: None, messages: Vec::new(), tools: Vec::new(), hosted_tools: Vec::new() };
+        let options = StreamOptions { api_key: Some("key".into()), ..Default::default() };
+        let events: Vec<_> = GoogleProvider::new().without_context_cache().stream(&model, &context, &options).collect().await;
+        let message = match events.into_iter().last() {
+            Some(Ok(StreamEvent::Done { message })) => message,
+            other => panic!("{:?}", other),
+        };
+
+        let texts: Vec<_> = message
+            .content
+            .iter()
+            .filter_map(|b| match b {
+                ContentBlock::Text(t) => Some((t.text.as_str(), t.thought_signature.as_deref())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(texts, [("Hello world", Some("sig-a")), ("Second", Some("sig-b"))]);
+
+        let replay = ChatContext { messages: vec![Message::Assistant(message)], ..context };
+        let parts = serde_json::to_value(&convert_messages(&replay, &model.id)[0].parts).unwrap();
+        assert_eq!(parts, json!([
+            {"text": "plan", "thought": true, "thoughtSignature": "sig-think"},
+            {"text": "Hello world", "thoughtSignature": "sig-a"},
+            {"text": "Second", "thoughtSignature": "sig-b"},
+            {"functionCall": {"name": "lookup", "args": {"q": "x"}}, "thoughtSignature": "sig-call"},
+        ]));
+
+        // Signatures do not validate on another model
+        let parts = serde_json::to_value(&convert_messages(&replay, "gemini-2.5-flash")[0].parts).unwrap();
+        assert_eq!(parts, json!([
+            {"text": "Hello world"},
+            {"text": "Second"},
+            {"functionCall": {"name": "lookup", "args": {"q": "x"}}},
+        ]));
+    }
+
+    #[tokio::test]
+    async fn code_execution_parts_keep_their_place_among_the_text() {
+        let server = FakeServer::start(|_| {
+            sse(&[
+                json!({"candidates": [{"content": {"parts": [{"text": "Let me compute it."}]}}]}),
+                json!({"candidates": [{"content": {"parts": [
+                    {"executableCode": {"language": "PYTHON", "code": "print(2 ** 10)"}},
+                    {"codeExecutionResult": {"outcome": "OUTCOME_OK", "output": "1024\n"}}
+                ]}}]}),
+                json!({"candidates": [{"content": {"parts": [{"text": "It is 1024."}]}, "finishReason": "STOP"}]}),
+            ])
+        })
+        .await;
+
+        let context = ChatContext { system_prompt: None, messages: Vec::new(), tools: Vec::new(), hosted_tools: vec![HostedTool::CodeExecution] };
+        let options = StreamOptions { api_key: Some("key".into()), ..Default::default() };
+        let events: Vec<_> = GoogleProvider::new().without_context_cache().stream(&model(&server.url), &context, &options).collect().await;
+        let message = match events.into_iter().last() {
+            Some(Ok(StreamEvent::Done { message })) => message,
+            other => panic!("{:?}", other),
+        };
+
+        match &message.content[..] {
+            [ContentBlock::Text(before), ContentBlock::ExecutableCode(code), ContentBlock::CodeExecutionResult(result), ContentBlock::Text(after)] => {
+                assert_eq!(before.text, "Let me compute it.");
+                assert_eq!(code.language.as_deref(), Some("python"));
+                assert_eq!(code.code, "print(2 ** 10)");
+                assert!(result.success);
+                assert_eq!(result.output, "1024\n");
+                assert_eq!(after.text, "It is 1024.");
+            }
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(server.requests()[0].json()["tools"], json!([{"codeExecution": {}}]));
+    }
+
+    #[test]
+    fn streamed_args_concatenate_to_the_final_arguments() {
+        let args = partial_args(json!([
+            {"jsonPath": "$.query", "stringValue": "hel"},
+            {"jsonPath": "$.query", "stringValue": "lo \"you\""},
+            {"jsonPath": "$.limit", "numberValue": 5},
+            {"jsonPath": "$.filters[0].name", "stringValue": "a"},
+            {"jsonPath": "$.filters[1].name", "stringValue": "b"},
+            {"jsonPath": "$.filters[1].exact", "boolValue": true},
+            {"jsonPath": "$.note"},
+        ]));
+
+        let mut streaming = StreamingArgs::new(0);
+        let mut text: String = args.iter().map(|a| streaming.write(a)).collect();
+        let (tail, value) = streaming.close();
+        text.push_str(&tail);
+
+        let expected = json!({
+            "query": "hello \"you\"",
+            "limit": 5,
+            "filters": [{"name": "a"}, {"name": "b", "exact": true}],
+            "note": null,
+        });
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&text).unwrap(), expected);
+        assert_eq!(value, expected);
+    }
+
+    #[test]
+    fn streamed_args_prefer_the_complete_arguments() {
+        let mut streaming = StreamingArgs::new(0);
+        streaming.write(&partial_args(json!([{"jsonPath": "$.a", "stringValue": "x"}]))[0]);
+        streaming.args = Some(json!({"a": "xyz"}));
+        assert_eq!(streaming.close(), ("\"}".to_string(), json!({"a": "xyz"})));
+    }
+
+    #[test]
+    fn no_streamed_args_close_to_an_empty_object() {
+        assert_eq!(StreamingArgs::new(0).close(), ("{}".to_string(), json!({})));
+    }
+
+    #[test]
+    fn json_paths_parse_keys_and_indices() {
+        assert_eq!(
+            parse_json_path("$.items[2]['odd.key'].name"),
+            [
+                PathSegment::Key("items".into()),
+                PathSegment::Index(2),
+                PathSegment::Key("odd.key".into()),
+                PathSegment::Key("name".into()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn context_caches_are_created_once_and_renewed_before_expiry() {
+        let server = FakeServer::start(|req| {
+            assert!(req.target.ends_with("/cachedContents"), "{}", req.target);
+            assert_eq!(req.headers["x-goog-api-key"], "key");
+            assert_eq!(req.json()["ttl"], "3600s");
+            Response::json(200, json!({"name": "cachedContents/c1", "usageMetadata": {"totalTokenCount": 5000}}))
+        })
+        .await;
+        let client = Client::new();
+        let caches = Mutex::new(HashMap::new());
+        let headers = HashMap::from([("x-goog-api-key".to_string(), "key".to_string())]);
+        let create = CreateCachedContentRequest {
+            model: "models/gemini-2.5-pro".into(),
+            system_instruction: None,
+            tools: None,
+            tool_config: None,
+            ttl: format!("{}s", CACHE_TTL_SECS),
+        };
+        let lookup = || cached_content(&client, &caches, "k", &server.url, &headers, &create);
+
+        // Created with the tokens written, then reused without writing again
+        assert_eq!(lookup().await, Some(("cachedContents/c1".into(), 5000)));
+        assert_eq!(lookup().await, Some(("cachedContents/c1".into(), 0)));
+        assert_eq!(server.requests().len(), 1);
+
+        // A cache about to expire is replaced
+        let soon = chrono::Utc::now().timestamp_millis() + CACHE_EXPIRY_MARGIN_MS / 2;
+        caches.lock().await.get_mut("k").unwrap().expires_at = soon;
+        assert_eq!(lookup().await, Some(("cachedContents/c1".into(), 5000)));
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn failed_cache_creation_sends_inline_for_a_while() {
+        let server = FakeServer::start(|_| Response::json(400, json!({"error": {"message": "too small"}}))).await;
+        let client = Client::new();
+        let caches = Mutex::new(HashMap::new());
+        let create = CreateCachedContentRequest {
+            model: "models/gemini-2.5-pro".into(),
+            system_instruction: None,
+            tools: None,
+            tool_config: None,
+            ttl: format!("{}s", CACHE_TTL_SECS),
+        };
+
+        for _ in 0..2 {
+            assert_eq!(cached_content(&client, &caches, "k", &server.url, &HashMap::new(), &create).await, None);
+        }
+        assert_eq!(server.requests().len(), 1);
+    }
+}
@@ -1,4 +1,12 @@
+use super::google::{
+    BuiltinTool, CitationMetadata, CodeExecutionResultPart, ExecutableCodePart,
+    FunctionCallResponse, FunctionCalls, GroundingMetadata, PromptFeedback, ResponseMetadata,
+    SafetyRating, SignedParts, ToolConfig, UrlContextMetadata, builtin_tools, function_call_signature,
+    signatures_valid_for, tool_config,
+};
+use super::code_assist::{self, DAILY_ENDPOINT, PROD_ENDPOINT};
 use super::{Provider, ProviderError};
+use crate::auth::config::ConfigManager;
 use crate::types::*;
 use async_trait::async_trait;
 use futures::stream::{self, BoxStream, StreamExt};
@@ -6,6 +14,10 @@ use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Project of one credential, locked while it is being discovered.
+type ProjectSlot = Arc<tokio::sync::Mutex<Option<String>>>;
 
 /// Google Gemini CLI / Antigravity provider.
 /// Uses the Cloud Code Assist API endpoint.
@@ -14,6 +26,10 @@ pub struct GoogleGeminiCliProvider {
     client: Client,
     /// Whether this instance operates in Antigravity mode.
     is_antigravity: bool,
+    /// Where discovered projects are persisted, on the credential they belong to.
+    config: Option<ConfigManager>,
+    /// Discovered projects by access token, for credentials that do not carry one.
+    projects: Arc<Mutex<HashMap<String, ProjectSlot>>>,
 }
 
 impl GoogleGeminiCliProvider {
@@ -21,6 +37,8 @@ impl GoogleGeminiCliProvider {
         Self {
             client: Client::new(),
             is_antigravity: false,
+            config: None,
+            projects: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 
@@ -28,14 +46,27 @@ impl GoogleGeminiCliProvider {
         Self {
             client: Client::new(),
             is_antigravity: true,
+            config: None,
+            projects: Arc::new(Mutex::new(HashMap::new())),
         }
     }
+
+    /// Persist discovered projects and tiers through this config manager.
+    pub fn with_config(mut self, config: ConfigManager) -> Self {
+        self.config = Some(config);
+        self
+    }
 }
 
-const DEFAULT_ENDPOINT: &str = "https://cloudcode-pa.googleapis.com";
-const ANTIGRAVITY_DAILY_ENDPOINT: &str = "https://daily-cloudcode-pa.sandbox.googleapis.com";
 const DEFAULT_ANTIGRAVITY_VERSION: &str = "1.15.8";
 
+/// Provider params holding the discovered project and the account's tier.
+pub const PROJECT_PARAM: &str = "project_id";
+/// OAuth credential field holding the discovered project.
+pub const PROJECT_FIELD: &str = "projectId";
+/// OAuth credential field holding the account's tier.
+pub const TIER_FIELD: &str = "tier";
+
 fn gemini_cli_headers() -> HashMap<String, String> {
     let mut h = HashMap::new();
     h.insert("User-Agent".into(), "google-cloud-sdk vscode_cloudshelleditor/0.1".into());
@@ -100,6 +131,10 @@ struct InnerRequest {
     generation_config: Option<GGenerationConfig>,
     #[serde(skip_serializing_if = "Option::is_none")]
     tools: Option<Vec<GToolDeclaration>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_config: Option<ToolConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    safety_settings: Option<Vec<SafetySetting>>,
 }
 
 #[derive(Serialize)]
@@ -117,6 +152,10 @@ struct GPart {
     function_call: Option<GFunctionCall>,
     #[serde(skip_serializing_if = "Option::is_none")]
     function_response: Option<GFunctionResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thought: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thought_signature: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -162,7 +201,10 @@ struct GThinkingConfig {
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
 struct GToolDeclaration {
-    function_declarations: Vec<GFunctionDeclaration>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    function_declarations: Option<Vec<GFunctionDeclaration>>,
+    #[serde(flatten)]
+    builtin: BuiltinTool,
 }
 
 #[derive(Serialize)]
@@ -186,6 +228,7 @@ struct ChunkEnvelope {
 struct ResponseData {
     candidates: Option<Vec<RCandidate>>,
     usage_metadata: Option<RUsageMetadata>,
+    prompt_feedback: Option<PromptFeedback>,
 }
 
 #[derive(Deserialize)]
@@ -193,6 +236,11 @@ struct ResponseData {
 struct RCandidate {
     content: Option<RContent>,
     finish_reason: Option<String>,
+    grounding_metadata: Option<GroundingMetadata>,
+    citation_metadata: Option<CitationMetadata>,
+    url_context_metadata: Option<UrlContextMetadata>,
+    #[serde(default)]
+    safety_ratings: Vec<SafetyRating>,
 }
 
 #[derive(Deserialize)]
@@ -205,14 +253,10 @@ struct RContent {
 struct RPart {
     text: Option<String>,
     thought: Option<bool>,
-    function_call: Option<RFunctionCall>,
-}
-
-#[derive(Deserialize)]
-struct RFunctionCall {
-    name: String,
-    args: Option<serde_json::Value>,
-    id: Option<String>,
+    thought_signature: Option<String>,
+    function_call: Option<FunctionCallResponse>,
+    executable_code: Option<ExecutableCodePart>,
+    code_execution_result: Option<CodeExecutionResultPart>,
 }
 
 #[derive(Deserialize)]
@@ -229,7 +273,7 @@ struct RUsageMetadata {
 // Conversion helpers
 // ---------------------------------------------------------------------------
 
-fn convert_messages(context: &ChatContext) -> Vec<GContent> {
+fn convert_messages(context: &ChatContext, model_id: &str) -> Vec<GContent> {
     let mut contents = Vec::new();
 
     for msg in &context.messages {
@@ -243,6 +287,8 @@ fn convert_messages(context: &ChatContext) -> Vec<GContent> {
                             text: Some(t.text.clone()),
                             function_call: None,
                             function_response: None,
+                            thought: None,
+                            thought_signature: None,
                         }),
                         _ => None,
                     })
@@ -254,14 +300,27 @@ fn convert_messages(context: &ChatContext) -> Vec<GContent> {
                 });
             }
             Message::Assistant(a) => {
+                let own = signatures_valid_for(a, model_id);
                 let mut parts = Vec::new();
                 for block in &a.content {
                     match block {
+                        // Thought text is only replayed as the carrier of its signature
+                        ContentBlock::Thinking(th) if own && th.signature.is_some() => {
+                            parts.push(GPart {
+                                text: Some(th.thinking.clone()),
+                                function_call: None,
+                                function_response: None,
+                                thought: Some(true),
+                                thought_signature: th.signature.clone(),
+                            });
+                        }
                         ContentBlock::Text(t) => {
                             parts.push(GPart {
                                 text: Some(t.text.clone()),
                                 function_call: None,
                                 function_response: None,
+                                thought: None,
+                                thought_signature: t.thought_signature.clone().filter(|_| own),
                             });
                         }
                         ContentBlock::ToolCall(tc) => {
@@ -272,6 +331,11 @@ fn convert_messages(context: &ChatContext) -> Vec<GContent> {
                                     args: tc.arguments.clone(),
                                 }),
                                 function_response: None,
+                                thought: None,
+                                thought_signature: function_call_signature(
+                                    model_id,
+                                    tc.thought_signature.as_ref().filter(|_| own),
+                                ),
                             });
                         }
                         _ => {}
@@ -305,6 +369,8 @@ fn convert_messages(context: &ChatContext) -> Vec<GContent> {
                             name: tr.tool_name.clone(),
                             response: json!({"result": text}),
                         }),
+                        thought: None,
+                        thought_signature: None,
                     }],
                 });
             }
@@ -314,45 +380,117 @@ fn convert_messages(context: &ChatContext) -> Vec<GContent> {
     contents
 }
 
-fn convert_tools(tools: &[ToolDef]) -> Vec<GToolDeclaration> {
-    vec![GToolDeclaration {
-        function_declarations: tools
-            .iter()
-            .map(|t| GFunctionDeclaration {
-                name: t.name.clone(),
-                description: t.description.clone(),
-                parameters: t.parameters.clone(),
-            })
-            .collect(),
-    }]
+fn convert_tools(tools: &[ToolDef], hosted: &[HostedTool]) -> Vec<GToolDeclaration> {
+    let mut decls = Vec::new();
+    if !tools.is_empty() {
+        decls.push(GToolDeclaration {
+            function_declarations: Some(
+                tools
+                    .iter()
+                    .map(|t| GFunctionDeclaration {
+                        name: t.name.clone(),
+                        description: t.description.clone(),
+                        parameters: t.parameters.clone(),
+                    })
+                    .collect(),
+            ),
+            builtin: BuiltinTool::default(),
+        });
+    }
+    decls.extend(builtin_tools(hosted).into_iter().map(|builtin| GToolDeclaration {
+        function_declarations: None,
+        builtin,
+    }));
+    decls
 }
 
 /// Parse the JSON-encoded API key used by Cloud Code Assist.
-/// Format: `{"token": "...", "projectId": "..."}`
-fn parse_cloud_code_api_key(api_key: &str) -> Result<(String, String), ProviderError> {
+/// Format: `{"token": "...", "projectId": "..."}`; the project is optional and is
+/// discovered when missing. A bare access token is accepted too.
+fn parse_cloud_code_api_key(api_key: &str) -> Result<(String, Option<String>), ProviderError> {
     #[derive(Deserialize)]
     struct CloudKey {
         token: String,
-        #[serde(rename = "projectId")]
-        project_id: String,
+        #[serde(rename = "projectId", default)]
+        project_id: Option<String>,
     }
 
-    let parsed: CloudKey = serde_json::from_str(api_key).map_err(|_| {
-        ProviderError::AuthRequired(
-            "Invalid Cloud Code Assist credentials. Expected JSON {token, projectId}.".into(),
-        )
-    })?;
+    let parsed = if api_key.trim_start().starts_with('{') {
+        serde_json::from_str(api_key).map_err(|_| {
+            ProviderError::AuthRequired(
+                "Invalid Cloud Code Assist credentials. Expected JSON {token, projectId}.".into(),
+            )
+        })?
+    } else {
+        CloudKey {
+            token: api_key.trim().to_string(),
+            project_id: None,
+        }
+    };
 
-    if parsed.token.is_empty() || parsed.project_id.is_empty() {
+    if parsed.token.is_empty() {
         return Err(ProviderError::AuthRequired(
-            "Missing token or projectId in Cloud Code credentials.".into(),
+            "Missing token in Cloud Code credentials.".into(),
         ));
     }
 
-    Ok((parsed.token, parsed.project_id))
+    Ok((parsed.token, parsed.project_id.filter(|p| !p.is_empty())))
 }
 
-static TOOL_CALL_COUNTER: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+/// Project for a credential that does not carry one: cached, or discovered (and
+/// onboarded if needed), then persisted on the stored credential with the API key
+/// `api_key`, so each account of a pool keeps its own project and tier.
+async fn resolve_project(
+    client: &Client,
+    projects: &Mutex<HashMap<String, ProjectSlot>>,
+    config: Option<&ConfigManager>,
+    provider_id: &str,
+    endpoints: &[String],
+    api_key: &str,
+    token: &str,
+) -> Result<String, ProviderError> {
+    // Holding the credential's lock keeps concurrent requests from onboarding twice
+    let slot = projects.lock().unwrap().entry(token.to_string()).or_default().clone();
+    let mut project = slot.lock().await;
+    if let Some(project) = project.as_ref() {
+        return Ok(project.clone());
+    }
+
+    let hint = std::env::var("GOOGLE_CLOUD_PROJECT").ok();
+    let setup = code_assist::setup_user(client, endpoints, token, hint.as_deref()).await?;
+    let tier = setup
+        .tier
+        .as_ref()
+        .map(|t| t.name.clone().unwrap_or_else(|| t.id.clone()));
+    tracing::info!(
+        "Cloud Code Assist project for {}: {} (tier: {})",
+        provider_id,
+        setup.project_id,
+        tier.as_deref().unwrap_or("unknown")
+    );
+
+    if let Some(config) = config {
+        let mut fields = vec![(PROJECT_FIELD, json!(setup.project_id))];
+        if let Some(tier) = &tier {
+            fields.push((TIER_FIELD, json!(tier)));
+        }
+        // The config lock blocks, so store them off the runtime's workers
+        let (config, pid, key) = (config.clone(), provider_id.to_string(), api_key.to_string());
+        let saved = tokio::task::spawn_blocking(move || config.set_oauth_extra(&pid, &key, &fields))
+            .await
+            .map_err(anyhow::Error::from)
+            .and_then(|r| r);
+        match saved {
+            Ok(true) => {}
+            // Credentials from env vars or other tools' files are not stored
+            Ok(false) => tracing::debug!("Cloud Code Assist credential for {} is not stored", provider_id),
+            Err(e) => tracing::warn!("Failed to save Cloud Code Assist project for {}: {}", provider_id, e),
+        }
+    }
+
+    *project = Some(setup.project_id.clone());
+    Ok(setup.project_id)
+}
 
 // ---------------------------------------------------------------------------
 // Provider impl
@@ -377,25 +515,31 @@ impl Provider for GoogleGeminiCliProvider {
             }
         };
 
-        let (access_token, project_id) = match parse_cloud_code_api_key(&api_key_raw) {
+        let (access_token, key_project) = match parse_cloud_code_api_key(&api_key_raw) {
             Ok(v) => v,
             Err(e) => {
                 return Box::pin(stream::once(async move { Err(e) }));
             }
         };
+        let known_project = key_project.or_else(|| {
+            options
+                .endpoint_params
+                .as_ref()
+                .and_then(|p| p.get(PROJECT_PARAM))
+                .cloned()
+        });
 
         let is_antigravity = self.is_antigravity;
         let base_url = if !model.base_url.is_empty() {
-            model.base_url.trim_end_matches('/').to_string()
+            model.base_url.clone()
         } else if is_antigravity {
-            ANTIGRAVITY_DAILY_ENDPOINT.to_string()
+            DAILY_ENDPOINT.to_string()
         } else {
-            DEFAULT_ENDPOINT.to_string()
+            PROD_ENDPOINT.to_string()
         };
+        let endpoints = code_assist::endpoints(&base_url);
 
-        let url = format!("{}/v1internal:streamGenerateContent?alt=sse", base_url);
-
-        let contents = convert_messages(context);
+        let contents = convert_messages(context, &model.id);
 
         let mut sys_parts = Vec::new();
         if is_antigravity {
@@ -406,6 +550,8 @@ impl Provider for GoogleGeminiCliProvider {
                 ),
                 function_call: None,
                 function_response: None,
+                thought: None,
+                thought_signature: None,
             });
         }
         if let Some(sp) = &context.system_prompt {
@@ -413,6 +559,8 @@ impl Provider for GoogleGeminiCliProvider {
                 text: Some(sp.clone()),
                 function_call: None,
                 function_response: None,
+                thought: None,
+                thought_signature: None,
             });
         }
 
@@ -462,21 +610,26 @@ impl Provider for GoogleGeminiCliProvider {
             }
         }
 
-        let tools = if context.tools.is_empty() {
+        let tools = if context.tools.is_empty() && context.hosted_tools.is_empty() {
             None
         } else {
-            Some(convert_tools(&context.tools))
+            Some(convert_tools(&context.tools, &context.hosted_tools))
         };
 
-        let request_body = CloudCodeAssistRequest {
-            project: project_id,
+        let mut request_body = CloudCodeAssistRequest {
+            project: known_project.clone().unwrap_or_default(),
             model: model.id.clone(),
             request: InnerRequest {
                 contents,
-                session_id: None,
+                session_id: options
+                    .conversation_id
+                    .as_deref()
+                    .map(code_assist::session_id),
                 system_instruction,
                 generation_config: Some(gen_config),
                 tools,
+                tool_config: tool_config(&model.id, &context.tools),
+                safety_settings: options.safety_settings.clone(),
             },
             request_type: if is_antigravity {
                 Some("agent".into())
@@ -507,48 +660,79 @@ impl Provider for GoogleGeminiCliProvider {
         let model_id = model.id.clone();
         let provider_id = model.provider.clone();
         let opt_extra_headers = options.extra_headers.clone();
+        let projects = self.projects.clone();
+        let config = self.config.clone();
 
         let s = async_stream::stream! {
-            let mut req = client
-                .post(&url)
-                .header("Authorization", format!("Bearer {}", access_token))
-                .header("Content-Type", "application/json")
-                .header("Accept", "text/event-stream");
-
-            for (k, v) in &extra_headers {
-                req = req.header(k.as_str(), v.as_str());
+            if known_project.is_none() {
+                match resolve_project(&client, &projects, config.as_ref(), &provider_id, &endpoints, &api_key_raw, &access_token).await {
+                    Ok(project) => request_body.project = project,
+                    Err(e) => {
+                        yield Err(e);
+                        return;
+                    }
+                }
             }
-            if let Some(mh) = &opt_extra_headers {
-                for (k, v) in mh {
+
+            // Fail over across endpoints on rate limits and server errors
+            let mut response = None;
+            let mut last_err = None;
+            for endpoint in &endpoints {
+                let mut req = client
+                    .post(format!("{}/v1internal:streamGenerateContent?alt=sse", endpoint))
+                    .header("Authorization", format!("Bearer {}", access_token))
+                    .header("Content-Type", "application/json")
+                    .header("Accept", "text/event-stream");
+
+                for (k, v) in &extra_headers {
                     req = req.header(k.as_str(), v.as_str());
                 }
+                if let Some(mh) = &opt_extra_headers {
+                    for (k, v) in mh {
+                        req = req.header(k.as_str(), v.as_str());
+                    }
+                }
+
+                match req.json(&request_body).send().await {
+                    Ok(r) if r.status().is_success() => {
+                        response = Some(r);
+                        break;
+                    }
+                    Ok(r) => {
+                        let status = r.status().as_u16();
+                        let err = ProviderError::from_response(r).await;
+                        if !code_assist::should_fail_over(status) {
+                            yield Err(err);
+                            return;
+                        }
+                        tracing::warn!("Cloud Code Assist request failed at {}: {}", endpoint, err);
+                        last_err = Some(err);
+                    }
+                    Err(e) => {
+                        tracing::warn!("Cloud Code Assist unreachable at {}: {}", endpoint, e);
+                        last_err = Some(ProviderError::Network(e));
+                    }
+                }
             }
 
-            let resp = match req.json(&request_body).send().await {
-                Ok(r) => r,
-                Err(e) => {
-                    yield Err(ProviderError::Network(e));
+            let resp = match response {
+                Some(r) => r,
+                None => {
+                    yield Err(last_err.unwrap_or_else(|| {
+                        ProviderError::Other("No Cloud Code Assist endpoint configured".into())
+                    }));
                     return;
                 }
             };
 
-            let status = resp.status();
-            if !status.is_success() {
-                let body_text = resp.text().await.unwrap_or_default();
-                yield Err(ProviderError::Http {
-                    status: status.as_u16(),
-                    body: body_text,
-                });
-                return;
-            }
-
             yield Ok(StreamEvent::Start);
 
-            let mut text_buf = String::new();
-            let mut thinking_buf = String::new();
-            let mut tool_calls: Vec<ToolCall> = Vec::new();
+            // Function-call signatures stay on the call
+            let mut signed_parts = SignedParts::default();
+            let mut function_calls = FunctionCalls::default();
             let mut usage = Usage::default();
             let mut stop_reason = StopReason::Stop;
+            let mut metadata = ResponseMetadata::default();
             let mut line_buf = String::new();
 
             let mut byte_stream = resp.bytes_stream();
@@ -598,58 +782,46 @@ impl Provider for GoogleGeminiCliProvider {
                         usage.total_tokens = um.total_token_count.unwrap_or(0);
                     }
 
+                    if resp_data.prompt_feedback.as_ref().is_some_and(|f| metadata.prompt_feedback(f)) {
+                        stop_reason = StopReason::ContentFilter;
+                    }
+
                     if let Some(candidates) = &resp_data.candidates {
                         for candidate in candidates {
                             if let Some(reason) = &candidate.finish_reason {
-                                stop_reason = match reason.as_str() {
-                                    "STOP" => StopReason::Stop,
-                                    "MAX_TOKENS" => StopReason::Length,
-                                    _ => StopReason::Stop,
-                                };
+                                stop_reason = metadata.finish(reason, &candidate.safety_ratings);
+                            }
+                            if let Some(g) = &candidate.grounding_metadata {
+                                metadata.set_grounding(g);
+                            }
+                            if let Some(c) = &candidate.citation_metadata {
+                                metadata.add_citations(c);
+                            }
+                            if let Some(u) = &candidate.url_context_metadata {
+                                metadata.set_url_context(u);
                             }
 
                             if let Some(content) = &candidate.content {
                                 if let Some(parts) = &content.parts {
                                     for part in parts {
-                                        if let Some(text) = &part.text {
-                                            let is_thinking = part.thought.unwrap_or(false);
-                                            if is_thinking {
-                                                thinking_buf.push_str(text);
-                                                yield Ok(StreamEvent::ThinkingDelta(text.clone()));
-                                            } else {
-                                                text_buf.push_str(text);
-                                                yield Ok(StreamEvent::TextDelta(text.clone()));
+                                        if part.function_call.is_none() {
+                                            let thought = part.thought.unwrap_or(false);
+                                            if let Some(event) = signed_parts.push(part.text.as_ref(), thought, part.thought_signature.as_ref()) {
+                                                yield Ok(event);
                                             }
                                         }
 
+                                        if let Some(code) = &part.executable_code {
+                                            signed_parts.push_block(code.to_block());
+                                        }
+                                        if let Some(result) = &part.code_execution_result {
+                                            signed_parts.push_block(result.to_block());
+                                        }
+
                                         if let Some(fc) = &part.function_call {
-                                            let counter = TOOL_CALL_COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-                                            let tc_id = fc.id.clone().unwrap_or_else(|| {
-                                                format!("{}_{}", fc.name, counter)
-                                            });
-                                            let args = fc.args.clone().unwrap_or(json!({}));
-                                            let idx = tool_calls.len();
-
-                                            let tc = ToolCall {
-                                                id: tc_id.clone(),
-                                                name: fc.name.clone(),
-                                                arguments: args.clone(),
-                                            };
-                                            tool_calls.push(tc.clone());
-
-                                            yield Ok(StreamEvent::ToolCallStart {
-                                                index: idx,
-                                                id: tc_id,
-                                                name: fc.name.clone(),
-                                            });
-                                            yield Ok(StreamEvent::ToolCallDelta {
-                                                index: idx,
-                                                delta: args.to_string(),
-                                            });
-                                            yield Ok(StreamEvent::ToolCallEnd {
-                                                index: idx,
-                                                tool_call: tc,
-                                            });
+                                            for event in function_calls.push(fc, part.thought_signature.as_ref()) {
+                                                yield Ok(event);
+                                            }
                                         }
                                     }
                                 }
@@ -659,20 +831,22 @@ impl Provider for GoogleGeminiCliProvider {
                 }
             }
 
-            if !tool_calls.is_empty() {
+            for event in function_calls.finish() {
+                yield Ok(event);
+            }
+            if !function_calls.is_empty() {
                 stop_reason = StopReason::ToolUse;
             }
 
+            let (metadata_blocks, error_message) = metadata.into_parts();
             let mut content = Vec::new();
-            if !thinking_buf.is_empty() {
-                content.push(ContentBlock::Thinking(ThinkingContent { thinking: thinking_buf, signature: None }));
-            }
-            if !text_buf.is_empty() {
-                content.push(ContentBlock::Text(TextContent { text: text_buf }));
-            }
-            for tc in tool_calls {
+            let (thinking_blocks, blocks) = signed_parts.into_blocks();
+            content.extend(thinking_blocks);
+            content.extend(blocks);
+            for tc in function_calls.into_calls() {
                 content.push(ContentBlock::ToolCall(tc));
             }
+            content.extend(metadata_blocks);
 
             let msg = AssistantMessage {
                 content,
@@ -680,6 +854,7 @@ impl Provider for GoogleGeminiCliProvider {
                 provider: provider_id,
                 usage: Some(usage),
                 stop_reason,
+                error_message,
             };
 
             yield Ok(StreamEvent::Done { message: msg });
@@ -688,7 +863,12 @@ impl Provider for GoogleGeminiCliProvider {
         Box::pin(s)
     }
 
-    async fn list_models(&self, _api_key: &str) -> Result<Vec<ModelDef>, ProviderError> {
+    async fn list_models(
+        &self,
+        _provider: &str,
+        _base_url: &str,
+        _api_key: &str,
+    ) -> Result<Vec<ModelDef>, ProviderError> {
         if self.is_antigravity {
             Ok(static_antigravity_models())
         } else {
@@ -700,7 +880,7 @@ impl Provider for GoogleGeminiCliProvider {
 /// Static model list for Gemini CLI provider.
 pub fn static_gemini_cli_models() -> Vec<ModelDef> {
     let provider = "gemini-cli";
-    let base_url = DEFAULT_ENDPOINT;
+    let base_url = PROD_ENDPOINT;
     let api = Api::GoogleGeminiCli;
 
     vec![
@@ -715,7 +895,7 @@ pub fn static_gemini_cli_models() -> Vec<ModelDef> {
 /// Static model list for Antigravity provider.
 pub fn static_antigravity_models() -> Vec<ModelDef> {
     let provider = "antigravity";
-    let base_url = ANTIGRAVITY_DAILY_ENDPOINT;
+    let base_url = DAILY_ENDPOINT;
     let api = Api::GoogleGeminiCli;
 
     vec![
@@ -752,5 +932,97 @@ fn model_def(
         context_window,
         max_tokens,
         headers: None,
+        compat: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{Credential, OAuthCredential};
+    use crate::providers::test_server::{FakeServer, Response};
+
+    fn oauth(access: &str) -> Credential {
+        Credential::OAuth(OAuthCredential {
+            refresh: format!("refresh-{}", access),
+            access: access.into(),
+            expires: i64::MAX,
+            extra: HashMap::new(),
+        })
+    }
+
+    #[tokio::test]
+    async fn each_pooled_account_keeps_its_own_project() {
+        let server = FakeServer::start(|req| {
+            let project = match req.headers["authorization"].as_str() {
+                "Bearer tok-a" => "proj-a",
+                _ => "proj-b",
+            };
+            Response::json(200, json!({
+                "currentTier": {"id": "standard-tier", "name": "Standard"},
+                "cloudaicompanionProject": project,
+            }))
+        })
+        .await;
+
+        let dir = std::env::temp_dir().join(format!("ai-rs-test-{}", uuid::Uuid::new_v4().simple()));
+        let config = ConfigManager::new(dir.join("config.json"));
+        config.set_credential("gemini-cli", oauth("tok-a")).unwrap();
+        config.set_pooled_credential("gemini-cli", "second", oauth("tok-b")).unwrap();
+
+        let projects = Mutex::new(HashMap::new());
+        let endpoints = [server.url.clone()];
+        for (label, token) in [("default", "tok-a"), ("second", "tok-b")] {
+            let key = config.get_pooled_credential("gemini-cli", label).unwrap().unwrap().api_key().unwrap();
+            resolve_project(&Client::new(), &projects, Some(&config), "gemini-cli", &endpoints, &key, token)
+                .await
+                .unwrap();
+        }
+
+        for (label, project) in [("default", "proj-a"), ("second", "proj-b")] {
+            let cred = config.get_pooled_credential("gemini-cli", label).unwrap().unwrap();
+            let key = cred.api_key().unwrap();
+            assert_eq!(parse_cloud_code_api_key(&key).unwrap().1.as_deref(), Some(project));
+            match cred {
+                Credential::OAuth(o) => assert_eq!(o.extra[TIER_FIELD], "Standard"),
+                other => panic!("{:?}", other),
+            }
+        }
+
+        // Cached per token: no second discovery
+        resolve_project(&Client::new(), &projects, None, "gemini-cli", &endpoints, "tok-a", "tok-a").await.unwrap();
+        assert_eq!(server.requests().len(), 2);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn text_signatures_are_replayed_on_their_own_parts() {
+        let text = |text: &str, sig: Option<&str>| {
+            ContentBlock::Text(TextContent { text: text.into(), thought_signature: sig.map(String::from) })
+        };
+        let context = ChatContext {
+            system_prompt: None,
+            messages: vec![Message::Assistant(AssistantMessage {
+                content: vec![
+                    ContentBlock::Thinking(ThinkingContent { thinking: "plan".into(), signature: Some("sig-think".into()) }),
+                    text("Hello", Some("sig-a")),
+                    text("Second", None),
+                ],
+                model: "gemini-2.5-pro".into(),
+                provider: "gemini-cli".into(),
+                usage: None,
+                stop_reason: StopReason::Stop,
+                error_message: None,
+            })],
+            tools: Vec::new(),
+            hosted_tools: Vec::new(),
+        };
+
+        let parts = serde_json::to_value(&convert_messages(&context, "gemini-2.5-pro")[0].parts).unwrap();
+        assert_eq!(parts, json!([
+            {"text": "plan", "thought": true, "thoughtSignature": "sig-think"},
+            {"text": "Hello", "thoughtSignature": "sig-a"},
+            {"text": "Second"},
+        ]));
     }
 }
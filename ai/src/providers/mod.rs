@@ -1,11 +1,20 @@
 pub mod anthropic;
+pub mod code_assist;
+pub mod github_copilot;
 pub mod google;
 pub mod google_gemini_cli;
+pub mod google_vertex;
+pub mod ollama;
 pub mod openai;
+pub mod openai_codex;
+pub mod qwen;
+#[cfg(test)]
+pub(crate) mod test_server;
 
 use crate::types::{ChatContext, ModelDef, StreamEvent, StreamOptions};
 use async_trait::async_trait;
 use futures::stream::BoxStream;
+use std::collections::HashMap;
 
 /// Errors from provider operations.
 #[derive(Debug, thiserror::Error)]
@@ -22,13 +31,86 @@ pub enum ProviderError {
     #[error("Authentication required: {0}")]
     AuthRequired(String),
 
-    #[error("Rate limited, retry after {retry_after_ms:?}ms")]
+    #[error("Rate limited{}", .retry_after_ms.map(|ms| format!(", retry after {}ms", ms)).unwrap_or_default())]
     RateLimited { retry_after_ms: Option<u64> },
 
     #[error("{0}")]
     Other(String),
 }
 
+impl ProviderError {
+    /// Error for an unsuccessful response. A 429 becomes `RateLimited`, with the
+    /// wait the server asked for, unless its body says the quota is used up.
+    pub fn from_status(status: u16, retry_after_ms: Option<u64>, body: String) -> Self {
+        if status == 429 && (retry_after_ms.is_some() || !is_quota_exhausted(&body)) {
+            ProviderError::RateLimited { retry_after_ms }
+        } else {
+            ProviderError::Http { status, body }
+        }
+    }
+
+    /// [`from_status`](Self::from_status) for a response whose body is not needed otherwise.
+    pub async fn from_response(resp: reqwest::Response) -> Self {
+        let status = resp.status().as_u16();
+        let retry_after = retry_after_ms(resp.headers());
+        Self::from_status(status, retry_after, resp.text().await.unwrap_or_default())
+    }
+
+    /// Scrub credentials from the error text before it reaches callers.
+    ///
+    /// Network errors lose their URL, which may carry a key in the query string.
+    pub fn redact(self, secrets: &[String]) -> Self {
+        let scrub = |text: String| redact_secrets(text, secrets);
+        match self {
+            ProviderError::Network(e) => ProviderError::Network(e.without_url()),
+            ProviderError::Http { status, body } => ProviderError::Http {
+                status,
+                body: scrub(body),
+            },
+            ProviderError::AuthRequired(msg) => ProviderError::AuthRequired(scrub(msg)),
+            ProviderError::Other(msg) => ProviderError::Other(scrub(msg)),
+            other => other,
+        }
+    }
+}
+
+/// Whether an error body reports an exhausted quota rather than a short-term limit.
+/// Gemini answers per-minute limits with `RESOURCE_EXHAUSTED` as well, so only a
+/// daily quota id counts there.
+pub fn is_quota_exhausted(body: &str) -> bool {
+    // OpenAI: out of credit
+    body.contains("insufficient_quota")
+        // Cloud Code Assist `ErrorInfo` reason
+        || body.contains("QUOTA_EXHAUSTED")
+        // Gemini quota ids, e.g. `GenerateRequestsPerDayPerProjectPerModel-FreeTier`
+        || body.contains("PerDay")
+}
+
+/// The wait a response asks for, from `retry-after-ms` (OpenAI, Azure) or
+/// `Retry-After` in seconds or as an HTTP date.
+pub fn retry_after_ms(headers: &reqwest::header::HeaderMap) -> Option<u64> {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).map(str::trim);
+    if let Some(ms) = header("retry-after-ms").and_then(|v| v.parse::<f64>().ok()) {
+        return Some(ms.max(0.0) as u64);
+    }
+    let value = header("retry-after")?;
+    if let Ok(secs) = value.parse::<f64>() {
+        return Some((secs.max(0.0) * 1000.0) as u64);
+    }
+    let at = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let wait = at.timestamp_millis() - chrono::Utc::now().timestamp_millis();
+    Some(wait.max(0) as u64)
+}
+
+/// Replace every credential in `text` with a placeholder. Values too short to be
+/// credentials are left alone, so they don't mangle ordinary words.
+pub fn redact_secrets(mut text: String, secrets: &[String]) -> String {
+    for secret in secrets.iter().filter(|s| s.len() >= 8) {
+        text = text.replace(secret.as_str(), "[REDACTED]");
+    }
+    text
+}
+
 /// Trait for AI provider implementations.
 ///
 /// Each provider (OpenAI, Anthropic, Google, etc.) implements this trait
@@ -45,5 +127,162 @@ pub trait Provider: Send + Sync {
 
     /// List models available from this provider.
     /// Some providers support dynamic model listing via API; others return a static list.
-    async fn list_models(&self, api_key: &str) -> Result<Vec<ModelDef>, ProviderError>;
+    ///
+    /// `provider` is the provider id the results are tagged with and `base_url` is the
+    /// endpoint to query, since one implementation may serve several providers.
+    async fn list_models(
+        &self,
+        provider: &str,
+        base_url: &str,
+        api_key: &str,
+    ) -> Result<Vec<ModelDef>, ProviderError>;
+
+    /// Whether requests need a credential. Local servers such as Ollama run without one.
+    fn requires_api_key(&self) -> bool {
+        true
+    }
+
+    /// Endpoint parameters implied by the credential, used for placeholders the
+    /// config leaves unset.
+    fn credential_endpoint_params(&self, _api_key: &str) -> HashMap<String, String> {
+        HashMap::new()
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Endpoint templates
+// ---------------------------------------------------------------------------
+
+/// Placeholders in a base URL template, as `(name, default)` pairs.
+///
+/// Templates use `{name}` for required parameters and `{name:default}` for
+/// optional ones, e.g. `https://bedrock-runtime.{region:us-east-1}.amazonaws.com`.
+pub fn template_params(template: &str) -> Vec<(String, Option<String>)> {
+    let mut params = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = match rest[start..].find('}') {
+            Some(e) => start + e,
+            None => break,
+        };
+        let inner = &rest[start + 1..end];
+        let (name, default) = match inner.split_once(':') {
+            Some((n, d)) => (n, Some(d.to_string())),
+            None => (inner, None),
+        };
+        params.push((name.to_string(), default));
+        rest = &rest[end + 1..];
+    }
+    params
+}
+
+/// Fill in a base URL template from provider parameters.
+pub fn resolve_base_url(
+    template: &str,
+    params: &HashMap<String, String>,
+) -> Result<String, ProviderError> {
+    let mut url = template.to_string();
+    for (name, default) in template_params(template) {
+        let placeholder = match &default {
+            Some(d) => format!("{{{}:{}}}", name, d),
+            None => format!("{{{}}}", name),
+        };
+        let value = params
+            .get(&name)
+            .filter(|v| !v.is_empty())
+            .or(default.as_ref())
+            .ok_or_else(|| {
+                ProviderError::Other(format!("Missing endpoint parameter: {}", name))
+            })?;
+        url = url.replace(&placeholder, value);
+    }
+    Ok(url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::{HeaderMap, HeaderValue};
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        for (name, value) in pairs {
+            map.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+        map
+    }
+
+    #[test]
+    fn retry_after_reads_seconds_milliseconds_and_dates() {
+        assert_eq!(retry_after_ms(&headers(&[("retry-after", "7")])), Some(7000));
+        assert_eq!(retry_after_ms(&headers(&[("retry-after", "7"), ("retry-after-ms", "1500")])), Some(1500));
+        assert_eq!(retry_after_ms(&headers(&[("retry-after", "Wed, 21 Oct 2015 07:28:00 GMT")])), Some(0));
+        assert_eq!(retry_after_ms(&headers(&[("retry-after", "soon")])), None);
+        assert_eq!(retry_after_ms(&headers(&[])), None);
+
+        let later = (chrono::Utc::now() + chrono::Duration::seconds(60)).to_rfc2822();
+        let wait = retry_after_ms(&headers(&[("retry-after", &later)])).unwrap();
+        assert!((55_000..=60_000).contains(&wait), "{}", wait);
+    }
+
+    #[test]
+    fn too_many_requests_is_rate_limited_unless_the_quota_is_used_up() {
+        assert!(matches!(
+            ProviderError::from_status(429, Some(2000), "slow down".into()),
+            ProviderError::RateLimited { retry_after_ms: Some(2000) }
+        ));
+        assert!(matches!(
+            ProviderError::from_status(429, None, "slow down".into()),
+            ProviderError::RateLimited { retry_after_ms: None }
+        ));
+        assert!(matches!(
+            ProviderError::from_status(429, None, r#"{"error": {"status": "RESOURCE_EXHAUSTED"}}"#.into()),
+            ProviderError::RateLimited { retry_after_ms: None }
+        ));
+        assert!(matches!(
+            ProviderError::from_status(429, None, r#"{"error": {"code": "insufficient_quota"}}"#.into()),
+            ProviderError::Http { status: 429, .. }
+        ));
+        assert!(matches!(ProviderError::from_status(500, Some(2000), String::new()), ProviderError::Http { status: 500, .. }));
+    }
+
+    #[test]
+    fn templates_list_required_and_defaulted_params() {
+        assert_eq!(
+            template_params("https://{resource}.openai.azure.com/v{version:1}/{path:a:b}"),
+            vec![
+                ("resource".to_string(), None),
+                ("version".to_string(), Some("1".to_string())),
+                ("path".to_string(), Some("a:b".to_string())),
+            ]
+        );
+        assert!(template_params("https://api.example.com/v1").is_empty());
+        assert_eq!(template_params("https://{host}/{broken"), vec![("host".to_string(), None)]);
+    }
+
+    #[test]
+    fn base_urls_fill_params_and_fall_back_to_defaults() {
+        let template = "https://bedrock-runtime.{region:us-east-1}.amazonaws.com/{account}";
+        let params = |pairs: &[(&str, &str)]| {
+            pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect::<HashMap<_, _>>()
+        };
+
+        assert_eq!(
+            resolve_base_url(template, &params(&[("region", "eu-west-1"), ("account", "acme")])).unwrap(),
+            "https://bedrock-runtime.eu-west-1.amazonaws.com/acme"
+        );
+        assert_eq!(
+            resolve_base_url(template, &params(&[("region", ""), ("account", "acme")])).unwrap(),
+            "https://bedrock-runtime.us-east-1.amazonaws.com/acme"
+        );
+
+        for missing in [params(&[("region", "eu-west-1")]), params(&[("account", "")])] {
+            let err = resolve_base_url(template, &missing).unwrap_err();
+            assert!(err.to_string().contains("Missing endpoint parameter: account"), "{}", err);
+        }
+        assert_eq!(
+            resolve_base_url("https://api.example.com/v1", &HashMap::new()).unwrap(),
+            "https://api.example.com/v1"
+        );
+    }
 }
@@ -0,0 +1,709 @@
+use super::{Provider, ProviderError};
+use crate::models::catalog::{DiscoveredModel, merge_discovered};
+use crate::types::*;
+use async_trait::async_trait;
+use futures::stream::{BoxStream, StreamExt};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+
+/// Native Ollama provider speaking `/api/chat` (NDJSON streaming).
+pub struct OllamaProvider {
+    client: Client,
+    settings: OllamaConfig,
+}
+
+/// Ollama settings from the config file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OllamaConfig {
+    /// How long Ollama keeps the model loaded after a request (e.g. "5m", "-1").
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keep_alive: Option<String>,
+    /// Context window to load models with. Ollama's own default when unset; the
+    /// model's full window can need more memory than the machine has.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub num_ctx: Option<u64>,
+}
+
+impl OllamaProvider {
+    pub fn new() -> Self {
+        Self {
+            client: Client::new(),
+            settings: OllamaConfig::default(),
+        }
+    }
+
+    pub fn with_settings(mut self, settings: OllamaConfig) -> Self {
+        self.settings = settings;
+        self
+    }
+}
+
+impl Default for OllamaProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Request types
+// ---------------------------------------------------------------------------
+
+#[derive(Serialize)]
+struct ChatRequest {
+    model: String,
+    messages: Vec<OllamaMessage>,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<ToolSchema>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    think: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    keep_alive: Option<String>,
+    options: ModelOptions,
+}
+
+#[derive(Serialize)]
+struct ModelOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    num_predict: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    num_ctx: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct OllamaMessage {
+    role: String,
+    content: String,
+    /// Base64-encoded images (no data URL prefix).
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    images: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thinking: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_calls: Option<Vec<OllamaToolCall>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_name: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct OllamaToolCall {
+    function: OllamaFunctionCall,
+}
+
+#[derive(Serialize, Deserialize)]
+struct OllamaFunctionCall {
+    name: String,
+    #[serde(default)]
+    arguments: serde_json::Value,
+}
+
+#[derive(Serialize)]
+struct ToolSchema {
+    #[serde(rename = "type")]
+    r#type: String,
+    function: FunctionSchema,
+}
+
+#[derive(Serialize)]
+struct FunctionSchema {
+    name: String,
+    description: String,
+    parameters: serde_json::Value,
+}
+
+// ---------------------------------------------------------------------------
+// Response types
+// ---------------------------------------------------------------------------
+
+#[derive(Deserialize)]
+struct ChatChunk {
+    message: Option<ChunkMessage>,
+    #[serde(default)]
+    done: bool,
+    done_reason: Option<String>,
+    prompt_eval_count: Option<u64>,
+    eval_count: Option<u64>,
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ChunkMessage {
+    content: Option<String>,
+    thinking: Option<String>,
+    tool_calls: Option<Vec<OllamaToolCall>>,
+}
+
+#[derive(Deserialize)]
+struct TagsResponse {
+    #[serde(default)]
+    models: Vec<TagEntry>,
+}
+
+#[derive(Deserialize)]
+struct TagEntry {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct ShowResponse {
+    #[serde(default)]
+    capabilities: Vec<String>,
+    #[serde(default)]
+    model_info: HashMap<String, serde_json::Value>,
+}
+
+// ---------------------------------------------------------------------------
+// Conversion helpers
+// ---------------------------------------------------------------------------
+
+fn convert_messages(context: &ChatContext) -> Vec<OllamaMessage> {
+    let mut msgs = Vec::new();
+
+    if let Some(sys) = &context.system_prompt {
+        msgs.push(OllamaMessage {
+            role: "system".into(),
+            content: sys.clone(),
+            images: Vec::new(),
+            thinking: None,
+            tool_calls: None,
+            tool_name: None,
+        });
+    }
+
+    for msg in &context.messages {
+        match msg {
+            Message::User(u) => {
+                let mut content = String::new();
+                let mut images = Vec::new();
+                for block in &u.content {
+                    match block {
+                        ContentBlock::Text(t) => content.push_str(&t.text),
+                        ContentBlock::Image(img) => images.push(img.data.clone()),
+                        _ => {}
+                    }
+                }
+                msgs.push(OllamaMessage {
+                    role: "user".into(),
+                    content,
+                    images,
+                    thinking: None,
+                    tool_calls: None,
+                    tool_name: None,
+                });
+            }
+            Message::Assistant(a) => {
+                let mut content = String::new();
+                let mut thinking = String::new();
+                let mut tool_calls = Vec::new();
+                for block in &a.content {
+                    match block {
+                        ContentBlock::Text(t) => content.push_str(&t.text),
+                        ContentBlock::Thinking(th) => thinking.push_str(&th.thinking),
+                        ContentBlock::ToolCall(tc) => tool_calls.push(OllamaToolCall {
+                            function: OllamaFunctionCall {
+                                name: tc.name.clone(),
+                                arguments: tc.arguments.clone(),
+                            },
+                        }),
+                        _ => {}
+                    }
+                }
+                msgs.push(OllamaMessage {
+                    role: "assistant".into(),
+                    content,
+                    images: Vec::new(),
+                    thinking: if thinking.is_empty() { None } else { Some(thinking) },
+                    tool_calls: if tool_calls.is_empty() { None } else { Some(tool_calls) },
+                    tool_name: None,
+                });
+            }
+            Message::ToolResult(tr) => {
+                let text = tr
+                    .content
+                    .iter()
+                    .filter_map(|b| {
+                        if let ContentBlock::Text(t) = b {
+                            Some(t.text.as_str())
+                        } else {
+                            None
+                        }
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+
+                msgs.push(OllamaMessage {
+                    role: "tool".into(),
+                    content: text,
+                    images: Vec::new(),
+                    thinking: None,
+                    tool_calls: None,
+                    tool_name: Some(tr.tool_name.clone()),
+                });
+            }
+        }
+    }
+
+    msgs
+}
+
+fn convert_tools(tools: &[ToolDef]) -> Vec<ToolSchema> {
+    tools
+        .iter()
+        .map(|t| ToolSchema {
+            r#type: "function".into(),
+            function: FunctionSchema {
+                name: t.name.clone(),
+                description: t.description.clone(),
+                parameters: t.parameters.clone(),
+            },
+        })
+        .collect()
+}
+
+/// Ollama itself has no auth, but it is often put behind a reverse proxy that does.
+fn with_auth(req: reqwest::RequestBuilder, api_key: &str) -> reqwest::RequestBuilder {
+    if api_key.is_empty() {
+        req
+    } else {
+        req.header("Authorization", format!("Bearer {}", api_key))
+    }
+}
+
+/// gpt-oss takes an effort level for `think`; other thinking models take a boolean.
+fn think_value(model: &ModelDef, level: &ThinkingLevel) -> serde_json::Value {
+    if model.id.starts_with("gpt-oss") {
+        match level {
+            ThinkingLevel::Minimal | ThinkingLevel::Low => json!("low"),
+            ThinkingLevel::Medium => json!("medium"),
+            ThinkingLevel::High => json!("high"),
+        }
+    } else {
+        json!(true)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Provider impl
+// ---------------------------------------------------------------------------
+
+#[async_trait]
+impl Provider for OllamaProvider {
+    fn stream(
+        &self,
+        model: &ModelDef,
+        context: &ChatContext,
+        options: &StreamOptions,
+    ) -> BoxStream<'static, Result<StreamEvent, ProviderError>> {
+        let base_url = model.base_url.trim_end_matches('/').to_string();
+        let url = format!("{}/api/chat", base_url);
+
+        let tools = if context.tools.is_empty() {
+            None
+        } else {
+            Some(convert_tools(&context.tools))
+        };
+
+        let think = if model.reasoning {
+            options.reasoning.as_ref().map(|l| think_value(model, l))
+        } else {
+            None
+        };
+
+        let body = ChatRequest {
+            model: model.id.clone(),
+            messages: convert_messages(context),
+            stream: true,
+            tools,
+            think,
+            keep_alive: self.settings.keep_alive.clone(),
+            options: ModelOptions {
+                temperature: options.temperature,
+                num_predict: options.max_tokens,
+                num_ctx: self.settings.num_ctx,
+            },
+        };
+
+        let mut headers_map = HashMap::new();
+        if let Some(model_headers) = &model.headers {
+            headers_map.extend(model_headers.clone());
+        }
+        if let Some(extra) = &options.extra_headers {
+            headers_map.extend(extra.clone());
+        }
+
+        let client = self.client.clone();
+        let api_key = options.api_key.clone().unwrap_or_default();
+        let model_id = model.id.clone();
+        let provider_id = model.provider.clone();
+
+        let s = async_stream::stream! {
+            let mut req = with_auth(client.post(&url), &api_key).header("Content-Type", "application/json");
+            for (k, v) in &headers_map {
+                req = req.header(k.as_str(), v.as_str());
+            }
+
+            let resp = match req.json(&body).send().await {
+                Ok(r) => r,
+                Err(e) => {
+                    yield Err(ProviderError::Network(e));
+                    return;
+                }
+            };
+
+            let status = resp.status();
+            if !status.is_success() {
+                yield Err(ProviderError::from_response(resp).await);
+                return;
+            }
+
+            yield Ok(StreamEvent::Start);
+
+            let mut text_buf = String::new();
+            let mut thinking_buf = String::new();
+            let mut tool_calls: Vec<ToolCall> = Vec::new();
+            let mut usage = Usage::default();
+            let mut stop_reason = StopReason::Stop;
+            let mut line_buf = String::new();
+
+            let mut byte_stream = resp.bytes_stream();
+
+            while let Some(chunk_result) = byte_stream.next().await {
+                let chunk_bytes = match chunk_result {
+                    Ok(b) => b,
+                    Err(e) => {
+                        yield Err(ProviderError::Network(e));
+                        return;
+                    }
+                };
+
+                line_buf.push_str(&String::from_utf8_lossy(&chunk_bytes));
+
+                // One JSON object per line
+                while let Some(newline_pos) = line_buf.find('\n') {
+                    let line: String = line_buf.drain(..=newline_pos).collect();
+                    let line = line.trim();
+                    if line.is_empty() {
+                        continue;
+                    }
+
+                    let chunk: ChatChunk = match serde_json::from_str(line) {
+                        Ok(c) => c,
+                        Err(_) => continue,
+                    };
+
+                    if let Some(err) = chunk.error {
+                        yield Err(ProviderError::Other(format!("Ollama error: {}", err)));
+                        return;
+                    }
+
+                    if let Some(msg) = chunk.message {
+                        if let Some(th) = msg.thinking.filter(|t| !t.is_empty()) {
+                            thinking_buf.push_str(&th);
+                            yield Ok(StreamEvent::ThinkingDelta(th));
+                        }
+                        if let Some(text) = msg.content.filter(|t| !t.is_empty()) {
+                            text_buf.push_str(&text);
+                            yield Ok(StreamEvent::TextDelta(text));
+                        }
+                        // Ollama sends each tool call complete, without an id
+                        for call in msg.tool_calls.unwrap_or_default() {
+                            let idx = tool_calls.len();
+                            let tc = ToolCall {
+                                id: format!("call_{}", uuid::Uuid::new_v4().simple()),
+                                name: call.function.name,
+                                arguments: call.function.arguments,
+                                thought_signature: None,
+                            };
+                            tool_calls.push(tc.clone());
+
+                            yield Ok(StreamEvent::ToolCallStart {
+                                index: idx,
+                                id: tc.id.clone(),
+                                name: tc.name.clone(),
+                            });
+                            yield Ok(StreamEvent::ToolCallDelta {
+                                index: idx,
+                                delta: tc.arguments.to_string(),
+                            });
+                            yield Ok(StreamEvent::ToolCallEnd {
+                                index: idx,
+                                tool_call: tc,
+                            });
+                        }
+                    }
+
+                    if chunk.done {
+                        usage.input_tokens = chunk.prompt_eval_count.unwrap_or(0);
+                        usage.output_tokens = chunk.eval_count.unwrap_or(0);
+                        usage.total_tokens = usage.input_tokens + usage.output_tokens;
+                        stop_reason = match chunk.done_reason.as_deref() {
+                            Some("length") => StopReason::Length,
+                            _ => StopReason::Stop,
+                        };
+                    }
+                }
+            }
+
+            if !tool_calls.is_empty() {
+                stop_reason = StopReason::ToolUse;
+            }
+
+            let mut content = Vec::new();
+            if !thinking_buf.is_empty() {
+                content.push(ContentBlock::Thinking(ThinkingContent { thinking: thinking_buf, signature: None }));
+            }
+            if !text_buf.is_empty() {
+                content.push(ContentBlock::Text(TextContent { text: text_buf, thought_signature: None }));
+            }
+            for tc in tool_calls {
+                content.push(ContentBlock::ToolCall(tc));
+            }
+
+            let msg = AssistantMessage {
+                content,
+                model: model_id,
+                provider: provider_id,
+                usage: Some(usage),
+                stop_reason,
+                error_message: None,
+            };
+
+            yield Ok(StreamEvent::Done { message: msg });
+        };
+
+        Box::pin(s)
+    }
+
+    async fn list_models(
+        &self,
+        provider: &str,
+        base_url: &str,
+        api_key: &str,
+    ) -> Result<Vec<ModelDef>, ProviderError> {
+        let base_url = base_url.trim_end_matches('/');
+
+        let resp = with_auth(self.client.get(format!("{}/api/tags", base_url)), api_key)
+            .send()
+            .await?;
+        let status = resp.status();
+        if !status.is_success() {
+            return Err(ProviderError::from_response(resp).await);
+        }
+        let tags: TagsResponse = resp.json().await?;
+
+        let mut discovered = Vec::new();
+        for tag in tags.models {
+            // /api/show carries capabilities and the architecture's context length
+            let show = match with_auth(self.client.post(format!("{}/api/show", base_url)), api_key)
+                .json(&json!({ "model": tag.name }))
+                .send()
+                .await
+            {
+                Ok(r) if r.status().is_success() => r.json::<ShowResponse>().await.ok(),
+                _ => None,
+            };
+
+            let mut model = DiscoveredModel {
+                id: tag.name,
+                ..Default::default()
+            };
+
+            if let Some(show) = show {
+                // Skip embedding-only models
+                if !show.capabilities.is_empty()
+                    && !show.capabilities.iter().any(|c| c == "completion")
+                {
+                    continue;
+                }
+
+                model.reasoning = Some(show.capabilities.iter().any(|c| c == "thinking"));
+                let mut input = vec![InputModality::Text];
+                if show.capabilities.iter().any(|c| c == "vision") {
+                    input.push(InputModality::Image);
+                }
+                model.input = Some(input);
+
+                let arch = show
+                    .model_info
+                    .get("general.architecture")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or_default();
+                model.context_window = show
+                    .model_info
+                    .get(&format!("{}.context_length", arch))
+                    .and_then(|v| v.as_u64());
+            }
+
+            discovered.push(model);
+        }
+
+        Ok(merge_discovered(provider, base_url, Api::OllamaChat, discovered))
+    }
+
+    fn requires_api_key(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::test_server::{FakeServer, Request, Response};
+
+    fn model(id: &str, base_url: &str) -> ModelDef {
+        ModelDef {
+            id: id.into(),
+            name: id.into(),
+            api: Api::OllamaChat,
+            provider: "ollama".into(),
+            base_url: base_url.into(),
+            reasoning: true,
+            input: vec![InputModality::Text, InputModality::Image],
+            cost: ModelCost::default(),
+            context_window: 32_768,
+            max_tokens: 4096,
+            headers: None,
+            compat: None,
+        }
+    }
+
+    fn ndjson(lines: &[serde_json::Value]) -> Response {
+        Response {
+            status: 200,
+            headers: vec![("content-type".into(), "application/x-ndjson".into())],
+            body: lines.iter().map(|l| format!("{}\n", l)).collect(),
+        }
+    }
+
+    fn user_with_image() -> ChatContext {
+        ChatContext {
+            system_prompt: Some("Be brief".into()),
+            messages: vec![Message::User(UserMessage {
+                content: vec![
+                    ContentBlock::Text(TextContent { text: "What is this?".into(), thought_signature: None }),
+                    ContentBlock::Image(ImageContent { data: "aGVsbG8=".into(), mime_type: "image/png".into() }),
+                ],
+            })],
+            tools: Vec::new(),
+            hosted_tools: Vec::new(),
+        }
+    }
+
+    async fn collect(
+        provider: &OllamaProvider,
+        model: &ModelDef,
+        context: &ChatContext,
+        options: &StreamOptions,
+    ) -> Vec<Result<StreamEvent, ProviderError>> {
+        provider.stream(model, context, options).collect().await
+    }
+
+    #[tokio::test]
+    async fn streams_thinking_text_and_tool_calls() {
+        let server = FakeServer::start(|_| {
+            ndjson(&[
+                json!({"message": {"role": "assistant", "content": "", "thinking": "Looking"}, "done": false}),
+                json!({"message": {"role": "assistant", "content": "A cat"}, "done": false}),
+                json!({"message": {"role": "assistant", "content": "", "tool_calls": [
+                    {"function": {"name": "lookup", "arguments": {"q": "cat"}}}
+                ]}, "done": false}),
+                json!({"message": {"role": "assistant", "content": ""}, "done": true, "done_reason": "stop",
+                    "prompt_eval_count": 12, "eval_count": 5}),
+            ])
+        })
+        .await;
+
+        let provider = OllamaProvider::new().with_settings(OllamaConfig { keep_alive: Some("10m".into()), num_ctx: None });
+        let options = StreamOptions { api_key: Some("proxy-key".into()), reasoning: Some(ThinkingLevel::High), ..Default::default() };
+        let events = collect(&provider, &model("qwen3", &server.url), &user_with_image(), &options).await;
+
+        let message = match events.last() {
+            Some(Ok(StreamEvent::Done { message })) => message.clone(),
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(message.stop_reason, StopReason::ToolUse);
+        let usage = message.usage.unwrap();
+        assert_eq!((usage.input_tokens, usage.output_tokens, usage.total_tokens), (12, 5, 17));
+        match &message.content[..] {
+            [ContentBlock::Thinking(th), ContentBlock::Text(t), ContentBlock::ToolCall(tc)] => {
+                assert_eq!(th.thinking, "Looking");
+                assert_eq!(t.text, "A cat");
+                assert_eq!((tc.name.as_str(), &tc.arguments), ("lookup", &json!({"q": "cat"})));
+            }
+            other => panic!("{:?}", other),
+        }
+
+        let request = &server.requests()[0];
+        assert_eq!(request.target, "/api/chat");
+        assert_eq!(request.headers["authorization"], "Bearer proxy-key");
+        let body = request.json();
+        assert_eq!(body["think"], json!(true));
+        assert_eq!(body["keep_alive"], "10m");
+        assert_eq!(body["options"], json!({}), "num_ctx is left to Ollama");
+        assert_eq!(body["messages"][0], json!({"role": "system", "content": "Be brief"}));
+        assert_eq!(body["messages"][1]["images"], json!(["aGVsbG8="]));
+    }
+
+    #[tokio::test]
+    async fn gpt_oss_takes_an_effort_level_and_no_key_sends_no_auth() {
+        let server = FakeServer::start(|_| ndjson(&[json!({"done": true, "done_reason": "length"})])).await;
+
+        let options = StreamOptions { reasoning: Some(ThinkingLevel::Minimal), max_tokens: Some(64), ..Default::default() };
+        let events = collect(&OllamaProvider::new(), &model("gpt-oss:20b", &server.url), &user_with_image(), &options).await;
+        assert!(matches!(events.last(), Some(Ok(StreamEvent::Done { message })) if message.stop_reason == StopReason::Length));
+
+        let request = &server.requests()[0];
+        assert!(!request.headers.contains_key("authorization"));
+        let body = request.json();
+        assert_eq!(body["think"], "low");
+        assert_eq!(body["options"], json!({"num_predict": 64}));
+        assert!(body.get("keep_alive").is_none());
+    }
+
+    #[tokio::test]
+    async fn error_lines_end_the_stream() {
+        let server = FakeServer::start(|_| ndjson(&[json!({"error": "model 'nope' not found"})])).await;
+        let events = collect(&OllamaProvider::new(), &model("nope", &server.url), &user_with_image(), &StreamOptions::default()).await;
+        match events.last() {
+            Some(Err(ProviderError::Other(msg))) => assert!(msg.contains("not found"), "{}", msg),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn discovery_reads_tags_and_capabilities() {
+        let server = FakeServer::start(|req: &Request| match req.target.as_str() {
+            "/api/tags" => Response::json(200, json!({"models": [
+                {"name": "llava:7b"}, {"name": "nomic-embed-text"}, {"name": "mystery"}
+            ]})),
+            "/api/show" => match req.json()["model"].as_str().unwrap() {
+                "llava:7b" => Response::json(200, json!({
+                    "capabilities": ["completion", "vision", "thinking"],
+                    "model_info": {"general.architecture": "llama", "llama.context_length": 4096},
+                })),
+                "nomic-embed-text" => Response::json(200, json!({"capabilities": ["embedding"]})),
+                _ => Response::json(404, json!({"error": "not found"})),
+            },
+            _ => Response::json(404, json!({})),
+        })
+        .await;
+
+        let models = OllamaProvider::new().list_models("ollama", &format!("{}/", server.url), "proxy-key").await.unwrap();
+        assert_eq!(models.iter().map(|m| m.id.as_str()).collect::<Vec<_>>(), ["llava:7b", "mystery"]);
+
+        let llava = &models[0];
+        assert!(llava.reasoning);
+        assert_eq!(llava.input, [InputModality::Text, InputModality::Image]);
+        assert_eq!(llava.context_window, 4096);
+        assert_eq!(llava.base_url, server.url);
+        assert_eq!(models[1].input, [InputModality::Text]);
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 4);
+        assert!(requests.iter().all(|r| r.headers["authorization"] == "Bearer proxy-key"));
+    }
+}
@@ -1,7 +1,8 @@
-use super::{Provider, ProviderError};
+use super::{Provider, ProviderError, retry_after_ms};
+use crate::models::catalog::{DiscoveredModel, merge_discovered};
 use crate::types::*;
 use async_trait::async_trait;
-use futures::stream::{self, BoxStream};
+use futures::stream::BoxStream;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
@@ -10,14 +11,96 @@ use std::collections::HashMap;
 /// OpenAI-compatible provider (also used by xAI, Groq, DeepSeek, etc.).
 pub struct OpenAiProvider {
     client: Client,
+    /// Set for Azure OpenAI, which routes by deployment and api-version.
+    azure: Option<AzureConfig>,
 }
 
 impl OpenAiProvider {
     pub fn new() -> Self {
         Self {
             client: Client::new(),
+            azure: None,
         }
     }
+
+    /// Azure OpenAI variant. Model base URLs point at the resource endpoint.
+    pub fn azure(config: AzureConfig) -> Self {
+        Self {
+            client: Client::new(),
+            azure: Some(config),
+        }
+    }
+
+    fn chat_url(&self, base_url: &str, model_id: &str) -> String {
+        match &self.azure {
+            Some(azure) => format!(
+                "{}/openai/deployments/{}/chat/completions?api-version={}",
+                base_url,
+                azure.deployment(model_id),
+                azure.api_version()
+            ),
+            None => format!("{}/chat/completions", base_url),
+        }
+    }
+
+    fn auth_header(&self, key: &str, kind: Option<CredentialKind>) -> (String, String) {
+        let auth = match &self.azure {
+            Some(azure) => azure.auth(kind),
+            None => AzureAuth::Bearer,
+        };
+        match auth {
+            AzureAuth::ApiKey => ("api-key".into(), key.into()),
+            AzureAuth::Bearer => ("Authorization".into(), format!("Bearer {}", key)),
+        }
+    }
+}
+
+/// Default Azure OpenAI `api-version`.
+pub const AZURE_API_VERSION: &str = "2024-10-21";
+
+/// Azure OpenAI settings from the config file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AzureConfig {
+    /// `api-version` query parameter. Defaults to [`AZURE_API_VERSION`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_version: Option<String>,
+    /// Model id -> deployment name. Models without an entry use their id.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub deployments: HashMap<String, String>,
+    /// How the credential is sent. Defaults to `api-key`, or `bearer` for OAuth tokens.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth: Option<AzureAuth>,
+}
+
+/// Header Azure OpenAI reads the credential from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AzureAuth {
+    /// Resource key in `api-key`.
+    ApiKey,
+    /// Entra ID access token in `Authorization`.
+    Bearer,
+}
+
+impl AzureConfig {
+    pub fn api_version(&self) -> &str {
+        self.api_version.as_deref().unwrap_or(AZURE_API_VERSION)
+    }
+
+    pub fn auth(&self, kind: Option<CredentialKind>) -> AzureAuth {
+        match (self.auth, kind) {
+            (Some(auth), _) => auth,
+            (None, Some(CredentialKind::OAuth | CredentialKind::SetupToken)) => AzureAuth::Bearer,
+            (None, _) => AzureAuth::ApiKey,
+        }
+    }
+
+    pub fn deployment<'a>(&'a self, model_id: &'a str) -> &'a str {
+        self.deployments
+            .get(model_id)
+            .map(|d| d.as_str())
+            .unwrap_or(model_id)
+    }
 }
 
 impl Default for OpenAiProvider {
@@ -38,11 +121,18 @@ struct ChatRequest {
     temperature: Option<f64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     max_tokens: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_completion_tokens: Option<u64>,
     stream: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     tools: Option<Vec<ToolSchema>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     stream_options: Option<StreamOptionsReq>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reasoning_effort: Option<String>,
+    /// Hosted web search, for backends whose compat allows it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    web_search_options: Option<serde_json::Value>,
 }
 
 #[derive(Serialize)]
@@ -89,6 +179,8 @@ struct FunctionSchema {
     name: String,
     description: String,
     parameters: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    strict: Option<bool>,
 }
 
 // ---------------------------------------------------------------------------
@@ -99,22 +191,77 @@ struct FunctionSchema {
 struct StreamChunk {
     choices: Option<Vec<StreamChoice>>,
     usage: Option<UsageResp>,
+    /// Azure content filter verdicts on the prompt.
+    #[serde(default, alias = "prompt_annotations")]
+    prompt_filter_results: Vec<PromptFilterResult>,
 }
 
 #[derive(Deserialize)]
 struct StreamChoice {
     delta: Option<DeltaContent>,
     finish_reason: Option<String>,
+    /// Azure content filter verdicts on the completion.
+    #[serde(default)]
+    content_filter_results: ContentFilterResults,
+}
+
+/// Azure content filter verdicts by category, e.g.
+/// `"hate": {"filtered": true, "severity": "high"}`.
+type ContentFilterResults = HashMap<String, serde_json::Value>;
+
+#[derive(Deserialize)]
+struct PromptFilterResult {
+    #[serde(default)]
+    content_filter_results: ContentFilterResults,
+}
+
+/// Error body of a request Azure refused, with `code: "content_filter"` when
+/// the prompt was filtered.
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error: ErrorDetail,
+}
+
+#[derive(Deserialize)]
+struct ErrorDetail {
+    code: Option<String>,
+    message: Option<String>,
+    innererror: Option<InnerError>,
+}
+
+#[derive(Deserialize)]
+struct InnerError {
+    #[serde(default)]
+    content_filter_result: ContentFilterResults,
 }
 
 #[derive(Deserialize)]
 struct DeltaContent {
     content: Option<String>,
+    /// DeepSeek, Qwen, Kimi and xAI stream reasoning here.
+    reasoning_content: Option<String>,
+    /// OpenRouter and some vLLM builds use `reasoning` instead.
+    reasoning: Option<String>,
     tool_calls: Option<Vec<ToolCallDelta>>,
+    /// Web search citations.
+    annotations: Option<Vec<Annotation>>,
     #[allow(dead_code)]
     role: Option<String>,
 }
 
+#[derive(Deserialize)]
+struct Annotation {
+    url_citation: Option<UrlCitation>,
+}
+
+#[derive(Deserialize)]
+struct UrlCitation {
+    url: Option<String>,
+    title: Option<String>,
+    start_index: Option<u64>,
+    end_index: Option<u64>,
+}
+
 #[derive(Deserialize)]
 struct ToolCallDelta {
     index: Option<usize>,
@@ -133,6 +280,12 @@ struct UsageResp {
     prompt_tokens: Option<u64>,
     completion_tokens: Option<u64>,
     total_tokens: Option<u64>,
+    completion_tokens_details: Option<CompletionTokensDetails>,
+}
+
+#[derive(Deserialize)]
+struct CompletionTokensDetails {
+    reasoning_tokens: Option<u64>,
 }
 
 // ---------------------------------------------------------------------------
@@ -144,23 +297,178 @@ struct ModelsResponse {
     data: Vec<ModelEntry>,
 }
 
+/// Entry of `GET /models`. Besides the OpenAI fields, several backends report
+/// extra metadata (OpenRouter/Together: `context_length`, `pricing`; Groq:
+/// `context_window`; vLLM: `max_model_len`).
 #[derive(Deserialize)]
 struct ModelEntry {
     id: String,
     #[allow(dead_code)]
     owned_by: Option<String>,
+    name: Option<String>,
+    context_length: Option<u64>,
+    context_window: Option<u64>,
+    max_model_len: Option<u64>,
+    pricing: Option<PricingEntry>,
+    top_provider: Option<TopProviderEntry>,
+    architecture: Option<ArchitectureEntry>,
+    supported_parameters: Option<Vec<String>>,
+}
+
+/// OpenRouter pricing, in USD per token encoded as strings.
+#[derive(Deserialize)]
+struct PricingEntry {
+    prompt: Option<String>,
+    completion: Option<String>,
+    input_cache_read: Option<String>,
+    input_cache_write: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TopProviderEntry {
+    max_completion_tokens: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct ArchitectureEntry {
+    input_modalities: Option<Vec<String>>,
+}
+
+impl ModelEntry {
+    fn into_discovered(self) -> DiscoveredModel {
+        let per_million = |v: &Option<String>| {
+            v.as_deref()
+                .and_then(|p| p.parse::<f64>().ok())
+                .map(|p| p * 1_000_000.0)
+                .unwrap_or(0.0)
+        };
+
+        DiscoveredModel {
+            cost: self.pricing.as_ref().map(|p| ModelCost {
+                input: per_million(&p.prompt),
+                output: per_million(&p.completion),
+                cache_read: per_million(&p.input_cache_read),
+                cache_write: per_million(&p.input_cache_write),
+            }),
+            context_window: self
+                .context_length
+                .or(self.context_window)
+                .or(self.max_model_len),
+            max_tokens: self.top_provider.and_then(|t| t.max_completion_tokens),
+            reasoning: self
+                .supported_parameters
+                .map(|params| params.iter().any(|p| p == "reasoning")),
+            input: self
+                .architecture
+                .and_then(|a| a.input_modalities)
+                .map(|modalities| {
+                    let mut input = vec![InputModality::Text];
+                    if modalities.iter().any(|m| m == "image") {
+                        input.push(InputModality::Image);
+                    }
+                    input
+                }),
+            name: self.name,
+            id: self.id,
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Compatibility profiles
+// ---------------------------------------------------------------------------
+
+/// Built-in compatibility defaults for the providers served by this implementation.
+pub fn provider_compat(provider: &str) -> OpenAiCompat {
+    let mut compat = OpenAiCompat::default();
+    match provider {
+        "openai" | "azure-openai" => {
+            compat.max_tokens_field = Some(MaxTokensField::MaxCompletionTokens);
+            compat.supports_developer_role = Some(true);
+            compat.supports_reasoning_effort = Some(true);
+        }
+        "xai" | "openrouter" => {
+            compat.supports_reasoning_effort = Some(true);
+        }
+        "groq" => {
+            compat.max_tokens_field = Some(MaxTokensField::MaxCompletionTokens);
+            compat.supports_tool_message_name = Some(false);
+            compat.supports_reasoning_effort = Some(true);
+        }
+        "deepseek" => {
+            compat.supports_tool_message_name = Some(false);
+        }
+        "mistral" => {
+            compat.supports_stream_options = Some(false);
+        }
+        "zhipuai" => {
+            compat.supports_stream_options = Some(false);
+            compat.supports_tool_message_name = Some(false);
+        }
+        "ollama" | "vllm" => {
+            compat.supports_tool_message_name = Some(false);
+        }
+        "github-copilot" => {
+            compat.supports_stream_options = Some(false);
+            compat.supports_tool_message_name = Some(false);
+        }
+        _ => {}
+    }
+    compat
+}
+
+/// Fully resolved compatibility settings for a single request.
+struct Compat {
+    max_tokens_field: MaxTokensField,
+    stream_options: bool,
+    developer_role: bool,
+    tool_message_name: bool,
+    reasoning_effort: bool,
+    strict_tools: bool,
+    web_search: bool,
+}
+
+fn resolve_compat(model: &ModelDef) -> Compat {
+    let mut compat = provider_compat(&model.provider);
+    if let Some(overrides) = &model.compat {
+        compat = compat.merged_with(overrides);
+    }
+    Compat {
+        max_tokens_field: compat.max_tokens_field.unwrap_or(MaxTokensField::MaxTokens),
+        stream_options: compat.supports_stream_options.unwrap_or(true),
+        developer_role: compat.supports_developer_role.unwrap_or(false),
+        tool_message_name: compat.supports_tool_message_name.unwrap_or(true),
+        reasoning_effort: compat.supports_reasoning_effort.unwrap_or(false),
+        strict_tools: compat.strict_tools.unwrap_or(false),
+        web_search: compat
+            .supports_web_search
+            .unwrap_or_else(|| is_search_model(&model.provider, &model.id)),
+    }
+}
+
+/// OpenAI's search models, the only Chat Completions models that take
+/// `web_search_options`.
+fn is_search_model(provider: &str, model_id: &str) -> bool {
+    matches!(provider, "openai" | "azure-openai")
+        && (model_id.contains("-search-preview") || model_id.contains("-search-api"))
 }
 
 // ---------------------------------------------------------------------------
 // Conversion helpers
 // ---------------------------------------------------------------------------
 
-fn convert_messages(context: &ChatContext) -> Vec<ChatMessage> {
+fn convert_messages(context: &ChatContext, model: &ModelDef, compat: &Compat) -> Vec<ChatMessage> {
     let mut msgs = Vec::new();
 
     if let Some(sys) = &context.system_prompt {
+        // Reasoning models on OpenAI expect the system prompt under the `developer` role.
+        let role = if compat.developer_role && model.reasoning {
+            "developer"
+        } else {
+            "system"
+        };
         msgs.push(ChatMessage {
-            role: "system".into(),
+            role: role.into(),
             content: Some(json!(sys)),
             tool_calls: None,
             tool_call_id: None,
@@ -236,7 +544,11 @@ fn convert_messages(context: &ChatContext) -> Vec<ChatMessage> {
                     content: Some(json!(text)),
                     tool_calls: None,
                     tool_call_id: Some(tr.tool_call_id.clone()),
-                    name: Some(tr.tool_name.clone()),
+                    name: if compat.tool_message_name {
+                        Some(tr.tool_name.clone())
+                    } else {
+                        None
+                    },
                 });
             }
         }
@@ -269,7 +581,23 @@ fn user_content_to_json(blocks: &[ContentBlock]) -> serde_json::Value {
     json!(parts)
 }
 
-fn convert_tools(tools: &[ToolDef]) -> Vec<ToolSchema> {
+/// Map a thinking level to the OpenAI `reasoning_effort` parameter. Only OpenAI's
+/// GPT-5 models take `minimal`; elsewhere it is sent as `low`, the least effort
+/// every backend accepts.
+fn reasoning_effort(level: &ThinkingLevel, model: &ModelDef) -> &'static str {
+    match level {
+        ThinkingLevel::Minimal if takes_minimal_effort(model) => "minimal",
+        ThinkingLevel::Minimal | ThinkingLevel::Low => "low",
+        ThinkingLevel::Medium => "medium",
+        ThinkingLevel::High => "high",
+    }
+}
+
+fn takes_minimal_effort(model: &ModelDef) -> bool {
+    matches!(model.provider.as_str(), "openai" | "azure-openai") && model.id.starts_with("gpt-5")
+}
+
+fn convert_tools(tools: &[ToolDef], strict: bool) -> Vec<ToolSchema> {
     tools
         .iter()
         .map(|t| ToolSchema {
@@ -277,12 +605,108 @@ fn convert_tools(tools: &[ToolDef]) -> Vec<ToolSchema> {
             function: FunctionSchema {
                 name: t.name.clone(),
                 description: t.description.clone(),
-                parameters: t.parameters.clone(),
+                parameters: if strict {
+                    strict_schema(&t.parameters)
+                } else {
+                    t.parameters.clone()
+                },
+                strict: if strict { Some(true) } else { None },
             },
         })
         .collect()
 }
 
+/// Rewrite a JSON schema to satisfy strict mode: every object closes
+/// `additionalProperties` and lists all of its properties as required. The
+/// properties that were optional accept null instead, so they may be left out.
+fn strict_schema(schema: &serde_json::Value) -> serde_json::Value {
+    match schema {
+        serde_json::Value::Object(map) => {
+            let mut out = serde_json::Map::new();
+            for (k, v) in map {
+                out.insert(k.clone(), strict_schema(v));
+            }
+            if out.get("type").and_then(|t| t.as_str()) == Some("object") {
+                let required = map.get("required").and_then(|r| r.as_array());
+                let is_required = |name: &String| required.is_some_and(|r| r.contains(&json!(name)));
+                if let Some(serde_json::Value::Object(props)) = out.get_mut("properties") {
+                    for (_, prop) in props.iter_mut().filter(|(name, _)| !is_required(name)) {
+                        *prop = nullable(prop.take());
+                    }
+                }
+                let keys: Vec<serde_json::Value> = out
+                    .get("properties")
+                    .and_then(|p| p.as_object())
+                    .map(|p| p.keys().map(|k| json!(k)).collect())
+                    .unwrap_or_default();
+                out.insert("required".into(), json!(keys));
+                out.insert("additionalProperties".into(), json!(false));
+            }
+            serde_json::Value::Object(out)
+        }
+        serde_json::Value::Array(items) => {
+            serde_json::Value::Array(items.iter().map(strict_schema).collect())
+        }
+        other => other.clone(),
+    }
+}
+
+/// Widen a schema to accept null as well.
+fn nullable(mut schema: serde_json::Value) -> serde_json::Value {
+    match schema.get_mut("type") {
+        Some(t @ serde_json::Value::String(_)) => *t = json!([t.take(), "null"]),
+        Some(serde_json::Value::Array(types)) if !types.contains(&json!("null")) => types.push(json!("null")),
+        Some(_) => {}
+        // `anyOf`, `$ref` and the like
+        None => return json!({"anyOf": [schema, {"type": "null"}]}),
+    }
+    if let Some(serde_json::Value::Array(values)) = schema.get_mut("enum")
+        && !values.contains(&serde_json::Value::Null)
+    {
+        values.push(serde_json::Value::Null);
+    }
+    schema
+}
+
+// ---------------------------------------------------------------------------
+// Content filter
+// ---------------------------------------------------------------------------
+
+/// Categories a content filter flagged, e.g. `hate (high)`, sorted.
+fn filtered_categories(results: &ContentFilterResults) -> Vec<String> {
+    let mut flagged: Vec<String> = results
+        .iter()
+        .filter(|(_, verdict)| verdict["filtered"].as_bool() == Some(true))
+        .map(|(category, verdict)| match verdict["severity"].as_str() {
+            Some(severity) => format!("{} ({})", category, severity),
+            None => category.clone(),
+        })
+        .collect();
+    flagged.sort();
+    flagged
+}
+
+/// Why the content filter blocked `what`, if it flagged anything.
+fn filter_message(what: &str, results: &ContentFilterResults) -> Option<String> {
+    let flagged = filtered_categories(results);
+    (!flagged.is_empty()).then(|| format!("{} blocked by the content filter: {}", what, flagged.join(", ")))
+}
+
+/// Reason for a request refused because the prompt was filtered.
+fn content_filter_error(status: u16, body: &str) -> Option<String> {
+    if status != 400 {
+        return None;
+    }
+    let error = serde_json::from_str::<ErrorResponse>(body).ok()?.error;
+    if error.code.as_deref() != Some("content_filter") {
+        return None;
+    }
+    let results = error.innererror.map(|e| e.content_filter_result).unwrap_or_default();
+    filter_message("Prompt", &results)
+        .or(error.message)
+        .or_else(|| Some("Prompt blocked by the content filter".into()))
+}
+
 // ---------------------------------------------------------------------------
 // SSE parsing
 // ---------------------------------------------------------------------------
@@ -312,37 +736,52 @@ impl Provider for OpenAiProvider {
         context: &ChatContext,
         options: &StreamOptions,
     ) -> BoxStream<'static, Result<StreamEvent, ProviderError>> {
-        let api_key = match &options.api_key {
-            Some(k) => k.clone(),
-            None => {
-                return Box::pin(stream::once(async {
-                    Err(ProviderError::AuthRequired(
-                        "API key required for OpenAI".into(),
-                    ))
-                }));
-            }
-        };
+        // A missing key means the endpoint needs none, or auth is carried in extra headers
+        let auth = options.api_key.as_deref().map(|k| self.auth_header(k, options.credential_kind));
 
         let base_url = model.base_url.trim_end_matches('/').to_string();
-        let url = format!("{}/chat/completions", base_url);
+        let url = self.chat_url(&base_url, &model.id);
 
-        let messages = convert_messages(context);
+        let compat = resolve_compat(model);
+        let messages = convert_messages(context, model, &compat);
         let tools = if context.tools.is_empty() {
             None
         } else {
-            Some(convert_tools(&context.tools))
+            Some(convert_tools(&context.tools, compat.strict_tools))
+        };
+
+        let (max_tokens, max_completion_tokens) = match compat.max_tokens_field {
+            MaxTokensField::MaxTokens => (options.max_tokens, None),
+            MaxTokensField::MaxCompletionTokens => (None, options.max_tokens),
         };
 
         let body = ChatRequest {
             model: model.id.clone(),
             messages,
             temperature: options.temperature,
-            max_tokens: options.max_tokens,
+            max_tokens,
+            max_completion_tokens,
             stream: true,
             tools,
-            stream_options: Some(StreamOptionsReq {
-                include_usage: true,
-            }),
+            stream_options: if compat.stream_options {
+                Some(StreamOptionsReq {
+                    include_usage: true,
+                })
+            } else {
+                None
+            },
+            reasoning_effort: if model.reasoning && compat.reasoning_effort {
+                options.reasoning.as_ref().map(|l| reasoning_effort(l, model).to_string())
+            } else {
+                None
+            },
+            // Code execution and URL context are only hosted by the Responses API
+            web_search_options: (compat.web_search
+                && context
+                    .hosted_tools
+                    .iter()
+                    .any(|t| matches!(t, HostedTool::WebSearch { .. })))
+            .then(|| json!({})),
         };
 
         let mut headers_map = HashMap::new();
@@ -358,10 +797,10 @@ impl Provider for OpenAiProvider {
         let provider_id = model.provider.clone();
 
         let s = async_stream::stream! {
-            let mut req = client
-                .post(&url)
-                .header("Authorization", format!("Bearer {}", api_key))
-                .header("Content-Type", "application/json");
+            let mut req = client.post(&url).header("Content-Type", "application/json");
+            if let Some((name, value)) = &auth {
+                req = req.header(name.as_str(), value.as_str());
+            }
 
             for (k, v) in &headers_map {
                 req = req.header(k.as_str(), v.as_str());
@@ -377,20 +816,35 @@ impl Provider for OpenAiProvider {
 
             let status = resp.status();
             if !status.is_success() {
+                let retry_after = retry_after_ms(resp.headers());
                 let body_text = resp.text().await.unwrap_or_default();
-                yield Err(ProviderError::Http {
-                    status: status.as_u16(),
-                    body: body_text,
-                });
+                if let Some(reason) = content_filter_error(status.as_u16(), &body_text) {
+                    yield Ok(StreamEvent::Start);
+                    yield Ok(StreamEvent::Done {
+                        message: AssistantMessage {
+                            content: Vec::new(),
+                            model: model_id,
+                            provider: provider_id,
+                            usage: Some(Usage::default()),
+                            stop_reason: StopReason::ContentFilter,
+                            error_message: Some(reason),
+                        },
+                    });
+                    return;
+                }
+                yield Err(ProviderError::from_status(status.as_u16(), retry_after, body_text));
                 return;
             }
 
             yield Ok(StreamEvent::Start);
 
             let mut text_buf = String::new();
+            let mut citations: Vec<CitationSource> = Vec::new();
+            let mut thinking_buf = String::new();
             let mut tool_calls: Vec<(String, String, String)> = Vec::new(); // (id, name, args)
             let mut usage = Usage::default();
             let mut stop_reason = StopReason::Stop;
+            let mut error_message: Option<String> = None;
             let mut line_buf = String::new();
 
             let mut byte_stream = resp.bytes_stream();
@@ -430,25 +884,60 @@ impl Provider for OpenAiProvider {
                         usage.input_tokens = u.prompt_tokens.unwrap_or(0);
                         usage.output_tokens = u.completion_tokens.unwrap_or(0);
                         usage.total_tokens = u.total_tokens.unwrap_or(0);
+                        usage.reasoning_tokens = u
+                            .completion_tokens_details
+                            .and_then(|d| d.reasoning_tokens)
+                            .unwrap_or(0);
+                    }
+
+                    for result in &chunk.prompt_filter_results {
+                        if let Some(message) = filter_message("Prompt", &result.content_filter_results) {
+                            error_message = Some(message);
+                            stop_reason = StopReason::ContentFilter;
+                        }
                     }
 
                     if let Some(choices) = chunk.choices {
                         for choice in choices {
+                            if let Some(message) = filter_message("Response", &choice.content_filter_results) {
+                                error_message = Some(message);
+                            }
                             if let Some(reason) = &choice.finish_reason {
                                 stop_reason = match reason.as_str() {
                                     "stop" => StopReason::Stop,
                                     "length" => StopReason::Length,
                                     "tool_calls" => StopReason::ToolUse,
+                                    "content_filter" => StopReason::ContentFilter,
                                     _ => StopReason::Stop,
                                 };
                             }
 
                             if let Some(delta) = &choice.delta {
+                                let reasoning = delta
+                                    .reasoning_content
+                                    .as_ref()
+                                    .or(delta.reasoning.as_ref())
+                                    .filter(|r| !r.is_empty());
+                                if let Some(r) = reasoning {
+                                    thinking_buf.push_str(r);
+                                    yield Ok(StreamEvent::ThinkingDelta(r.clone()));
+                                }
+
                                 if let Some(content) = &delta.content {
                                     text_buf.push_str(content);
                                     yield Ok(StreamEvent::TextDelta(content.clone()));
                                 }
 
+                                for cit in delta.annotations.iter().flatten().filter_map(|a| a.url_citation.as_ref()) {
+                                    citations.push(CitationSource {
+                                        uri: cit.url.clone(),
+                                        title: cit.title.clone(),
+                                        license: None,
+                                        start_index: cit.start_index,
+                                        end_index: cit.end_index,
+                                    });
+                                }
+
                                 if let Some(tc_deltas) = &delta.tool_calls {
                                     for tc_delta in tc_deltas {
                                         let idx = tc_delta.index.unwrap_or(tool_calls.len());
@@ -498,13 +987,17 @@ impl Provider for OpenAiProvider {
                         id: id.clone(),
                         name: name.clone(),
                         arguments,
+                        thought_signature: None,
                     },
                 });
             }
 
             let mut content = Vec::new();
+            if !thinking_buf.is_empty() {
+                content.push(ContentBlock::Thinking(ThinkingContent { thinking: thinking_buf, signature: None }));
+            }
             if !text_buf.is_empty() {
-                content.push(ContentBlock::Text(TextContent { text: text_buf }));
+                content.push(ContentBlock::Text(TextContent { text: text_buf, thought_signature: None }));
             }
             for (id, name, args_str) in tool_calls {
                 let arguments: serde_json::Value =
@@ -513,6 +1006,13 @@ impl Provider for OpenAiProvider {
                     id,
                     name,
                     arguments,
+                    thought_signature: None,
+                }));
+            }
+            if !citations.is_empty() {
+                content.push(ContentBlock::Citations(CitationContent {
+                    sources: citations,
+                    queries: Vec::new(),
                 }));
             }
 
@@ -522,6 +1022,7 @@ impl Provider for OpenAiProvider {
                 provider: provider_id,
                 usage: Some(usage),
                 stop_reason,
+                error_message,
             };
 
             yield Ok(StreamEvent::Done { message: msg });
@@ -530,44 +1031,351 @@ impl Provider for OpenAiProvider {
         Box::pin(s)
     }
 
-    async fn list_models(&self, api_key: &str) -> Result<Vec<ModelDef>, ProviderError> {
-        // OpenAI supports GET /v1/models
-        let url = "https://api.openai.com/v1/models";
-        let resp = self
-            .client
-            .get(url)
-            .header("Authorization", format!("Bearer {}", api_key))
-            .send()
-            .await?;
-
-        if !resp.status().is_success() {
-            let body = resp.text().await.unwrap_or_default();
-            return Err(ProviderError::Http {
-                status: 400,
-                body,
-            });
+    async fn list_models(
+        &self,
+        provider: &str,
+        base_url: &str,
+        api_key: &str,
+    ) -> Result<Vec<ModelDef>, ProviderError> {
+        let base_url = base_url.trim_end_matches('/');
+
+        // Azure serves deployments, which only the config knows about
+        if let Some(azure) = &self.azure {
+            let mut ids: Vec<String> = crate::models::static_models_for_provider(provider)
+                .into_iter()
+                .map(|m| m.id)
+                .collect();
+            for id in azure.deployments.keys() {
+                if !ids.contains(id) {
+                    ids.push(id.clone());
+                }
+            }
+            let discovered = ids
+                .into_iter()
+                .map(|id| DiscoveredModel {
+                    id,
+                    ..Default::default()
+                })
+                .collect();
+            return Ok(merge_discovered(
+                provider,
+                base_url,
+                Api::OpenaiCompletions,
+                discovered,
+            ));
+        }
+
+        // Every OpenAI-compatible backend exposes GET {base_url}/models
+        let url = format!("{}/models", base_url);
+
+        let mut req = self.client.get(&url);
+        // Local backends (Ollama, vLLM) usually run without a key
+        if !api_key.is_empty() {
+            req = req.header("Authorization", format!("Bearer {}", api_key));
         }
+        let resp = req.send().await?;
 
-        let models_resp: ModelsResponse = resp.json().await?;
+        let status = resp.status();
+        if !status.is_success() {
+            return Err(ProviderError::from_response(resp).await);
+        }
 
-        let models = models_resp
+        let models_resp: ModelsResponse = resp.json().await?;
+        let discovered = models_resp
             .data
             .into_iter()
-            .map(|entry| ModelDef {
-                id: entry.id.clone(),
-                name: entry.id,
-                api: Api::OpenaiCompletions,
-                provider: "openai".into(),
-                base_url: "https://api.openai.com/v1".into(),
-                reasoning: false,
-                input: vec![InputModality::Text],
-                cost: ModelCost::default(),
-                context_window: 128000,
-                max_tokens: 16384,
-                headers: None,
+            .map(ModelEntry::into_discovered)
+            .collect();
+
+        Ok(merge_discovered(
+            provider,
+            base_url,
+            Api::OpenaiCompletions,
+            discovered,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::test_server::{FakeServer, Response};
+    use futures::StreamExt;
+
+    fn azure_model(base_url: &str) -> ModelDef {
+        ModelDef {
+            id: "gpt-4o".into(),
+            name: "GPT-4o".into(),
+            api: Api::OpenaiCompletions,
+            provider: "azure-openai".into(),
+            base_url: base_url.into(),
+            reasoning: false,
+            input: vec![InputModality::Text],
+            cost: ModelCost::default(),
+            context_window: 128_000,
+            max_tokens: 4096,
+            headers: None,
+            compat: None,
+        }
+    }
+
+    async fn done_message(server: &FakeServer) -> AssistantMessage {
+        let context = ChatContext { system_prompt: None, messages: Vec::new(), tools: Vec::new(), hosted_tools: Vec::new() };
+        let options = StreamOptions { api_key: Some("key".into()), ..Default::default() };
+        let provider = OpenAiProvider::azure(AzureConfig::default());
+        let events: Vec<_> = provider.stream(&azure_model(&server.url), &context, &options).collect().await;
+        match events.into_iter().last() {
+            Some(Ok(StreamEvent::Done { message })) => message,
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn azure_auth_follows_the_credential_kind_unless_configured() {
+        let azure = OpenAiProvider::azure(AzureConfig::default());
+        assert_eq!(azure.auth_header("eyJkey", Some(CredentialKind::ApiKey)).0, "api-key");
+        assert_eq!(azure.auth_header("tok", Some(CredentialKind::OAuth)).0, "Authorization");
+
+        let bearer = OpenAiProvider::azure(AzureConfig { auth: Some(AzureAuth::Bearer), ..Default::default() });
+        assert_eq!(bearer.auth_header("tok", Some(CredentialKind::ApiKey)), ("Authorization".into(), "Bearer tok".into()));
+
+        assert_eq!(OpenAiProvider::new().auth_header("key", None).0, "Authorization");
+    }
+
+    #[tokio::test]
+    async fn filtered_prompts_end_with_a_content_filter_stop() {
+        let server = FakeServer::start(|_| {
+            Response::json(400, json!({"error": {
+                "code": "content_filter",
+                "message": "The response was filtered",
+                "innererror": {
+                    "code": "ResponsibleAIPolicyViolation",
+                    "content_filter_result": {
+                        "hate": {"filtered": false, "severity": "safe"},
+                        "violence": {"filtered": true, "severity": "high"},
+                        "jailbreak": {"filtered": true, "detected": true},
+                    },
+                },
+            }}))
+        })
+        .await;
+
+        let message = done_message(&server).await;
+        assert_eq!(message.stop_reason, StopReason::ContentFilter);
+        assert_eq!(
+            message.error_message.as_deref(),
+            Some("Prompt blocked by the content filter: jailbreak, violence (high)")
+        );
+    }
+
+    #[tokio::test]
+    async fn filtered_completions_report_the_flagged_categories() {
+        let server = FakeServer::start(|_| {
+            let chunks = [
+                json!({"choices": [], "prompt_filter_results": [{"prompt_index": 0, "content_filter_results": {
+                    "hate": {"filtered": false, "severity": "safe"},
+                }}]}),
+                json!({"choices": [{"index": 0, "delta": {"content": "Once"}, "content_filter_results": {}}]}),
+                json!({"choices": [{"index": 0, "delta": {}, "finish_reason": "content_filter", "content_filter_results": {
+                    "sexual": {"filtered": true, "severity": "medium"},
+                }}]}),
+            ];
+            Response {
+                status: 200,
+                headers: vec![("content-type".into(), "text/event-stream".into())],
+                body: chunks.iter().map(|c| format!("data: {}\n\n", c)).collect::<String>() + "data: [DONE]\n\n",
+            }
+        })
+        .await;
+
+        let message = done_message(&server).await;
+        assert_eq!(message.stop_reason, StopReason::ContentFilter);
+        assert_eq!(message.error_message.as_deref(), Some("Response blocked by the content filter: sexual (medium)"));
+        assert!(matches!(&message.content[..], [ContentBlock::Text(t)] if t.text == "Once"));
+        assert_eq!(server.requests()[0].headers["api-key"], "key");
+    }
+
+    #[tokio::test]
+    async fn too_many_requests_carry_the_retry_after_wait() {
+        let server = FakeServer::start(|_| {
+            Response::json(429, json!({"error": {"message": "Rate limit reached"}})).header("retry-after", "7")
+        })
+        .await;
+
+        let context = ChatContext { system_prompt: None, messages: Vec::new(), tools: Vec::new(), hosted_tools: Vec::new() };
+        let options = StreamOptions { api_key: Some("key".into()), ..Default::default() };
+        let events: Vec<_> = OpenAiProvider::new().stream(&azure_model(&server.url), &context, &options).collect().await;
+        assert!(
+            matches!(events.last(), Some(Err(ProviderError::RateLimited { retry_after_ms: Some(7000) }))),
+            "{:?}",
+            events.last()
+        );
+    }
+
+    #[tokio::test]
+    async fn reasoning_deltas_and_tokens_are_collected() {
+        let server = FakeServer::start(|_| {
+            let chunks = [
+                json!({"choices": [{"index": 0, "delta": {"role": "assistant", "reasoning_content": "Two "}}]}),
+                json!({"choices": [{"index": 0, "delta": {"reasoning_content": ""}}]}),
+                // OpenRouter's field name
+                json!({"choices": [{"index": 0, "delta": {"reasoning": "primes."}}]}),
+                json!({"choices": [{"index": 0, "delta": {"content": "2 and 3"}}]}),
+                json!({"choices": [{"index": 0, "delta": {}, "finish_reason": "stop"}]}),
+                json!({"choices": [], "usage": {"prompt_tokens": 11, "completion_tokens": 40, "total_tokens": 51,
+                    "completion_tokens_details": {"reasoning_tokens": 32}}}),
+            ];
+            Response {
+                status: 200,
+                headers: vec![("content-type".into(), "text/event-stream".into())],
+                body: chunks.iter().map(|c| format!("data: {}\n\n", c)).collect::<String>() + "data: [DONE]\n\n",
+            }
+        })
+        .await;
+        let mut model = azure_model(&server.url);
+        model.provider = "deepseek".into();
+        model.reasoning = true;
+        let context = ChatContext { system_prompt: None, messages: Vec::new(), tools: Vec::new(), hosted_tools: Vec::new() };
+        let options = StreamOptions { api_key: Some("key".into()), ..Default::default() };
+
+        let events: Vec<_> = OpenAiProvider::new().stream(&model, &context, &options).collect().await;
+        let thinking: Vec<_> = events
+            .iter()
+            .filter_map(|e| match e {
+                Ok(StreamEvent::ThinkingDelta(d)) => Some(d.as_str()),
+                _ => None,
             })
             .collect();
+        assert_eq!(thinking, ["Two ", "primes."]);
+        let message = match events.last() {
+            Some(Ok(StreamEvent::Done { message })) => message,
+            other => panic!("{:?}", other),
+        };
+        match &message.content[..] {
+            [ContentBlock::Thinking(t), ContentBlock::Text(text)] => {
+                assert_eq!(t.thinking, "Two primes.");
+                assert_eq!(text.text, "2 and 3");
+            }
+            other => panic!("{:?}", other),
+        }
+        let usage = message.usage.as_ref().unwrap();
+        assert_eq!((usage.input_tokens, usage.output_tokens, usage.reasoning_tokens), (11, 40, 32));
+    }
+
+    #[test]
+    fn model_listings_report_what_each_backend_knows() {
+        // OpenRouter
+        let entry: ModelEntry = serde_json::from_value(json!({
+            "id": "anthropic/claude-sonnet-4",
+            "name": "Anthropic: Claude Sonnet 4",
+            "context_length": 200000,
+            "pricing": {"prompt": "0.000003", "completion": "0.000015", "input_cache_read": "0.0000003"},
+            "top_provider": {"max_completion_tokens": 64000},
+            "supported_parameters": ["tools", "reasoning"],
+            "architecture": {"input_modalities": ["text", "image"]},
+        }))
+        .unwrap();
+        let d = entry.into_discovered();
+        assert_eq!(d.id, "anthropic/claude-sonnet-4");
+        assert_eq!(d.name.as_deref(), Some("Anthropic: Claude Sonnet 4"));
+        assert_eq!((d.context_window, d.max_tokens, d.reasoning), (Some(200_000), Some(64_000), Some(true)));
+        assert_eq!(d.input, Some(vec![InputModality::Text, InputModality::Image]));
+        let cost = d.cost.unwrap();
+        assert!((cost.input - 3.0).abs() < 1e-9 && (cost.output - 15.0).abs() < 1e-9);
+        assert!((cost.cache_read - 0.3).abs() < 1e-9 && cost.cache_write == 0.0);
+
+        // vLLM reports only the context length
+        let entry: ModelEntry = serde_json::from_value(json!({"id": "qwen3-8b", "max_model_len": 32768})).unwrap();
+        let d = entry.into_discovered();
+        assert_eq!(d.context_window, Some(32_768));
+        assert!(d.name.is_none() && d.cost.is_none() && d.reasoning.is_none() && d.input.is_none());
+    }
+
+    #[test]
+    fn minimal_effort_is_sent_where_accepted() {
+        let mut model = azure_model("http://localhost");
+        model.provider = "openai".into();
+        model.id = "gpt-5-mini".into();
+        assert_eq!(reasoning_effort(&ThinkingLevel::Minimal, &model), "minimal");
+        assert_eq!(reasoning_effort(&ThinkingLevel::Low, &model), "low");
+        model.id = "o4-mini".into();
+        assert_eq!(reasoning_effort(&ThinkingLevel::Minimal, &model), "low");
+        model.id = "gpt-5-mini".into();
+        model.provider = "openrouter".into();
+        assert_eq!(reasoning_effort(&ThinkingLevel::Minimal, &model), "low");
+        assert_eq!(reasoning_effort(&ThinkingLevel::High, &model), "high");
+    }
+
+    #[test]
+    fn strict_schemas_require_everything_and_null_the_optional_properties() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "path": {"type": "string"},
+                "limit": {"type": "integer"},
+                "mode": {"type": "string", "enum": ["read", "write"]},
+                "range": {
+                    "type": "object",
+                    "properties": {"start": {"type": "integer"}, "end": {"type": ["integer", "string"]}},
+                    "required": ["start"],
+                },
+                "target": {"anyOf": [{"type": "string"}, {"type": "integer"}]},
+            },
+            "required": ["path"],
+        });
+
+        assert_eq!(strict_schema(&schema), json!({
+            "type": "object",
+            "properties": {
+                "path": {"type": "string"},
+                "limit": {"type": ["integer", "null"]},
+                "mode": {"type": ["string", "null"], "enum": ["read", "write", null]},
+                "range": {
+                    "type": ["object", "null"],
+                    "properties": {"start": {"type": "integer"}, "end": {"type": ["integer", "string", "null"]}},
+                    "required": ["end", "start"],
+                    "additionalProperties": false,
+                },
+                "target": {"anyOf": [{"anyOf": [{"type": "string"}, {"type": "integer"}]}, {"type": "null"}]},
+            },
+            "required": ["limit", "mode", "path", "range", "target"],
+            "additionalProperties": false,
+        }));
+    }
 
-        Ok(models)
+    #[test]
+    fn compat_layers_model_overrides_on_provider_defaults() {
+        let openai = provider_compat("openai");
+        assert_eq!(openai.max_tokens_field, Some(MaxTokensField::MaxCompletionTokens));
+        assert_eq!(openai.supports_developer_role, Some(true));
+        assert_eq!(provider_compat("mistral").supports_stream_options, Some(false));
+        assert_eq!(provider_compat("unknown"), OpenAiCompat::default());
+
+        let mut model = azure_model("http://localhost");
+        model.provider = "openai".into();
+        let compat = resolve_compat(&model);
+        assert_eq!(compat.max_tokens_field, MaxTokensField::MaxCompletionTokens);
+        assert!(compat.developer_role && compat.reasoning_effort && compat.stream_options);
+        assert!(!compat.strict_tools && !compat.web_search);
+
+        model.compat = Some(OpenAiCompat {
+            max_tokens_field: Some(MaxTokensField::MaxTokens),
+            strict_tools: Some(true),
+            ..Default::default()
+        });
+        let compat = resolve_compat(&model);
+        assert_eq!(compat.max_tokens_field, MaxTokensField::MaxTokens);
+        assert!(compat.strict_tools && compat.developer_role);
+
+        // Unknown providers get the plain Chat Completions defaults
+        model.provider = "my-llm".into();
+        model.compat = None;
+        let compat = resolve_compat(&model);
+        assert_eq!(compat.max_tokens_field, MaxTokensField::MaxTokens);
+        assert!(compat.stream_options && compat.tool_message_name && !compat.developer_role && !compat.reasoning_effort);
+
+        model.provider = "openai".into();
+        model.id = "gpt-4o-search-preview".into();
+        assert!(resolve_compat(&model).web_search);
     }
 }
@@ -0,0 +1,785 @@
+use super::{Provider, ProviderError};
+use crate::types::*;
+use async_trait::async_trait;
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use futures::stream::{self, BoxStream, StreamExt};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+
+pub const CODEX_BASE_URL: &str = "https://chatgpt.com/backend-api";
+const ORIGINATOR: &str = "codex_cli_rs";
+/// JWT claim holding the ChatGPT account details.
+const AUTH_CLAIM: &str = "https://api.openai.com/auth";
+
+/// OpenAI Codex provider: the ChatGPT subscription backend.
+///
+/// Speaks the Responses API at `{base}/codex/responses`, stateless (`store: false`),
+/// with reasoning carried between turns as encrypted content. The credential is
+/// the ChatGPT access token, or JSON `{"token", "accountId"}`.
+pub struct OpenAiCodexProvider {
+    client: Client,
+}
+
+impl OpenAiCodexProvider {
+    pub fn new() -> Self {
+        Self {
+            client: Client::new(),
+        }
+    }
+}
+
+impl Default for OpenAiCodexProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The ChatGPT account id in an id or access token's claims.
+pub fn chatgpt_account_id(jwt: &str) -> Option<String> {
+    let payload = jwt.split('.').nth(1)?;
+    let claims: serde_json::Value = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload).ok()?).ok()?;
+    claims
+        .get(AUTH_CLAIM)?
+        .get("chatgpt_account_id")?
+        .as_str()
+        .map(String::from)
+}
+
+/// Parse the API key into (token, account id).
+fn parse_codex_api_key(api_key: &str) -> Result<(String, String), ProviderError> {
+    #[derive(Deserialize)]
+    struct CodexKey {
+        token: String,
+        #[serde(rename = "accountId", default)]
+        account_id: Option<String>,
+    }
+
+    let parsed = if api_key.trim_start().starts_with('{') {
+        serde_json::from_str(api_key).map_err(|_| {
+            ProviderError::AuthRequired(
+                "Invalid OpenAI Codex credentials. Expected JSON {token, accountId}.".into(),
+            )
+        })?
+    } else {
+        CodexKey {
+            token: api_key.trim().to_string(),
+            account_id: None,
+        }
+    };
+
+    if parsed.token.is_empty() {
+        return Err(ProviderError::AuthRequired(
+            "Missing token in OpenAI Codex credentials.".into(),
+        ));
+    }
+
+    // The access token carries the account id too
+    let account_id = parsed
+        .account_id
+        .filter(|a| !a.is_empty())
+        .or_else(|| chatgpt_account_id(&parsed.token))
+        .ok_or_else(|| {
+            ProviderError::AuthRequired(
+                "No ChatGPT account id in OpenAI Codex credentials; log in again.".into(),
+            )
+        })?;
+    Ok((parsed.token, account_id))
+}
+
+// ---------------------------------------------------------------------------
+// Request types
+// ---------------------------------------------------------------------------
+
+#[derive(Serialize)]
+struct ResponsesRequest {
+    model: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    instructions: Option<String>,
+    input: Vec<serde_json::Value>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<serde_json::Value>,
+    tool_choice: String,
+    parallel_tool_calls: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reasoning: Option<ReasoningConfig>,
+    store: bool,
+    stream: bool,
+    include: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    prompt_cache_key: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ReasoningConfig {
+    effort: String,
+    summary: String,
+}
+
+// ---------------------------------------------------------------------------
+// Response types
+// ---------------------------------------------------------------------------
+
+#[derive(Deserialize)]
+struct StreamEventData {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    delta: Option<String>,
+    #[serde(default)]
+    item: Option<OutputItem>,
+    #[serde(default)]
+    output_index: Option<usize>,
+    #[serde(default)]
+    response: Option<ResponseBody>,
+    #[serde(default)]
+    message: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct OutputItem {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    call_id: Option<String>,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    arguments: Option<String>,
+    #[serde(default)]
+    encrypted_content: Option<String>,
+    #[serde(default)]
+    content: Vec<OutputContent>,
+}
+
+#[derive(Deserialize)]
+struct OutputContent {
+    #[serde(default)]
+    annotations: Vec<Annotation>,
+}
+
+#[derive(Deserialize)]
+struct Annotation {
+    #[serde(rename = "type")]
+    kind: String,
+    url: Option<String>,
+    title: Option<String>,
+    start_index: Option<u64>,
+    end_index: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct ResponseBody {
+    #[serde(default)]
+    usage: Option<ResponseUsage>,
+    #[serde(default)]
+    incomplete_details: Option<IncompleteDetails>,
+    #[serde(default)]
+    error: Option<ResponseError>,
+}
+
+#[derive(Deserialize)]
+struct ResponseUsage {
+    input_tokens: Option<u64>,
+    input_tokens_details: Option<InputTokensDetails>,
+    output_tokens: Option<u64>,
+    output_tokens_details: Option<OutputTokensDetails>,
+    total_tokens: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct InputTokensDetails {
+    cached_tokens: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct OutputTokensDetails {
+    reasoning_tokens: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct IncompleteDetails {
+    reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ResponseError {
+    message: Option<String>,
+}
+
+// ---------------------------------------------------------------------------
+// Conversion helpers
+// ---------------------------------------------------------------------------
+
+fn input_content(blocks: &[ContentBlock]) -> Vec<serde_json::Value> {
+    blocks
+        .iter()
+        .filter_map(|b| match b {
+            ContentBlock::Text(t) => Some(json!({"type": "input_text", "text": t.text})),
+            ContentBlock::Image(img) => Some(json!({
+                "type": "input_image",
+                "image_url": format!("data:{};base64,{}", img.mime_type, img.data),
+            })),
+            _ => None,
+        })
+        .collect()
+}
+
+fn tool_output(blocks: &[ContentBlock]) -> String {
+    blocks
+        .iter()
+        .filter_map(|b| match b {
+            ContentBlock::Text(t) => Some(t.text.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn convert_messages(context: &ChatContext, model: &ModelDef) -> Vec<serde_json::Value> {
+    let mut input = Vec::new();
+
+    for msg in &context.messages {
+        match msg {
+            Message::User(u) => {
+                input.push(json!({"type": "message", "role": "user", "content": input_content(&u.content)}));
+            }
+            Message::Assistant(a) => {
+                // Encrypted reasoning only decrypts for the provider that produced it
+                let same_provider = a.provider == model.provider;
+                for block in &a.content {
+                    match block {
+                        ContentBlock::Thinking(t) if same_provider && t.signature.is_some() => {
+                            input.push(json!({
+                                "type": "reasoning",
+                                "summary": [{"type": "summary_text", "text": t.thinking}],
+                                "encrypted_content": t.signature,
+                            }));
+                        }
+                        ContentBlock::Text(t) => {
+                            input.push(json!({
+                                "type": "message",
+                                "role": "assistant",
+                                "content": [{"type": "output_text", "text": t.text}],
+                            }));
+                        }
+                        ContentBlock::ToolCall(tc) => {
+                            input.push(json!({
+                                "type": "function_call",
+                                "call_id": tc.id,
+                                "name": tc.name,
+                                "arguments": tc.arguments.to_string(),
+                            }));
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            Message::ToolResult(tr) => {
+                input.push(json!({
+                    "type": "function_call_output",
+                    "call_id": tr.tool_call_id,
+                    "output": tool_output(&tr.content),
+                }));
+            }
+        }
+    }
+
+    input
+}
+
+fn convert_tools(tools: &[ToolDef], hosted: &[HostedTool]) -> Vec<serde_json::Value> {
+    let mut out: Vec<serde_json::Value> = tools
+        .iter()
+        .map(|t| {
+            json!({
+                "type": "function",
+                "name": t.name,
+                "description": t.description,
+                "parameters": t.parameters,
+                "strict": false,
+            })
+        })
+        .collect();
+    // The backend hosts web search only
+    if hosted.iter().any(|t| matches!(t, HostedTool::WebSearch { .. })) {
+        out.push(json!({"type": "web_search"}));
+    }
+    out
+}
+
+fn reasoning_effort(level: &ThinkingLevel) -> &'static str {
+    match level {
+        ThinkingLevel::Minimal => "minimal",
+        ThinkingLevel::Low => "low",
+        ThinkingLevel::Medium => "medium",
+        ThinkingLevel::High => "high",
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Provider impl
+// ---------------------------------------------------------------------------
+
+#[async_trait]
+impl Provider for OpenAiCodexProvider {
+    fn stream(
+        &self,
+        model: &ModelDef,
+        context: &ChatContext,
+        options: &StreamOptions,
+    ) -> BoxStream<'static, Result<StreamEvent, ProviderError>> {
+        let api_key = options.api_key.clone().unwrap_or_default();
+        let (token, account_id) = match parse_codex_api_key(&api_key) {
+            Ok(parsed) => parsed,
+            Err(e) => return Box::pin(stream::once(async move { Err(e) })),
+        };
+
+        let base_url = model.base_url.trim_end_matches('/').to_string();
+        let url = format!("{}/codex/responses", base_url);
+
+        let reasoning = if model.reasoning {
+            Some(ReasoningConfig {
+                effort: reasoning_effort(options.reasoning.as_ref().unwrap_or(&ThinkingLevel::Medium)).into(),
+                summary: "auto".into(),
+            })
+        } else {
+            None
+        };
+
+        // The backend takes no sampling or token limits
+        let body = ResponsesRequest {
+            model: model.id.clone(),
+            instructions: context.system_prompt.clone(),
+            input: convert_messages(context, model),
+            tools: convert_tools(&context.tools, &context.hosted_tools),
+            tool_choice: "auto".into(),
+            parallel_tool_calls: true,
+            reasoning,
+            store: false,
+            stream: true,
+            include: vec!["reasoning.encrypted_content".into()],
+            prompt_cache_key: options.conversation_id.clone(),
+        };
+
+        let mut headers_map = HashMap::new();
+        headers_map.insert("Authorization".to_string(), format!("Bearer {}", token));
+        headers_map.insert("chatgpt-account-id".to_string(), account_id);
+        headers_map.insert("OpenAI-Beta".to_string(), "responses=experimental".to_string());
+        headers_map.insert("originator".to_string(), ORIGINATOR.to_string());
+        headers_map.insert("Accept".to_string(), "text/event-stream".to_string());
+        if let Some(conversation_id) = &options.conversation_id {
+            headers_map.insert("session_id".to_string(), conversation_id.clone());
+        }
+        if let Some(model_headers) = &model.headers {
+            headers_map.extend(model_headers.clone());
+        }
+        if let Some(extra) = &options.extra_headers {
+            headers_map.extend(extra.clone());
+        }
+
+        let client = self.client.clone();
+        let model_id = model.id.clone();
+        let provider_id = model.provider.clone();
+
+        let s = async_stream::stream! {
+            let mut req = client.post(&url).header("Content-Type", "application/json");
+            for (k, v) in &headers_map {
+                req = req.header(k.as_str(), v.as_str());
+            }
+
+            let resp = match req.json(&body).send().await {
+                Ok(r) => r,
+                Err(e) => {
+                    yield Err(ProviderError::Network(e));
+                    return;
+                }
+            };
+
+            let status = resp.status();
+            if !status.is_success() {
+                yield Err(ProviderError::from_response(resp).await);
+                return;
+            }
+
+            yield Ok(StreamEvent::Start);
+
+            let mut text_buf = String::new();
+            let mut thinking_buf = String::new();
+            let mut encrypted_reasoning: Option<String> = None;
+            let mut citations: Vec<CitationSource> = Vec::new();
+            // Tool calls in order, and their position by output index
+            let mut tool_calls: Vec<ToolCall> = Vec::new();
+            let mut call_args: Vec<String> = Vec::new();
+            let mut call_index: HashMap<usize, usize> = HashMap::new();
+            let mut usage = Usage::default();
+            let mut stop_reason = StopReason::Stop;
+            let mut line_buf = String::new();
+
+            let mut byte_stream = resp.bytes_stream();
+
+            while let Some(chunk_result) = byte_stream.next().await {
+                let chunk_bytes = match chunk_result {
+                    Ok(b) => b,
+                    Err(e) => {
+                        yield Err(ProviderError::Network(e));
+                        return;
+                    }
+                };
+
+                let chunk_str = String::from_utf8_lossy(&chunk_bytes);
+                line_buf.push_str(&chunk_str);
+
+                while let Some(newline_pos) = line_buf.find('\n') {
+                    let line: String = line_buf.drain(..=newline_pos).collect();
+                    let line = line.trim();
+
+                    let data = match line.strip_prefix("data: ") {
+                        Some(d) => d,
+                        None => continue,
+                    };
+                    let event: StreamEventData = match serde_json::from_str(data) {
+                        Ok(e) => e,
+                        Err(_) => continue,
+                    };
+
+                    match event.kind.as_str() {
+                        "response.output_text.delta" => {
+                            if let Some(delta) = event.delta {
+                                text_buf.push_str(&delta);
+                                yield Ok(StreamEvent::TextDelta(delta));
+                            }
+                        }
+                        "response.reasoning_summary_text.delta" => {
+                            if let Some(delta) = event.delta {
+                                thinking_buf.push_str(&delta);
+                                yield Ok(StreamEvent::ThinkingDelta(delta));
+                            }
+                        }
+                        // Separate the summary's parts
+                        "response.reasoning_summary_part.added" if !thinking_buf.is_empty() => {
+                            thinking_buf.push_str("\n\n");
+                            yield Ok(StreamEvent::ThinkingDelta("\n\n".into()));
+                        }
+                        "response.output_item.added" => {
+                            let item = match event.item {
+                                Some(i) if i.kind == "function_call" => i,
+                                _ => continue,
+                            };
+                            let index = tool_calls.len();
+                            if let Some(output_index) = event.output_index {
+                                call_index.insert(output_index, index);
+                            }
+                            let id = item.call_id.unwrap_or_default();
+                            let name = item.name.unwrap_or_default();
+                            tool_calls.push(ToolCall {
+                                id: id.clone(),
+                                name: name.clone(),
+                                arguments: json!({}),
+                                thought_signature: None,
+                            });
+                            call_args.push(String::new());
+                            yield Ok(StreamEvent::ToolCallStart { index, id, name });
+                        }
+                        "response.function_call_arguments.delta" => {
+                            let index = event.output_index.and_then(|i| call_index.get(&i).copied());
+                            if let (Some(index), Some(delta)) = (index, event.delta) {
+                                call_args[index].push_str(&delta);
+                                yield Ok(StreamEvent::ToolCallDelta { index, delta });
+                            }
+                        }
+                        "response.output_item.done" => {
+                            let item = match event.item {
+                                Some(i) => i,
+                                None => continue,
+                            };
+                            match item.kind.as_str() {
+                                "function_call" => {
+                                    let index = match event.output_index.and_then(|i| call_index.get(&i).copied()) {
+                                        Some(i) => i,
+                                        None => continue,
+                                    };
+                                    let args = item.arguments.unwrap_or_else(|| call_args[index].clone());
+                                    tool_calls[index].arguments = serde_json::from_str(&args).unwrap_or(json!({}));
+                                    yield Ok(StreamEvent::ToolCallEnd {
+                                        index,
+                                        tool_call: tool_calls[index].clone(),
+                                    });
+                                }
+                                "reasoning" if item.encrypted_content.is_some() => {
+                                    encrypted_reasoning = item.encrypted_content;
+                                }
+                                "message" => {
+                                    let urls = item
+                                        .content
+                                        .iter()
+                                        .flat_map(|c| &c.annotations)
+                                        .filter(|a| a.kind == "url_citation");
+                                    for a in urls {
+                                        citations.push(CitationSource {
+                                            uri: a.url.clone(),
+                                            title: a.title.clone(),
+                                            license: None,
+                                            start_index: a.start_index,
+                                            end_index: a.end_index,
+                                        });
+                                    }
+                                }
+                                _ => {}
+                            }
+                        }
+                        "response.completed" | "response.incomplete" => {
+                            let response = match event.response {
+                                Some(r) => r,
+                                None => continue,
+                            };
+                            if let Some(u) = response.usage {
+                                let input = u.input_tokens.unwrap_or(0);
+                                let cached = u.input_tokens_details.and_then(|d| d.cached_tokens).unwrap_or(0);
+                                usage.input_tokens = input.saturating_sub(cached);
+                                usage.cache_read_tokens = cached;
+                                usage.output_tokens = u.output_tokens.unwrap_or(0);
+                                usage.reasoning_tokens = u.output_tokens_details.and_then(|d| d.reasoning_tokens).unwrap_or(0);
+                                usage.total_tokens = u.total_tokens.unwrap_or(0);
+                            }
+                            if let Some(reason) = response.incomplete_details.and_then(|d| d.reason) {
+                                stop_reason = match reason.as_str() {
+                                    "content_filter" => StopReason::ContentFilter,
+                                    _ => StopReason::Length,
+                                };
+                            }
+                        }
+                        "response.failed" | "error" => {
+                            let message = event
+                                .response
+                                .and_then(|r| r.error)
+                                .and_then(|e| e.message)
+                                .or(event.message)
+                                .unwrap_or_else(|| "OpenAI Codex response failed".into());
+                            yield Err(ProviderError::Other(message));
+                            return;
+                        }
+                        _ => {}
+                    }
+                }
+            }
+
+            if !tool_calls.is_empty() {
+                stop_reason = StopReason::ToolUse;
+            }
+
+            let mut content = Vec::new();
+            if !thinking_buf.is_empty() || encrypted_reasoning.is_some() {
+                content.push(ContentBlock::Thinking(ThinkingContent { thinking: thinking_buf, signature: encrypted_reasoning }));
+            }
+            if !text_buf.is_empty() {
+                content.push(ContentBlock::Text(TextContent { text: text_buf, thought_signature: None }));
+            }
+            for tc in tool_calls {
+                content.push(ContentBlock::ToolCall(tc));
+            }
+            if !citations.is_empty() {
+                content.push(ContentBlock::Citations(CitationContent {
+                    sources: citations,
+                    queries: Vec::new(),
+                }));
+            }
+
+            let msg = AssistantMessage {
+                content,
+                model: model_id,
+                provider: provider_id,
+                usage: Some(usage),
+                stop_reason,
+                error_message: None,
+            };
+
+            yield Ok(StreamEvent::Done { message: msg });
+        };
+
+        Box::pin(s)
+    }
+
+    async fn list_models(
+        &self,
+        provider: &str,
+        _base_url: &str,
+        _api_key: &str,
+    ) -> Result<Vec<ModelDef>, ProviderError> {
+        Ok(crate::models::static_models_for_provider(provider))
+    }
+}
+
+/// Models served to ChatGPT subscriptions through Codex.
+pub fn static_codex_models() -> Vec<ModelDef> {
+    let p = "openai-codex";
+    vec![
+        codex_model(p, "gpt-5.1-codex", "GPT-5.1 Codex"),
+        codex_model(p, "gpt-5.1-codex-mini", "GPT-5.1 Codex Mini"),
+        codex_model(p, "gpt-5.1", "GPT-5.1"),
+        codex_model(p, "gpt-5-codex", "GPT-5 Codex"),
+        codex_model(p, "gpt-5", "GPT-5"),
+    ]
+}
+
+fn codex_model(provider: &str, id: &str, name: &str) -> ModelDef {
+    ModelDef {
+        id: id.into(),
+        name: name.into(),
+        api: Api::OpenaiResponses,
+        provider: provider.into(),
+        base_url: CODEX_BASE_URL.into(),
+        reasoning: true,
+        input: vec![InputModality::Text, InputModality::Image],
+        cost: ModelCost::default(),
+        context_window: 272000,
+        max_tokens: 128000,
+        headers: None,
+        compat: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::test_server::{FakeServer, Response};
+
+    /// An unsigned JWT with these claims.
+    fn jwt(claims: serde_json::Value) -> String {
+        format!("{}.{}.sig", URL_SAFE_NO_PAD.encode(br#"{"alg":"none"}"#), URL_SAFE_NO_PAD.encode(claims.to_string()))
+    }
+
+    fn sse(events: &[serde_json::Value]) -> Response {
+        Response {
+            status: 200,
+            headers: vec![("content-type".into(), "text/event-stream".into())],
+            body: events.iter().map(|e| format!("event: {}\ndata: {}\n\n", e["type"].as_str().unwrap(), e)).collect(),
+        }
+    }
+
+    fn context() -> ChatContext {
+        ChatContext {
+            system_prompt: Some("Be brief".into()),
+            messages: vec![Message::User(UserMessage {
+                content: vec![ContentBlock::Text(TextContent { text: "Weather in Paris?".into(), thought_signature: None })],
+            })],
+            tools: vec![ToolDef {
+                name: "get_weather".into(),
+                description: "Current weather".into(),
+                parameters: json!({"type": "object", "properties": {"city": {"type": "string"}}}),
+            }],
+            hosted_tools: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn account_ids_come_from_the_auth_claim() {
+        let token = jwt(json!({"sub": "u1", AUTH_CLAIM: {"chatgpt_account_id": "acct-1", "chatgpt_plan_type": "plus"}}));
+        assert_eq!(chatgpt_account_id(&token).as_deref(), Some("acct-1"));
+        assert_eq!(chatgpt_account_id(&jwt(json!({"sub": "u1"}))), None);
+        assert_eq!(chatgpt_account_id("not-a-jwt"), None);
+        assert_eq!(chatgpt_account_id("a.!!!.c"), None);
+
+        // A stored account id wins over the token's
+        assert_eq!(parse_codex_api_key(&token).unwrap().1, "acct-1");
+        let key = json!({"token": token, "accountId": "acct-2"}).to_string();
+        assert_eq!(parse_codex_api_key(&key).unwrap(), (token.clone(), "acct-2".into()));
+        assert!(matches!(parse_codex_api_key("opaque-token"), Err(ProviderError::AuthRequired(_))));
+    }
+
+    #[tokio::test]
+    async fn streams_a_stateless_responses_request() {
+        let server = FakeServer::start(|_| {
+            sse(&[
+                json!({"type": "response.created", "response": {}}),
+                json!({"type": "response.reasoning_summary_part.added", "output_index": 0}),
+                json!({"type": "response.reasoning_summary_text.delta", "output_index": 0, "delta": "Look it up."}),
+                json!({"type": "response.reasoning_summary_part.added", "output_index": 0}),
+                json!({"type": "response.reasoning_summary_text.delta", "output_index": 0, "delta": "Then answer."}),
+                json!({"type": "response.output_item.done", "output_index": 0,
+                    "item": {"type": "reasoning", "encrypted_content": "enc-1"}}),
+                json!({"type": "response.output_text.delta", "output_index": 1, "delta": "Checking."}),
+                json!({"type": "response.output_item.done", "output_index": 1, "item": {"type": "message", "content": [
+                    {"type": "output_text", "annotations": [{"type": "url_citation", "url": "https://weather.example", "title": "Weather", "start_index": 0, "end_index": 9}]},
+                ]}}),
+                json!({"type": "response.output_item.added", "output_index": 2,
+                    "item": {"type": "function_call", "call_id": "call_1", "name": "get_weather"}}),
+                json!({"type": "response.function_call_arguments.delta", "output_index": 2, "delta": "{\"city\":"}),
+                json!({"type": "response.function_call_arguments.delta", "output_index": 2, "delta": "\"Paris\"}"}),
+                json!({"type": "response.output_item.done", "output_index": 2,
+                    "item": {"type": "function_call", "call_id": "call_1", "name": "get_weather", "arguments": "{\"city\":\"Paris\"}"}}),
+                json!({"type": "response.completed", "response": {"usage": {
+                    "input_tokens": 100, "input_tokens_details": {"cached_tokens": 40},
+                    "output_tokens": 30, "output_tokens_details": {"reasoning_tokens": 20}, "total_tokens": 130,
+                }}}),
+            ])
+        })
+        .await;
+
+        let token = jwt(json!({AUTH_CLAIM: {"chatgpt_account_id": "acct-1"}}));
+        let options = StreamOptions {
+            api_key: Some(token.clone()),
+            reasoning: Some(ThinkingLevel::High),
+            conversation_id: Some("conv-1".into()),
+            ..Default::default()
+        };
+        let mut model = codex_model("openai-codex", "gpt-5.1-codex", "GPT-5.1 Codex");
+        model.base_url = server.url.clone();
+        let events: Vec<_> = OpenAiCodexProvider::new().stream(&model, &context(), &options).collect().await;
+
+        let request = &server.requests()[0];
+        assert_eq!(request.target, "/codex/responses");
+        assert_eq!(request.headers["authorization"], format!("Bearer {}", token));
+        assert_eq!(request.headers["chatgpt-account-id"], "acct-1");
+        assert_eq!(request.headers["originator"], ORIGINATOR);
+        assert_eq!(request.headers["session_id"], "conv-1");
+        let body = request.json();
+        assert_eq!(body["store"], false);
+        assert_eq!(body["stream"], true);
+        assert_eq!(body["instructions"], "Be brief");
+        assert_eq!(body["input"][0]["role"], "user");
+        assert_eq!(body["reasoning"], json!({"effort": "high", "summary": "auto"}));
+        assert_eq!(body["include"], json!(["reasoning.encrypted_content"]));
+        assert_eq!(body["prompt_cache_key"], "conv-1");
+        assert!(body.get("max_output_tokens").is_none() && body.get("temperature").is_none());
+
+        let message = match events.last() {
+            Some(Ok(StreamEvent::Done { message })) => message,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(message.stop_reason, StopReason::ToolUse);
+        match &message.content[..] {
+            [ContentBlock::Thinking(thinking), ContentBlock::Text(text), ContentBlock::ToolCall(call), ContentBlock::Citations(cited)] => {
+                assert_eq!(thinking.thinking, "Look it up.\n\nThen answer.");
+                assert_eq!(thinking.signature.as_deref(), Some("enc-1"));
+                assert_eq!(text.text, "Checking.");
+                assert_eq!((call.id.as_str(), call.name.as_str()), ("call_1", "get_weather"));
+                assert_eq!(call.arguments, json!({"city": "Paris"}));
+                assert_eq!(cited.sources[0].uri.as_deref(), Some("https://weather.example"));
+            }
+            other => panic!("{:?}", other),
+        }
+        let usage = message.usage.as_ref().unwrap();
+        assert_eq!((usage.input_tokens, usage.cache_read_tokens), (60, 40));
+        assert_eq!((usage.output_tokens, usage.reasoning_tokens, usage.total_tokens), (30, 20, 130));
+        assert!(events.iter().any(|e| matches!(e, Ok(StreamEvent::ToolCallDelta { index: 0, .. }))));
+    }
+
+    #[tokio::test]
+    async fn failed_responses_end_the_stream_with_their_message() {
+        let server = FakeServer::start(|_| {
+            sse(&[json!({"type": "response.failed", "response": {"error": {"message": "Usage limit reached"}}})])
+        })
+        .await;
+        let mut model = codex_model("openai-codex", "gpt-5.1-codex", "GPT-5.1 Codex");
+        model.base_url = server.url.clone();
+        let options = StreamOptions {
+            api_key: Some(json!({"token": "tok", "accountId": "acct-1"}).to_string()),
+            ..Default::default()
+        };
+
+        let events: Vec<_> = OpenAiCodexProvider::new().stream(&model, &context(), &options).collect().await;
+        assert!(matches!(events.last(), Some(Err(ProviderError::Other(m))) if m == "Usage limit reached"), "{:?}", events.last());
+    }
+}
@@ -0,0 +1,184 @@
+use super::openai::OpenAiProvider;
+use super::{Provider, ProviderError};
+use crate::types::*;
+use async_trait::async_trait;
+use futures::stream::{self, BoxStream};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub const DASHSCOPE_BASE_URL: &str = "https://dashscope.aliyuncs.com/compatible-mode/v1";
+
+/// Qwen provider, for Qwen OAuth tokens and DashScope API keys.
+///
+/// Both speak the OpenAI chat-completions format. API keys go to DashScope (the
+/// model's base URL); OAuth tokens go to the `resource_url` their login returned,
+/// and arrive as JSON `{"token", "resourceUrl"}`.
+pub struct QwenProvider {
+    openai: Arc<OpenAiProvider>,
+}
+
+impl QwenProvider {
+    pub fn new() -> Self {
+        Self {
+            openai: Arc::new(OpenAiProvider::new()),
+        }
+    }
+}
+
+impl Default for QwenProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// API base URL for an OAuth `resource_url`, which is usually a bare host.
+pub fn resource_base_url(resource_url: &str) -> String {
+    let url = if resource_url.starts_with("http://") || resource_url.starts_with("https://") {
+        resource_url.to_string()
+    } else {
+        format!("https://{}", resource_url)
+    };
+    let url = url.trim_end_matches('/');
+    if url.ends_with("/v1") {
+        url.to_string()
+    } else {
+        format!("{}/v1", url)
+    }
+}
+
+/// Parse the API key into (token, base URL). Only OAuth keys carry a base URL.
+fn parse_qwen_api_key(api_key: &str) -> Result<(String, Option<String>), ProviderError> {
+    #[derive(Deserialize)]
+    struct QwenKey {
+        token: String,
+        #[serde(rename = "resourceUrl", default)]
+        resource_url: Option<String>,
+    }
+
+    if !api_key.trim_start().starts_with('{') {
+        return Ok((api_key.trim().to_string(), None));
+    }
+
+    let parsed: QwenKey = serde_json::from_str(api_key).map_err(|_| {
+        ProviderError::AuthRequired(
+            "Invalid Qwen OAuth credentials. Expected JSON {token, resourceUrl}.".into(),
+        )
+    })?;
+    if parsed.token.is_empty() {
+        return Err(ProviderError::AuthRequired(
+            "Missing token in Qwen OAuth credentials.".into(),
+        ));
+    }
+
+    let base_url = parsed
+        .resource_url
+        .filter(|r| !r.is_empty())
+        .map(|r| resource_base_url(&r));
+    Ok((parsed.token, base_url))
+}
+
+#[async_trait]
+impl Provider for QwenProvider {
+    fn stream(
+        &self,
+        model: &ModelDef,
+        context: &ChatContext,
+        options: &StreamOptions,
+    ) -> BoxStream<'static, Result<StreamEvent, ProviderError>> {
+        let api_key = options.api_key.clone().unwrap_or_default();
+        let (token, base_url) = match parse_qwen_api_key(&api_key) {
+            Ok(parsed) => parsed,
+            Err(e) => return Box::pin(stream::once(async move { Err(e) })),
+        };
+
+        let mut model = model.clone();
+        let mut options = options.clone();
+        options.api_key = Some(token);
+        if let Some(base_url) = base_url {
+            model.base_url = base_url;
+            options
+                .extra_headers
+                .get_or_insert_with(HashMap::new)
+                .insert("X-DashScope-AuthType".into(), "qwen-oauth".into());
+        }
+
+        self.openai.stream(&model, context, &options)
+    }
+
+    async fn list_models(
+        &self,
+        provider: &str,
+        _base_url: &str,
+        _api_key: &str,
+    ) -> Result<Vec<ModelDef>, ProviderError> {
+        Ok(crate::models::static_models_for_provider(provider))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::test_server::{FakeServer, Response};
+    use futures::StreamExt;
+    use serde_json::json;
+
+    fn model(base_url: &str) -> ModelDef {
+        ModelDef {
+            id: "qwen3-coder-plus".into(),
+            name: "Qwen3 Coder Plus".into(),
+            api: Api::OpenaiCompletions,
+            provider: "qwen".into(),
+            base_url: base_url.into(),
+            reasoning: false,
+            input: vec![InputModality::Text],
+            cost: ModelCost::default(),
+            context_window: 1_000_000,
+            max_tokens: 65_536,
+            headers: None,
+            compat: None,
+        }
+    }
+
+    async fn send(base_url: &str, api_key: &str) {
+        let context = ChatContext { system_prompt: None, messages: Vec::new(), tools: Vec::new(), hosted_tools: Vec::new() };
+        let options = StreamOptions { api_key: Some(api_key.into()), ..Default::default() };
+        let _: Vec<_> = QwenProvider::new().stream(&model(base_url), &context, &options).collect().await;
+    }
+
+    #[test]
+    fn resource_urls_become_v1_base_urls() {
+        assert_eq!(resource_base_url("portal.qwen.ai"), "https://portal.qwen.ai/v1");
+        assert_eq!(resource_base_url("https://portal.qwen.ai/"), "https://portal.qwen.ai/v1");
+        assert_eq!(resource_base_url("http://127.0.0.1:8080"), "http://127.0.0.1:8080/v1");
+        assert_eq!(resource_base_url("https://portal.qwen.ai/v1/"), "https://portal.qwen.ai/v1");
+    }
+
+    #[test]
+    fn only_oauth_keys_carry_a_base_url() {
+        assert_eq!(parse_qwen_api_key(" sk-dashscope ").unwrap(), ("sk-dashscope".into(), None));
+        let oauth = json!({"token": "tok", "resourceUrl": "portal.qwen.ai"}).to_string();
+        assert_eq!(parse_qwen_api_key(&oauth).unwrap(), ("tok".into(), Some("https://portal.qwen.ai/v1".into())));
+        assert_eq!(parse_qwen_api_key(r#"{"token": "tok", "resourceUrl": ""}"#).unwrap().1, None);
+        assert!(matches!(parse_qwen_api_key(r#"{"token": ""}"#), Err(ProviderError::AuthRequired(_))));
+    }
+
+    #[tokio::test]
+    async fn oauth_tokens_go_to_their_resource_url_and_keys_to_dashscope() {
+        let server = FakeServer::start(|_| Response::json(400, json!({"error": {"message": "stop"}}))).await;
+
+        // The model's base URL stands in for DashScope
+        send(&server.url, "sk-dashscope").await;
+        let oauth = json!({"token": "tok", "resourceUrl": server.url}).to_string();
+        send("https://dashscope.invalid/compatible-mode/v1", &oauth).await;
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].target, "/chat/completions");
+        assert_eq!(requests[0].headers["authorization"], "Bearer sk-dashscope");
+        assert!(!requests[0].headers.contains_key("x-dashscope-authtype"));
+        assert_eq!(requests[1].target, "/v1/chat/completions");
+        assert_eq!(requests[1].headers["authorization"], "Bearer tok");
+        assert_eq!(requests[1].headers["x-dashscope-authtype"], "qwen-oauth");
+    }
+}
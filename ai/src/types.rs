@@ -14,6 +14,8 @@ pub enum Api {
     AnthropicMessages,
     GoogleGenerativeAi,
     GoogleGeminiCli,
+    GoogleVertex,
+    OllamaChat,
     #[serde(untagged)]
     Custom(String),
 }
@@ -60,6 +62,61 @@ pub enum InputModality {
     Image,
 }
 
+/// Which request field carries the output token limit on OpenAI-compatible endpoints.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MaxTokensField {
+    MaxTokens,
+    MaxCompletionTokens,
+}
+
+/// Behaviour toggles for OpenAI-compatible endpoints that disagree with OpenAI.
+///
+/// Every field is optional: unset fields fall back to the defaults of the
+/// model's provider, so a model only needs to list what it does differently.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct OpenAiCompat {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens_field: Option<MaxTokensField>,
+    /// Whether `stream_options.include_usage` is accepted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub supports_stream_options: Option<bool>,
+    /// Whether the system prompt may be sent with the `developer` role.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub supports_developer_role: Option<bool>,
+    /// Whether `name` is accepted on `tool` messages.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub supports_tool_message_name: Option<bool>,
+    /// Whether `reasoning_effort` is accepted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub supports_reasoning_effort: Option<bool>,
+    /// Whether tool schemas must be sent with `strict: true`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strict_tools: Option<bool>,
+    /// Whether `web_search_options` is accepted. Defaults to the OpenAI search models.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub supports_web_search: Option<bool>,
+}
+
+impl OpenAiCompat {
+    /// Overlay `other` on top of `self`; fields set in `other` win.
+    pub fn merged_with(&self, other: &OpenAiCompat) -> OpenAiCompat {
+        OpenAiCompat {
+            max_tokens_field: other.max_tokens_field.or(self.max_tokens_field),
+            supports_stream_options: other.supports_stream_options.or(self.supports_stream_options),
+            supports_developer_role: other.supports_developer_role.or(self.supports_developer_role),
+            supports_tool_message_name: other
+                .supports_tool_message_name
+                .or(self.supports_tool_message_name),
+            supports_reasoning_effort: other
+                .supports_reasoning_effort
+                .or(self.supports_reasoning_effort),
+            strict_tools: other.strict_tools.or(self.strict_tools),
+            supports_web_search: other.supports_web_search.or(self.supports_web_search),
+        }
+    }
+}
+
 /// A model definition known to the system.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ModelDef {
@@ -86,6 +143,9 @@ pub struct ModelDef {
     /// Additional headers to send with every request.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub headers: Option<HashMap<String, String>>,
+    /// Overrides for OpenAI-compatible endpoint quirks (see [`OpenAiCompat`]).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compat: Option<OpenAiCompat>,
 }
 
 // ---------------------------------------------------------------------------
@@ -95,6 +155,10 @@ pub struct ModelDef {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TextContent {
     pub text: String,
+    /// Opaque reasoning signature that arrived with this text (Gemini
+    /// `thoughtSignature`). Must be sent back on the same part on later turns.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thought_signature: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -117,6 +181,68 @@ pub struct ToolCall {
     pub id: String,
     pub name: String,
     pub arguments: serde_json::Value,
+    /// Opaque reasoning signature attached to the call (Gemini `thoughtSignature`).
+    /// Must be sent back with the call on later turns.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thought_signature: Option<String>,
+}
+
+/// A source backing part of the response.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CitationSource {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uri: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+    /// Range of the supported response text, when the provider reports it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_index: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_index: Option<u64>,
+}
+
+/// Sources the response was grounded in or cites.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CitationContent {
+    pub sources: Vec<CitationSource>,
+    /// Web search queries the model ran, when grounded with search.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub queries: Vec<String>,
+}
+
+/// Code a hosted code-execution tool ran on the provider's side.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecutableCode {
+    /// Id of the hosted tool call, when the provider assigns one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    pub code: String,
+}
+
+/// Output of a hosted code-execution run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CodeExecutionResult {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_use_id: Option<String>,
+    pub success: bool,
+    pub output: String,
+}
+
+/// Results of a hosted web search or URL fetch.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebSearchResult {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_use_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub query: Option<String>,
+    pub results: Vec<CitationSource>,
+    /// Error code reported instead of results, e.g. `max_uses_exceeded`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -126,6 +252,10 @@ pub enum ContentBlock {
     Thinking(ThinkingContent),
     Image(ImageContent),
     ToolCall(ToolCall),
+    Citations(CitationContent),
+    ExecutableCode(ExecutableCode),
+    CodeExecutionResult(CodeExecutionResult),
+    WebSearchResult(WebSearchResult),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -140,6 +270,9 @@ pub struct AssistantMessage {
     pub provider: ProviderId,
     pub usage: Option<Usage>,
     pub stop_reason: StopReason,
+    /// Why the response was blocked or failed, when the provider says.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_message: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -169,6 +302,9 @@ pub struct Usage {
     pub cache_read_tokens: u64,
     pub cache_write_tokens: u64,
     pub total_tokens: u64,
+    /// Output tokens spent on reasoning (already included in `output_tokens`).
+    #[serde(default)]
+    pub reasoning_tokens: u64,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -177,6 +313,8 @@ pub enum StopReason {
     Stop,
     Length,
     ToolUse,
+    /// Output was withheld by the provider's content filter.
+    ContentFilter,
     Error,
     Aborted,
 }
@@ -199,6 +337,20 @@ pub struct ToolDef {
     pub parameters: serde_json::Value,
 }
 
+/// A tool the provider runs itself. Providers translate these to their own
+/// hosted tool declarations and skip the ones they do not offer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum HostedTool {
+    WebSearch {
+        /// Cap on searches per request, where the provider supports one.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_uses: Option<u32>,
+    },
+    CodeExecution,
+    UrlContext,
+}
+
 // ---------------------------------------------------------------------------
 // Chat context
 // ---------------------------------------------------------------------------
@@ -210,6 +362,8 @@ pub struct ChatContext {
     pub messages: Vec<Message>,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub tools: Vec<ToolDef>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hosted_tools: Vec<HostedTool>,
 }
 
 // ---------------------------------------------------------------------------
@@ -225,13 +379,41 @@ pub enum ThinkingLevel {
     High,
 }
 
+/// Gemini safety threshold for one harm category,
+/// e.g. `HARM_CATEGORY_DANGEROUS_CONTENT` / `BLOCK_ONLY_HIGH`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SafetySetting {
+    pub category: String,
+    pub threshold: String,
+}
+
+/// What kind of credential `StreamOptions::api_key` holds, for providers that
+/// authenticate API keys and subscription tokens differently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CredentialKind {
+    ApiKey,
+    /// Access token from an OAuth login; refreshable.
+    OAuth,
+    /// Long-lived OAuth token pasted by the user (e.g. `claude setup-token`).
+    SetupToken,
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct StreamOptions {
     pub temperature: Option<f64>,
     pub max_tokens: Option<u64>,
     pub reasoning: Option<ThinkingLevel>,
     pub api_key: Option<String>,
+    /// Kind of `api_key`; `None` is treated as a plain API key.
+    pub credential_kind: Option<CredentialKind>,
     pub extra_headers: Option<HashMap<String, String>>,
+    /// Values for `{placeholder}`s in the model's base URL (account id, region, ...).
+    pub endpoint_params: Option<HashMap<String, String>>,
+    /// Safety thresholds for Gemini models; other providers ignore them.
+    pub safety_settings: Option<Vec<SafetySetting>>,
+    /// Caller's id for the conversation, stable across its turns; providers with
+    /// server-side sessions use it to reuse cached context.
+    pub conversation_id: Option<String>,
 }
 
 // ---------------------------------------------------------------------------